@@ -0,0 +1,133 @@
+//! `crangon admin` subcommands
+//!
+//! Every command skips password checks, so only instance operators (who can already reach
+//! the database) should be able to run them.
+use pastemd::database::Database;
+
+const USAGE: &str = "Usage: crangon admin <command>
+
+Commands:
+  list [query] [--page=N]      list pastes whose url contains query or which are owned by query
+  show <url>                   show a paste's settings and metadata
+  delete <url>                 delete a paste (skipping the trash)
+  restore <url>                restore a paste from the trash
+  rename <url> <new url>       move a paste to a new url
+  reset-password <url>         replace a paste's edit password with a random one
+  transfer <url> [owner]       give a paste to another user (or remove its owner)
+  purge-cache <url>            remove everything cached for a paste";
+
+/// Run an admin command (`args` is everything after `admin`)
+pub async fn run(database: &Database, args: &[String]) {
+    let arg = |i: usize| args.get(i).map(|a| a.as_str());
+
+    match (arg(0), arg(1), arg(2)) {
+        (Some("list"), _, _) => {
+            let mut query = String::new();
+            let mut page: i64 = 0;
+
+            for a in &args[1..] {
+                match a.strip_prefix("--page=") {
+                    Some(p) => page = p.parse::<i64>().unwrap_or(0),
+                    None => query = a.to_string(),
+                }
+            }
+
+            match database.find_pastes(query, 50, page * 50).await {
+                Ok(pastes) => {
+                    for paste in pastes {
+                        println!(
+                            "{}\towner: {}\tpublished: {}\t{}",
+                            paste.url,
+                            if paste.metadata.owner.is_empty() {
+                                "-"
+                            } else {
+                                paste.metadata.owner.as_str()
+                            },
+                            paste.date_published,
+                            if paste.is_trashed() {
+                                "trashed"
+                            } else if paste.metadata.view_password.is_empty() {
+                                "public"
+                            } else {
+                                "private"
+                            }
+                        );
+                    }
+                }
+                Err(e) => fail(e),
+            }
+        }
+        (Some("show"), Some(url), None) => {
+            match database.get_paste_including_trash(url.to_string()).await {
+                Ok(paste) => {
+                    let views = database.get_views_by_url(paste.url.clone()).await;
+
+                    println!("url: {}", paste.url);
+                    println!("id: {}", paste.id);
+                    println!("published: {}", paste.date_published);
+                    println!("edited: {}", paste.date_edited);
+                    println!("expires: {}", paste.expires_at);
+                    println!("max views: {}", paste.max_views);
+                    println!("trashed: {}", paste.trashed_at);
+                    println!("views: {views}");
+                    println!(
+                        "metadata: {}",
+                        serde_json::to_string_pretty(&paste.metadata.redacted()).unwrap()
+                    );
+                }
+                Err(e) => fail(e),
+            }
+        }
+        (Some("delete"), Some(url), None) => {
+            match database.force_delete_paste_by_url(url.to_string()).await {
+                Ok(_) => println!("Deleted {url}."),
+                Err(e) => fail(e),
+            }
+        }
+        (Some("restore"), Some(url), None) => {
+            match database.force_restore_paste_by_url(url.to_string()).await {
+                Ok(_) => println!("Restored {url}."),
+                Err(e) => fail(e),
+            }
+        }
+        (Some("rename"), Some(url), Some(new_url)) => match database
+            .force_rename_paste_by_url(url.to_string(), new_url.to_string())
+            .await
+        {
+            Ok(_) => println!("Renamed {url} to {new_url}."),
+            Err(e) => fail(e),
+        },
+        (Some("reset-password"), Some(url), None) => {
+            match database.force_reset_password_by_url(url.to_string()).await {
+                Ok(password) => println!("New edit password of {url}: {password}"),
+                Err(e) => fail(e),
+            }
+        }
+        (Some("transfer"), Some(url), owner) => match database
+            .force_transfer_paste_by_url(url.to_string(), owner.unwrap_or("").to_string())
+            .await
+        {
+            Ok(_) => match owner {
+                Some(owner) => println!("Transferred {url} to {owner}."),
+                None => println!("Removed the owner of {url}."),
+            },
+            Err(e) => fail(e),
+        },
+        (Some("purge-cache"), Some(url), None) => {
+            match database.purge_cache_by_url(url.to_string()).await {
+                Ok(_) => println!("Purged the cache of {url}."),
+                Err(e) => fail(e),
+            }
+        }
+        _ => {
+            eprintln!("{USAGE}");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Print a paste error and exit
+fn fail(e: pastemd::model::PasteError) -> ! {
+    eprintln!("{}", e);
+    std::process::exit(1);
+}
@@ -22,18 +22,9 @@ impl BaseStore {
                 Ok(s) => s,
                 Err(_) => String::from("Crangon"),
             },
-            info_url: match env::var("INFO_URL") {
-                Ok(s) => s,
-                Err(_) => String::new(),
-            },
-            body_embed: match env::var("BODY_EMBED") {
-                Ok(s) => s,
-                Err(_) => String::new(),
-            },
-            guppy_root: match env::var("GUPPY_ROOT") {
-                Ok(s) => s,
-                Err(_) => String::new(),
-            },
+            info_url: env::var("INFO_URL").unwrap_or_default(),
+            body_embed: env::var("BODY_EMBED").unwrap_or_default(),
+            guppy_root: env::var("GUPPY_ROOT").unwrap_or_default(),
             secret: match env::var("SECRET") {
                 Ok(s) => s == "true",
                 Err(_) => false,
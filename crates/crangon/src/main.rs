@@ -1,7 +1,13 @@
 use axum::{routing::get, Router};
-use pastemd::{database::Database, routing::api};
+use pastemd::{
+    database::Database,
+    routing::api,
+    store::{memory::MemoryStore, PasteStore},
+};
 use std::env;
+use std::net::SocketAddr;
 
+mod admin;
 mod base;
 mod markdown;
 mod pages;
@@ -9,17 +15,28 @@ mod pages;
 pub fn env_options() -> pastemd::DatabaseOpts {
     use std::env::var;
     pastemd::DatabaseOpts {
-        _type: match var("DB_TYPE") {
-            Ok(v) => Option::Some(v),
-            Err(_) => Option::None,
-        },
-        host: match var("DB_HOST") {
-            Ok(v) => Option::Some(v),
-            Err(_) => Option::None,
-        },
-        user: var("DB_USER").unwrap_or(String::new()),
-        pass: var("DB_PASS").unwrap_or(String::new()),
-        name: var("DB_NAME").unwrap_or(String::new()),
+        _type: var("DB_TYPE").ok(),
+        host: var("DB_HOST").ok(),
+        user: var("DB_USER").unwrap_or_default(),
+        pass: var("DB_PASS").unwrap_or_default(),
+        name: var("DB_NAME").unwrap_or_default(),
+    }
+}
+
+/// Read a rate limit given as `{requests}/{window seconds}` (`0/60` = unlimited)
+fn env_rate_limit(
+    name: &str,
+    default: pastemd::database::RateLimit,
+) -> pastemd::database::RateLimit {
+    match env::var(name) {
+        Ok(v) => {
+            let (requests, window_secs) = v.split_once("/").expect("invalid rate limit");
+            pastemd::database::RateLimit {
+                requests: requests.parse::<u32>().unwrap(),
+                window_secs: window_secs.parse::<u64>().unwrap(),
+            }
+        }
+        Err(_) => default,
     }
 }
 
@@ -32,43 +49,301 @@ async fn main() {
         Err(_) => 8080,
     };
 
+    // server options
+    let options = pastemd::database::ServerOptions {
+        view_password: true,
+        guppy: env::var("GUPPY_ROOT").is_ok(),
+        paste_ownership: true,
+        view_mode: if env::var("GUPPY_ROOT").is_ok() {
+            pastemd::database::ViewMode::AuthenticatedOnce
+        } else {
+            match env::var("VIEW_WINDOW") {
+                Ok(v) => pastemd::database::ViewMode::OpenDeduplicated {
+                    window_secs: v.parse::<u64>().unwrap(),
+                },
+                Err(_) => pastemd::database::ViewMode::OpenMultiple,
+            }
+        },
+        table_pastes: pastemd::database::PastesTableConfig {
+            table_name: "cr_pastes".to_string(),
+            prefix: "cr_paste".to_string(),
+            url: "custom_url".to_string(),
+            password: "edit_password".to_string(),
+            content: "content".to_string(),
+            date_published: "pub_date".to_string(),
+            date_edited: "edit_date".to_string(),
+            ..Default::default()
+        },
+        table_views: pastemd::database::ViewsTableConfig {
+            table_name: "cr_views".to_string(),
+            prefix: "cr_views".to_string(),
+        },
+        table_revisions: pastemd::database::RevisionsTableConfig {
+            table_name: "cr_revisions".to_string(),
+            prefix: "cr_revisions".to_string(),
+            ..Default::default()
+        },
+        table_search: pastemd::database::SearchTableConfig {
+            table_name: "cr_search".to_string(),
+            prefix: "cr_search".to_string(),
+        },
+        table_stats: pastemd::database::StatsTableConfig {
+            table_name: "cr_view_stats".to_string(),
+            prefix: "cr_view_stats".to_string(),
+        },
+        table_attachments: pastemd::database::AttachmentsTableConfig {
+            table_name: "cr_attachments".to_string(),
+            prefix: "cr_attachments".to_string(),
+        },
+        table_tags: pastemd::database::TagsTableConfig {
+            table_name: "cr_tags".to_string(),
+            prefix: "cr_tags".to_string(),
+        },
+        table_collections: pastemd::database::CollectionsTableConfig {
+            table_name: "cr_collections".to_string(),
+            members_table_name: "cr_collection_pastes".to_string(),
+            prefix: "cr_collections".to_string(),
+        },
+        table_migrations: pastemd::database::MigrationsTableConfig {
+            table_name: "cr_migrations".to_string(),
+            prefix: "cr_migrations".to_string(),
+        },
+        reaper_interval: match env::var("REAPER_INTERVAL") {
+            Ok(v) => v.parse::<u64>().unwrap(),
+            Err(_) => 60,
+        },
+        view_flush_interval: match env::var("VIEW_FLUSH_INTERVAL") {
+            Ok(v) => v.parse::<u64>().unwrap(),
+            Err(_) => 30,
+        },
+        view_salt: match env::var("VIEW_SALT") {
+            Ok(v) => v,
+            Err(_) => pastemd::database::ServerOptions::default().view_salt, // random
+        },
+        unlock_secret: match env::var("UNLOCK_SECRET") {
+            Ok(v) => v,
+            Err(_) => pastemd::database::ServerOptions::default().unlock_secret, // random
+        },
+        unlock_ttl: match env::var("UNLOCK_TTL") {
+            Ok(v) => v.parse::<u64>().unwrap(),
+            Err(_) => 60 * 60,
+        },
+        rate_limits: {
+            let defaults = pastemd::database::RateLimitConfig::default();
+            pastemd::database::RateLimitConfig {
+                prefix: "cr_rate_limit".to_string(),
+                create: env_rate_limit("RATE_LIMIT_CREATE", defaults.create),
+                write: env_rate_limit("RATE_LIMIT_WRITE", defaults.write),
+                read: env_rate_limit("RATE_LIMIT_READ", defaults.read),
+                trusted_proxies: match env::var("TRUSTED_PROXIES") {
+                    Ok(v) => v.parse::<usize>().unwrap(),
+                    Err(_) => defaults.trusted_proxies,
+                },
+                ..defaults
+            }
+        },
+        trash_retention: match env::var("TRASH_RETENTION") {
+            Ok(v) => v.parse::<u64>().unwrap(),
+            Err(_) => 60 * 60 * 24 * 7,
+        },
+        validation: pastemd::database::ValidationPolicy::default(),
+        attachments: {
+            let defaults = pastemd::database::AttachmentConfig::default();
+            pastemd::database::AttachmentConfig {
+                directory: env::var("ATTACHMENTS_DIR").unwrap_or(defaults.directory),
+                max_size: match env::var("ATTACHMENT_MAX_SIZE") {
+                    Ok(v) => v.parse::<usize>().unwrap(),
+                    Err(_) => defaults.max_size,
+                },
+                allowed_types: match env::var("ATTACHMENT_TYPES") {
+                    Ok(v) => v
+                        .split(",")
+                        .map(|t| t.trim().to_lowercase())
+                        .filter(|t| !t.is_empty())
+                        .collect(),
+                    Err(_) => defaults.allowed_types,
+                },
+                ..defaults
+            }
+        },
+    };
+
+    // handle subcommands
+    let args: Vec<String> = env::args().collect();
+
+    if args.iter().any(|a| a == "--ephemeral") {
+        // nothing is written to the database in this mode
+        println!("Running in ephemeral mode, pastes will be lost when the server stops.");
+        let store = MemoryStore::new(options);
+        store.spawn_background_tasks();
+        serve(store, port).await;
+        return;
+    }
+
     // init database
-    let database = Database::new(
-        env_options(),
-        pastemd::database::ServerOptions {
-            view_password: true,
-            guppy: env::var("GUPPY_ROOT").is_ok(),
-            paste_ownership: true,
-            view_mode: if env::var("GUPPY_ROOT").is_ok() {
-                pastemd::database::ViewMode::AuthenticatedOnce
-            } else {
-                pastemd::database::ViewMode::OpenMultiple
-            },
-            table_pastes: pastemd::database::PastesTableConfig {
-                table_name: "cr_pastes".to_string(),
-                prefix: "cr_paste".to_string(),
-                url: "custom_url".to_string(),
-                password: "edit_password".to_string(),
-                content: "content".to_string(),
-                date_published: "pub_date".to_string(),
-                date_edited: "edit_date".to_string(),
-                ..Default::default()
-            },
-            table_views: pastemd::database::ViewsTableConfig {
-                table_name: "cr_views".to_string(),
-                prefix: "cr_views".to_string(),
-            },
+    let database = Database::new(env_options(), options).await;
+
+    if args.get(1).map(|a| a.as_str()) == Some("migrate") {
+        match database.migrate().await {
+            Ok(applied) => {
+                if applied.is_empty() {
+                    println!("Database schema is already up to date.");
+                } else {
+                    println!("Applied migrations: {:?}", applied);
+                }
+            }
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(1);
+            }
+        }
+
+        return;
+    }
+
+    if args.get(1).map(|a| a.as_str()) == Some("admin") {
+        if let Err(e) = database.check_schema().await {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+
+        admin::run(&database, &args[2..]).await;
+        return;
+    }
+
+    if let Some(command @ ("export" | "import")) = args.get(1).map(|a| a.as_str()) {
+        if let Err(e) = database.check_schema().await {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+
+        if command == "export" {
+            export(&database, args.get(2)).await;
+        } else {
+            import(&database, &args[2..]).await;
+        }
+
+        return;
+    }
+
+    // check database schema
+    let auto_migrate = match env::var("AUTO_MIGRATE") {
+        Ok(v) => v != "false",
+        Err(_) => true,
+    };
+
+    if let Err(e) = if auto_migrate {
+        database.init().await.map(|_| ())
+    } else {
+        database.check_schema().await.map(|_| ())
+    } {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
+
+    database.spawn_background_tasks();
+    serve(database.clone(), port).await;
+
+    // save the views counted since the last flush
+    if database.options.view_flush_interval != 0 {
+        if let Err(e) = database.flush_views().await {
+            eprintln!("Failed to save view counts: {}", e);
+        }
+    }
+}
+
+/// Export every paste as JSON Lines to `path` (stdout if not given)
+async fn export(database: &Database, path: Option<&String>) {
+    let result = match path {
+        Some(path) => match std::fs::File::create(path) {
+            Ok(f) => database.export_all(std::io::BufWriter::new(f)).await,
+            Err(e) => {
+                eprintln!("Failed to create {path}: {e}");
+                std::process::exit(1);
+            }
         },
-    )
-    .await;
+        None => {
+            database
+                .export_all(std::io::BufWriter::new(std::io::stdout()))
+                .await
+        }
+    };
+
+    match result {
+        Ok(count) => eprintln!("Exported {count} pastes."),
+        Err(e) => {
+            eprintln!("Export failed: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Import pastes from a JSON Lines file (`crangon import <file> [--on-conflict=skip|overwrite|rename]`)
+async fn import(database: &Database, args: &[String]) {
+    let mut path: Option<&String> = None;
+    let mut conflict = pastemd::model::ImportConflict::Skip;
+
+    for arg in args {
+        match arg.strip_prefix("--on-conflict=") {
+            Some("skip") => conflict = pastemd::model::ImportConflict::Skip,
+            Some("overwrite") => conflict = pastemd::model::ImportConflict::Overwrite,
+            Some("rename") => conflict = pastemd::model::ImportConflict::Rename,
+            Some(other) => {
+                eprintln!(
+                    "Unknown conflict policy \"{other}\" (expected skip, overwrite or rename)."
+                );
+                std::process::exit(1);
+            }
+            None => path = Some(arg),
+        }
+    }
 
-    database.init().await;
+    let path = match path {
+        Some(p) => p,
+        None => {
+            eprintln!("Usage: crangon import <file> [--on-conflict=skip|overwrite|rename]");
+            std::process::exit(1);
+        }
+    };
+
+    let file = match std::fs::File::open(path) {
+        Ok(f) => f,
+        Err(e) => {
+            eprintln!("Failed to open {path}: {e}");
+            std::process::exit(1);
+        }
+    };
+
+    match database
+        .import(std::io::BufReader::new(file), conflict)
+        .await
+    {
+        Ok(summary) => {
+            println!(
+                "Imported {} pastes, overwrote {}, skipped {}, renamed {}.",
+                summary.imported,
+                summary.overwritten,
+                summary.skipped,
+                summary.renamed.len()
+            );
 
-    // ...
+            for (old, new) in summary.renamed {
+                println!("  {old} -> {new}");
+            }
+        }
+        Err(e) => {
+            eprintln!("Import failed: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Serve crangon using the given paste store
+async fn serve<S: PasteStore>(store: S, port: u16) {
     let app = Router::new()
         .route("/", get(pages::homepage))
-        .merge(pages::routes(database.clone()))
-        .nest("/api", api::routes(database.clone()))
+        .merge(pages::routes(store.clone()))
+        .nest("/api", api::routes(store.clone()))
         .fallback(api::not_found);
 
     let listener = tokio::net::TcpListener::bind(format!("127.0.0.1:{port}"))
@@ -76,5 +351,36 @@ async fn main() {
         .unwrap();
 
     println!("Starting server at http://localhost:{port}!");
-    axum::serve(listener, app).await.unwrap();
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown_signal())
+    .await
+    .unwrap();
+}
+
+/// Wait until the server is asked to stop (ctrl+c, or `SIGTERM` on unix)
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c().await.unwrap();
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .unwrap()
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => (),
+        _ = terminate => (),
+    }
+
+    println!("Shutting down...");
 }
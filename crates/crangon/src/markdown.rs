@@ -44,7 +44,7 @@ pub fn parse_markdown(input: String) -> String {
                 let content = capture.get(4).unwrap().as_str(); // columns split by "|"
 
                 // build modifiers
-                let gap = modifiers.get(0).unwrap_or(&"0.2rem");
+                let gap = modifiers.first().unwrap_or(&"0.2rem");
                 let justify = modifiers.get(1).unwrap_or(&"center");
                 let align = modifiers.get(2).unwrap_or(&"center");
                 let direction = modifiers.get(3).unwrap_or(&"row");
@@ -78,6 +78,22 @@ pub fn parse_markdown(input: String) -> String {
     )
 }
 
+/// Point links and images which reference an attachment by its relative name
+/// (`shot.png` or `./shot.png`) to where the attachment is served (`/:url/files/:name`)
+pub fn link_attachments(mut html: String, url: &str, names: &[String]) -> String {
+    for name in names {
+        for attr in ["src", "href"] {
+            let target = format!("{attr}=\"/{url}/files/{name}\"");
+
+            html = html
+                .replace(&format!("{attr}=\"{name}\""), &target)
+                .replace(&format!("{attr}=\"./{name}\""), &target);
+        }
+    }
+
+    html
+}
+
 #[allow(dead_code)]
 fn regex_replace_exp(input: &str, pattern: &mut RegexBuilder, replace_with: &str) -> String {
     pattern
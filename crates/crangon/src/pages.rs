@@ -1,23 +1,48 @@
 use askama_axum::Template;
 use axum::{
-    extract::{Path, State, Query},
+    extract::{ConnectInfo, Path, State, Query},
+    http::{HeaderMap, StatusCode},
+    middleware,
     response::{Html, Json, IntoResponse},
     routing::{get, post, get_service},
-    Router,
+    Form, Router,
 };
 use axum_extra::extract::cookie::CookieJar;
 
 use tower_http::services::ServeDir;
-use pastemd::{database::Database, model::Paste};
-use crate::markdown::parse_markdown;
+use pastemd::{
+    routing::ratelimit::{client_ip, rate_limit, RouteClass},
+    store::{can_view_stats, PasteStore},
+    model::{
+        Paste, PasteAttachment, PasteForks, PasteRevision, PasteStats, PasteSearchResult,
+        PasteSort, PasteSummary, PasteTagged, TagCount, Collection, PublicCollection,
+    },
+};
+use crate::markdown::{link_attachments, parse_markdown};
 use serde::{Serialize, Deserialize};
+use std::net::SocketAddr;
 
-pub fn routes(database: Database) -> Router {
+pub fn routes<S: PasteStore>(database: S) -> Router {
     Router::new()
-        .route("/:url/edit/config", get(config_editor_request))
-        .route("/:url/edit", get(editor_request))
-        .route("/:url", get(view_paste_request))
-        .route("/api/render", post(render_markdown))
+        .route("/search", get(search_request::<S>))
+        .route("/dashboard", get(dashboard_request::<S>))
+        .route("/collections/:name", get(collection_request::<S>))
+        .route("/tags", get(tags_request::<S>))
+        .route("/tags/:tag", get(tag_request::<S>))
+        .route("/:url/edit/config", get(config_editor_request::<S>))
+        .route("/:url/edit", get(editor_request::<S>))
+        .route("/:url/history", get(history_request::<S>))
+        .route("/:url/stats", get(stats_request::<S>))
+        .route(
+            "/:url/unlock",
+            post(unlock_request::<S>).route_layer(middleware::from_fn_with_state(
+                (database.clone(), RouteClass::Write),
+                rate_limit::<S>,
+            )),
+        )
+        .route("/:url/files/:name", get(file_request::<S>))
+        .route("/:url", get(view_paste_request::<S>))
+        .route("/api/render", post(render_markdown::<S>))
         // serve static dir
         .nest_service("/static", get_service(ServeDir::new("./static")))
         // ...
@@ -40,18 +65,31 @@ struct PasteViewTemplate {
     title: String,
     views: i32,
     head_stuff: String,
+    is_owner: bool,
+    /// The first page of pastes derived from this paste (if it's a template)
+    forks: Option<PasteForks>,
+    /// If the template this paste is derived from has changed since it was derived (or synced)
+    template_updates: bool,
+    /// Where this paste sits in each collection it belongs to
+    collections: Vec<CollectionNav>,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
-pub struct PasteViewQuery {
-    #[serde(default)]
-    view_password: String,
+/// Where a paste sits in one of the collections it belongs to
+struct CollectionNav {
+    name: String,
+    title: String,
+    /// The position of the paste among the public pastes of the collection (starting at 1)
+    position: usize,
+    total: usize,
+    previous: Option<PasteSummary>,
+    next: Option<PasteSummary>,
 }
 
 #[derive(Template)]
 #[template(path = "paste_password.html")]
 struct PastePasswordTemplate {
     paste: Paste,
+    error: String,
 }
 
 #[derive(Template)]
@@ -60,48 +98,146 @@ struct ErrorViewTemplate {
     error: String,
 }
 
-pub async fn view_paste_request(
+/// Check if a paste has a view password which hasn't been unlocked by the `__Secure-Unlock` cookie
+fn is_locked<S: PasteStore>(database: &S, jar: &CookieJar, paste: &Paste) -> bool {
+    if (!database.options().view_password) | paste.metadata.view_password.is_empty() {
+        return false;
+    }
+
+    match jar.get("__Secure-Unlock") {
+        Some(c) => !database.paste_is_unlocked(paste, c.value_trimmed()),
+        None => true,
+    }
+}
+
+#[derive(Deserialize)]
+pub struct UnlockForm {
+    #[serde(default)]
+    view_password: String,
+}
+
+/// Check a paste's view password and unlock it for a while (`POST /:url/unlock`)
+pub async fn unlock_request<S: PasteStore>(
+    Path(url): Path<String>,
+    State(database): State<S>,
+    Form(form): Form<UnlockForm>,
+) -> impl IntoResponse {
+    match database.unlock_paste_by_url(url.clone(), form.view_password).await {
+        // the cookie is scoped to the paste, so it isn't sent anywhere else
+        Ok((token, p)) => (
+            StatusCode::SEE_OTHER,
+            [
+                ("Location".to_string(), format!("/{}", p.url)),
+                (
+                    "Set-Cookie".to_string(),
+                    format!(
+                        "__Secure-Unlock={}; SameSite=Lax; Secure; Path=/{}; HostOnly=true; HttpOnly=true; Max-Age={}",
+                        token,
+                        p.url,
+                        database.options().unlock_ttl
+                    ),
+                ),
+            ],
+        )
+            .into_response(),
+        Err(pastemd::model::PasteError::PasswordIncorrect) => {
+            match database.get_paste_by_url(url).await {
+                Ok(p) => Html(
+                    PastePasswordTemplate {
+                        paste: p,
+                        error: pastemd::model::PasteError::PasswordIncorrect.to_string(),
+                    }
+                    .render()
+                    .unwrap(),
+                )
+                .into_response(),
+                Err(e) => Html(
+                    ErrorViewTemplate {
+                        error: e.to_string(),
+                    }
+                    .render()
+                    .unwrap(),
+                )
+                .into_response(),
+            }
+        }
+        Err(e) => Html(
+            ErrorViewTemplate {
+                error: e.to_string(),
+            }
+            .render()
+            .unwrap(),
+        )
+        .into_response(),
+    }
+}
+
+pub async fn view_paste_request<S: PasteStore>(
     jar: CookieJar,
+    headers: HeaderMap,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
     Path(url): Path<String>,
-    State(database): State<Database>,
-    Query(query_params): Query<PasteViewQuery>,
+    State(database): State<S>,
 ) -> impl IntoResponse {
     match database.get_paste_by_url(url).await {
         Ok(p) => {
             // get user from token
             let auth_user = match jar.get("__Secure-Token") {
-                Some(c) => match database
-                    .auth
-                    .get_user_by_unhashed(c.value_trimmed().to_string())
+                Some(c) => database
+                    .get_user_by_token(c.value_trimmed().to_string())
                     .await
-                {
-                    Ok(ua) => Some(ua),
-                    Err(_) => None,
-                },
+                    .ok(),
                 None => None,
             };
 
             // check for view password
-            if database.options.view_password == true {
-                match query_params.view_password.is_empty() {
-                    false => {
-                        if !p.metadata.view_password.is_empty()
-                            && (query_params.view_password != p.metadata.view_password)
-                        {
-                            return Html(PastePasswordTemplate { paste: p }.render().unwrap());
-                        }
-                    }
-                    true => {
-                        if !p.metadata.view_password.is_empty() {
-                            return Html(PastePasswordTemplate { paste: p }.render().unwrap());
-                        }
+            if is_locked(&database, &jar, &p) {
+                return Html(
+                    PastePasswordTemplate {
+                        paste: p,
+                        error: String::new(),
                     }
-                }
+                    .render()
+                    .unwrap(),
+                );
             }
 
             // push view
             // we could not support paste views by just.. not doing this
-            if let Err(e) = database.incr_views_by_url(p.url.clone(), auth_user).await {
+            // anonymous viewers are identified by their ip and user agent
+            let ip = client_ip(
+                &headers,
+                Some(addr),
+                database.options().rate_limits.trusted_proxies,
+            );
+
+            let user_agent = headers
+                .get("User-Agent")
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or("");
+
+            let referrer = headers
+                .get("Referer")
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or("")
+                .to_string();
+
+            let is_owner = match auth_user {
+                Some(ref ua) => {
+                    !p.metadata.owner.is_empty() && (ua.user.username == p.metadata.owner)
+                }
+                None => false,
+            };
+
+            if let Err(e) = database
+                .incr_views_by_url(
+                    p.url.clone(),
+                    auth_user,
+                    database.viewer_key(&ip, user_agent),
+                    referrer,
+                )
+                .await
+            {
                 return Html(
                     ErrorViewTemplate {
                         error: e.to_string(),
@@ -112,7 +248,18 @@ pub async fn view_paste_request(
             }
 
             // ...
-            let rendered = parse_markdown(p.content.clone());
+            let forks = if p.metadata.template == "@" {
+                database.get_forks_by_url(p.url.clone(), 25, 0).await.ok()
+            } else {
+                None
+            };
+
+            let template_updates = database.template_has_updates(&p).await;
+            let collections = collection_navigation(&database, &p.url).await;
+
+            let attachments = attachment_names(&database, &p.url).await;
+            let rendered =
+                link_attachments(parse_markdown(p.content.clone()), &p.url, &attachments);
             Html(
                 PasteViewTemplate {
                     paste: p.clone(),
@@ -147,6 +294,10 @@ pub async fn view_paste_request(
                             &p.metadata.favicon
                         }
                     ),
+                    is_owner,
+                    forks,
+                    template_updates,
+                    collections,
                 }
                 .render()
                 .unwrap(),
@@ -162,24 +313,136 @@ pub async fn view_paste_request(
     }
 }
 
+/// Get the position of the paste at `url` in each collection it belongs to (blank if they
+/// can't be read)
+///
+/// Only the public pastes of a collection are counted, so private pastes are never linked.
+async fn collection_navigation<S: PasteStore>(database: &S, url: &str) -> Vec<CollectionNav> {
+    let collections = match database.get_collections_by_url(url.to_string()).await {
+        Ok(c) => c,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut navigation: Vec<CollectionNav> = Vec::new();
+
+    for collection in collections {
+        let collection = match database
+            .get_public_collection_by_name(collection.name)
+            .await
+        {
+            Ok(c) => c,
+            Err(_) => continue,
+        };
+
+        let index = match collection.pastes.iter().position(|p| p.url == url) {
+            Some(i) => i,
+            None => continue,
+        };
+
+        navigation.push(CollectionNav {
+            position: index + 1,
+            total: collection.pastes.len(),
+            previous: match index {
+                0 => None,
+                i => collection.pastes.get(i - 1).cloned(),
+            },
+            next: collection.pastes.get(index + 1).cloned(),
+            name: collection.name,
+            title: collection.title,
+        });
+    }
+
+    navigation
+}
+
+/// Get the names of the attachments of the paste at `url` (blank if they can't be read)
+async fn attachment_names<S: PasteStore>(database: &S, url: &str) -> Vec<String> {
+    match database.get_attachments_by_url(url.to_string()).await {
+        Ok(a) => a.into_iter().map(|a| a.name).collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Serve an attachment of a paste (`/:url/files/:name`)
+pub async fn file_request<S: PasteStore>(
+    jar: CookieJar,
+    Path((url, name)): Path<(String, String)>,
+    State(database): State<S>,
+) -> impl IntoResponse {
+    let p = match database.get_paste_by_url(url).await {
+        Ok(p) => p,
+        Err(e) => {
+            return Html(
+                ErrorViewTemplate {
+                    error: e.to_string(),
+                }
+                .render()
+                .unwrap(),
+            )
+            .into_response()
+        }
+    };
+
+    // check for view password
+    if is_locked(&database, &jar, &p) {
+        return Html(
+            PastePasswordTemplate {
+                paste: p,
+                error: String::new(),
+            }
+            .render()
+            .unwrap(),
+        )
+        .into_response();
+    }
+
+    // ...
+    match database.get_attachment_by_name(p.url, name).await {
+        Ok((a, data)) => (
+            [
+                ("Content-Type".to_string(), a.content_type),
+                (
+                    "Content-Disposition".to_string(),
+                    format!("inline; filename=\"{}\"", a.name),
+                ),
+                ("X-Content-Type-Options".to_string(), "nosniff".to_string()),
+                // uploaded files never get to run scripts on our origin
+                (
+                    "Content-Security-Policy".to_string(),
+                    "default-src 'none'; sandbox".to_string(),
+                ),
+            ],
+            data,
+        )
+            .into_response(),
+        Err(e) => Html(
+            ErrorViewTemplate {
+                error: e.to_string(),
+            }
+            .render()
+            .unwrap(),
+        )
+        .into_response(),
+    }
+}
+
 #[derive(Template)]
 #[template(path = "paste_editor.html")]
 struct EditorTemplate {
     paste: Paste,
     passwordless: bool,
+    attachments: Vec<PasteAttachment>,
 }
 
-pub async fn editor_request(
+pub async fn editor_request<S: PasteStore>(
     jar: CookieJar,
     Path(url): Path<String>,
-    State(database): State<Database>,
-    Query(query_params): Query<PasteViewQuery>,
+    State(database): State<S>,
 ) -> impl IntoResponse {
     // get user from token
     let auth_user = match jar.get("__Secure-Token") {
         Some(c) => match database
-            .auth
-            .get_user_by_unhashed(c.value_trimmed().to_string())
+            .get_user_by_token(c.value_trimmed().to_string())
             .await
         {
             Ok(ua) => ua.user.username,
@@ -192,29 +455,29 @@ pub async fn editor_request(
     match database.get_paste_by_url(url).await {
         Ok(p) => {
             // check for view password
-            if database.options.view_password == true {
-                match query_params.view_password.is_empty() {
-                    false => {
-                        if !p.metadata.view_password.is_empty()
-                            && (query_params.view_password != p.metadata.view_password)
-                        {
-                            return Html(PastePasswordTemplate { paste: p }.render().unwrap());
-                        }
-                    }
-                    true => {
-                        if !p.metadata.view_password.is_empty() {
-                            return Html(PastePasswordTemplate { paste: p }.render().unwrap());
-                        }
+            if is_locked(&database, &jar, &p) {
+                return Html(
+                    PastePasswordTemplate {
+                        paste: p,
+                        error: String::new(),
                     }
-                }
+                    .render()
+                    .unwrap(),
+                );
             }
 
             // ...
             let passwordless = !p.metadata.owner.is_empty() && auth_user == p.metadata.owner;
+            let attachments = database
+                .get_attachments_by_url(p.url.clone())
+                .await
+                .unwrap_or_default();
+
             Html(
                 EditorTemplate {
                     paste: p,
                     passwordless,
+                    attachments,
                 }
                 .render()
                 .unwrap(),
@@ -237,19 +500,19 @@ struct ConfigEditorTemplate {
     paste_metadata: String,
     auth_user: String,
     passwordless: bool,
+    /// The collections this paste belongs to
+    collections: Vec<Collection>,
 }
 
-pub async fn config_editor_request(
+pub async fn config_editor_request<S: PasteStore>(
     jar: CookieJar,
     Path(url): Path<String>,
-    State(database): State<Database>,
-    Query(query_params): Query<PasteViewQuery>,
+    State(database): State<S>,
 ) -> impl IntoResponse {
     // get user from token
     let auth_user = match jar.get("__Secure-Token") {
         Some(c) => match database
-            .auth
-            .get_user_by_unhashed(c.value_trimmed().to_string())
+            .get_user_by_token(c.value_trimmed().to_string())
             .await
         {
             Ok(ua) => ua.user.username,
@@ -262,29 +525,29 @@ pub async fn config_editor_request(
     match database.get_paste_by_url(url).await {
         Ok(p) => {
             // check for view password
-            if database.options.view_password == true {
-                match query_params.view_password.is_empty() {
-                    false => {
-                        if !p.metadata.view_password.is_empty()
-                            && (query_params.view_password != p.metadata.view_password)
-                        {
-                            return Html(PastePasswordTemplate { paste: p }.render().unwrap());
-                        }
-                    }
-                    true => {
-                        if !p.metadata.view_password.is_empty() {
-                            return Html(PastePasswordTemplate { paste: p }.render().unwrap());
-                        }
+            if is_locked(&database, &jar, &p) {
+                return Html(
+                    PastePasswordTemplate {
+                        paste: p,
+                        error: String::new(),
                     }
-                }
+                    .render()
+                    .unwrap(),
+                );
             }
 
             // ...
             let passwordless = !p.metadata.owner.is_empty() && auth_user == p.metadata.owner;
+            let collections = database
+                .get_collections_by_url(p.url.clone())
+                .await
+                .unwrap_or_default();
+
             Html(
                 ConfigEditorTemplate {
                     paste: p.clone(),
-                    paste_metadata: match serde_json::to_string(&p.metadata) {
+                    // the view password hash never leaves the server
+                    paste_metadata: match serde_json::to_string(&p.metadata.clone().redacted()) {
                         Ok(m) => m,
                         Err(_) => {
                             return Html(
@@ -298,11 +561,420 @@ pub async fn config_editor_request(
                     },
                     auth_user,
                     passwordless,
+                    collections,
+                }
+                .render()
+                .unwrap(),
+            )
+        }
+        Err(e) => Html(
+            ErrorViewTemplate {
+                error: e.to_string(),
+            }
+            .render()
+            .unwrap(),
+        ),
+    }
+}
+
+#[derive(Template)]
+#[template(path = "paste_stats.html")]
+struct StatsTemplate {
+    paste: Paste,
+    stats: PasteStats,
+    max_day_views: i64,
+}
+
+pub async fn stats_request<S: PasteStore>(
+    jar: CookieJar,
+    Path(url): Path<String>,
+    State(database): State<S>,
+) -> impl IntoResponse {
+    // get user from token
+    let auth_user = match jar.get("__Secure-Token") {
+        Some(c) => database
+            .get_user_by_token(c.value_trimmed().to_string())
+            .await
+            .ok(),
+        None => None,
+    };
+
+    // ...
+    match database.get_paste_by_url(url).await {
+        Ok(p) => {
+            // only the paste owner and paste managers can see this page (like the api)
+            if !can_view_stats(&p, &auth_user) {
+                return Html(
+                    ErrorViewTemplate {
+                        error:
+                            "Only the owner of this paste (or a paste manager) can view its stats."
+                                .to_string(),
+                    }
+                    .render()
+                    .unwrap(),
+                );
+            }
+
+            // get stats
+            let stats = match database.get_stats_by_url(p.url.clone(), 30).await {
+                Ok(s) => s,
+                Err(e) => {
+                    return Html(
+                        ErrorViewTemplate {
+                            error: e.to_string(),
+                        }
+                        .render()
+                        .unwrap(),
+                    )
+                }
+            };
+
+            // ...
+            Html(
+                StatsTemplate {
+                    paste: p,
+                    max_day_views: stats.days.iter().map(|d| d.views).max().unwrap_or(1),
+                    stats,
+                }
+                .render()
+                .unwrap(),
+            )
+        }
+        Err(e) => Html(
+            ErrorViewTemplate {
+                error: e.to_string(),
+            }
+            .render()
+            .unwrap(),
+        ),
+    }
+}
+
+#[derive(Template)]
+#[template(path = "paste_history.html")]
+struct HistoryTemplate {
+    paste: Paste,
+    revisions: Vec<PasteRevision>,
+    passwordless: bool,
+}
+
+pub async fn history_request<S: PasteStore>(
+    jar: CookieJar,
+    Path(url): Path<String>,
+    State(database): State<S>,
+) -> impl IntoResponse {
+    // get user from token
+    let auth_user = match jar.get("__Secure-Token") {
+        Some(c) => match database
+            .get_user_by_token(c.value_trimmed().to_string())
+            .await
+        {
+            Ok(ua) => ua.user.username,
+            Err(_) => String::new(),
+        },
+        None => String::new(),
+    };
+
+    // ...
+    match database.get_paste_by_url(url).await {
+        Ok(p) => {
+            // check for view password
+            if is_locked(&database, &jar, &p) {
+                return Html(
+                    PastePasswordTemplate {
+                        paste: p,
+                        error: String::new(),
+                    }
+                    .render()
+                    .unwrap(),
+                );
+            }
+
+            // get revisions
+            let revisions = match database.get_revisions_by_url(p.url.clone()).await {
+                Ok(r) => r,
+                Err(e) => {
+                    return Html(
+                        ErrorViewTemplate {
+                            error: e.to_string(),
+                        }
+                        .render()
+                        .unwrap(),
+                    )
+                }
+            };
+
+            // ...
+            let passwordless = !p.metadata.owner.is_empty() && auth_user == p.metadata.owner;
+            Html(
+                HistoryTemplate {
+                    paste: p,
+                    revisions,
+                    passwordless,
+                }
+                .render()
+                .unwrap(),
+            )
+        }
+        Err(e) => Html(
+            ErrorViewTemplate {
+                error: e.to_string(),
+            }
+            .render()
+            .unwrap(),
+        ),
+    }
+}
+
+#[derive(Template)]
+#[template(path = "tags.html")]
+struct TagsTemplate {
+    tags: Vec<TagCount>,
+    /// The count of the most used tag (used to scale the cloud)
+    max_count: i64,
+}
+
+pub async fn tags_request<S: PasteStore>(State(database): State<S>) -> impl IntoResponse {
+    match database.get_tags(100).await {
+        Ok(mut tags) => {
+            let max_count = tags.iter().map(|t| t.count).max().unwrap_or(1);
+
+            // the cloud is shown alphabetically
+            tags.sort_by(|a, b| a.tag.cmp(&b.tag));
+
+            Html(TagsTemplate { tags, max_count }.render().unwrap())
+        }
+        Err(e) => Html(
+            ErrorViewTemplate {
+                error: e.to_string(),
+            }
+            .render()
+            .unwrap(),
+        ),
+    }
+}
+
+#[derive(Template)]
+#[template(path = "tag.html")]
+struct TagTemplate {
+    tagged: PasteTagged,
+    page: i64,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TagQuery {
+    #[serde(default)]
+    page: i64,
+}
+
+pub async fn tag_request<S: PasteStore>(
+    Path(tag): Path<String>,
+    State(database): State<S>,
+    Query(query_params): Query<TagQuery>,
+) -> impl IntoResponse {
+    let offset = match query_params.page.checked_mul(25) {
+        Some(o) => o,
+        None => {
+            return Html(
+                ErrorViewTemplate {
+                    error: pastemd::model::PasteError::ValueError.to_string(),
                 }
                 .render()
                 .unwrap(),
             )
         }
+    };
+
+    match database.get_pastes_by_tag(tag, 25, offset).await {
+        Ok(tagged) => Html(
+            TagTemplate {
+                tagged,
+                page: query_params.page,
+            }
+            .render()
+            .unwrap(),
+        ),
+        Err(e) => Html(
+            ErrorViewTemplate {
+                error: e.to_string(),
+            }
+            .render()
+            .unwrap(),
+        ),
+    }
+}
+
+#[derive(Template)]
+#[template(path = "collection.html")]
+struct CollectionTemplate {
+    collection: PublicCollection,
+    is_owner: bool,
+}
+
+pub async fn collection_request<S: PasteStore>(
+    jar: CookieJar,
+    Path(name): Path<String>,
+    State(database): State<S>,
+) -> impl IntoResponse {
+    // get user from token
+    let auth_user = match jar.get("__Secure-Token") {
+        Some(c) => match database
+            .get_user_by_token(c.value_trimmed().to_string())
+            .await
+        {
+            Ok(ua) => ua.user.username,
+            Err(_) => String::new(),
+        },
+        None => String::new(),
+    };
+
+    // ...
+    match database.get_public_collection_by_name(name).await {
+        Ok(collection) => Html(
+            CollectionTemplate {
+                is_owner: !collection.owner.is_empty() && (auth_user == collection.owner),
+                collection,
+            }
+            .render()
+            .unwrap(),
+        ),
+        Err(e) => Html(
+            ErrorViewTemplate {
+                error: e.to_string(),
+            }
+            .render()
+            .unwrap(),
+        ),
+    }
+}
+
+#[derive(Template)]
+#[template(path = "search.html")]
+struct SearchTemplate {
+    query: String,
+    page: i64,
+    results: Vec<PasteSearchResult>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SearchQuery {
+    #[serde(default)]
+    q: String,
+    #[serde(default)]
+    page: i64,
+}
+
+pub async fn search_request<S: PasteStore>(
+    State(database): State<S>,
+    Query(query_params): Query<SearchQuery>,
+) -> impl IntoResponse {
+    let offset = match query_params.page.checked_mul(25) {
+        Some(o) => o,
+        None => {
+            return Html(
+                ErrorViewTemplate {
+                    error: pastemd::model::PasteError::ValueError.to_string(),
+                }
+                .render()
+                .unwrap(),
+            )
+        }
+    };
+
+    match database
+        .search_pastes(query_params.q.clone(), 25, offset)
+        .await
+    {
+        Ok(results) => Html(
+            SearchTemplate {
+                query: query_params.q,
+                page: query_params.page,
+                results,
+            }
+            .render()
+            .unwrap(),
+        ),
+        Err(e) => Html(
+            ErrorViewTemplate {
+                error: e.to_string(),
+            }
+            .render()
+            .unwrap(),
+        ),
+    }
+}
+
+#[derive(Template)]
+#[template(path = "dashboard.html")]
+struct DashboardTemplate {
+    auth_user: String,
+    pastes: Vec<PasteSummary>,
+    sort: String,
+    next_cursor: String,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DashboardQuery {
+    #[serde(default)]
+    sort: PasteSort,
+    #[serde(default)]
+    cursor: String,
+}
+
+pub async fn dashboard_request<S: PasteStore>(
+    jar: CookieJar,
+    State(database): State<S>,
+    Query(query_params): Query<DashboardQuery>,
+) -> impl IntoResponse {
+    // get user from token
+    let auth_user = match jar.get("__Secure-Token") {
+        Some(c) => match database
+            .get_user_by_token(c.value_trimmed().to_string())
+            .await
+        {
+            Ok(ua) => ua.user.username,
+            Err(_) => String::new(),
+        },
+        None => String::new(),
+    };
+
+    if auth_user.is_empty() {
+        return Html(
+            DashboardTemplate {
+                auth_user,
+                pastes: Vec::new(),
+                sort: String::new(),
+                next_cursor: String::new(),
+            }
+            .render()
+            .unwrap(),
+        );
+    }
+
+    // ...
+    match database
+        .list_pastes_by_owner(
+            auth_user.clone(),
+            query_params.sort,
+            query_params.cursor,
+            25,
+            true,
+        )
+        .await
+    {
+        Ok(list) => Html(
+            DashboardTemplate {
+                auth_user,
+                pastes: list.pastes,
+                sort: match query_params.sort {
+                    PasteSort::DatePublished => "date_published".to_string(),
+                    PasteSort::DateEdited => "date_edited".to_string(),
+                },
+                next_cursor: list.next_cursor,
+            }
+            .render()
+            .unwrap(),
+        ),
         Err(e) => Html(
             ErrorViewTemplate {
                 error: e.to_string(),
@@ -316,9 +988,28 @@ pub async fn config_editor_request(
 #[derive(Clone, serde::Serialize, serde::Deserialize)]
 pub struct RenderMarkdown {
     pub content: String,
+    /// The paste being previewed, so its attachments can be linked (blank if there isn't one)
+    #[serde(default)]
+    pub url: String,
 }
 
 /// Render markdown body
-async fn render_markdown(Json(req): Json<RenderMarkdown>) -> Result<String, ()> {
-    Ok(parse_markdown(req.content.clone()))
+async fn render_markdown<S: PasteStore>(
+    State(database): State<S>,
+    Json(req): Json<RenderMarkdown>,
+) -> Result<String, ()> {
+    let rendered = parse_markdown(req.content.clone());
+
+    if req.url.is_empty() {
+        return Ok(rendered);
+    }
+
+    // the unlock cookie isn't sent here, so attachments of private pastes aren't linked
+    match database.get_paste_by_url(req.url.clone()).await {
+        Ok(p) if p.metadata.view_password.is_empty() => (),
+        _ => return Ok(rendered),
+    }
+
+    let attachments = attachment_names(&database, &req.url).await;
+    Ok(link_attachments(rendered, &req.url, &attachments))
 }
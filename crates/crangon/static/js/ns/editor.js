@@ -72,6 +72,8 @@
                             },
                             body: JSON.stringify({
                                 content: globalThis.editor.getValue(),
+                                // link attachments of the paste being edited
+                                url: preview_tab.getAttribute("data-url") || "",
                             }),
                         })
                     ).text();
@@ -84,6 +84,15 @@
             .addEventListener("submit", async (e) => {
                 e.preventDefault();
 
+                // template variables
+                const values = {};
+
+                for (const input of e.target.querySelectorAll(
+                    "[data-variable]",
+                )) {
+                    values[input.getAttribute("data-variable")] = input.value;
+                }
+
                 const res = await (
                     await fetch("/api/clone", {
                         method: "POST",
@@ -94,6 +103,7 @@
                             url: e.target.url.value,
                             password: e.target.password.value,
                             source: paste_url,
+                            values,
                         }),
                     })
                 ).json();
@@ -111,4 +121,38 @@
                 }
             });
     });
+
+    markdown.define("sync_template", async function (_, paste_url, is_owner) {
+        // the owner doesn't need the edit password
+        const password = is_owner
+            ? ""
+            : prompt("Enter this paste's edit password:");
+
+        if (password === null) {
+            return;
+        }
+
+        const res = await (
+            await fetch(`/api/${paste_url}/sync-template`, {
+                method: "POST",
+                headers: {
+                    "Content-Type": "application/json",
+                },
+                body: JSON.stringify({
+                    password,
+                }),
+            })
+        ).json();
+
+        if (res.success === false) {
+            // conflicts are reported instead of overwriting our changes
+            trigger("crangon:gen_secret", [
+                "note-error",
+                "Error",
+                res.message,
+            ]);
+        } else {
+            window.location.reload();
+        }
+    });
 })();
@@ -28,6 +28,70 @@
                     continue;
                 }
 
+                // managed by the server
+                if (
+                    field[0] === "orphaned" ||
+                    field[0] === "template_revision" ||
+                    field[0] === "template_values" ||
+                    field[0] === "pending_owner"
+                ) {
+                    continue;
+                }
+
+                // tags are edited as a comma separated list (the server normalizes them)
+                if (field[0] === "tags") {
+                    globalThis.update_metadata_tags = (value) => {
+                        $.metadata.tags = value
+                            .split(",")
+                            .map((tag) => tag.trim())
+                            .filter((tag) => tag);
+                    };
+
+                    bind_to.innerHTML += `<div class="card secondary round flex justify-between items-center gap-2" style="flex-wrap: wrap;" id="field:${field[0]}">
+                        <label for="field_input:${field[0]}">${field[0]}</label>
+                        <input 
+                          id="field_input:${field[0]}" 
+                          type="text" 
+                          placeholder="rust, notes"
+                          value="${field[1].join(", ").replaceAll('"', "&quot;")}"
+                          onchange="globalThis.update_metadata_tags(event.target.value)"
+                          style="width: max-content"
+                        />
+                    </div>`;
+
+                    continue;
+                }
+
+                // variables are only used by templates, and are edited as json
+                if (field[0] === "variables") {
+                    if ($.metadata.template !== "@") {
+                        continue;
+                    }
+
+                    globalThis.update_metadata_variables = (value) => {
+                        try {
+                            $.metadata.variables = JSON.parse(value || "[]");
+                        } catch {
+                            alert(
+                                'Variables must be a JSON list like [{"name": "service", "default": "", "required": true}]',
+                            );
+                        }
+                    };
+
+                    bind_to.innerHTML += `<div class="card secondary round flex justify-between items-center gap-2" style="flex-wrap: wrap;" id="field:${field[0]}">
+                        <label for="field_input:${field[0]}">${field[0]}</label>
+                        <input 
+                          id="field_input:${field[0]}" 
+                          type="text" 
+                          value="${JSON.stringify(field[1]).replaceAll('"', "&quot;")}"
+                          onchange="globalThis.update_metadata_variables(event.target.value)"
+                          style="width: max-content"
+                        />
+                    </div>`;
+
+                    continue;
+                }
+
                 if (field[0] === "template") {
                     const paste_is_template = field[1] === "@";
                     const paste_source =
@@ -61,6 +125,11 @@
                             <label for="field_input:${field[0]}">${field[0]}</label>
                             <button class=\"theme:primary round\" onclick=\"globalThis.mark_as_not_template()\" type=\"button\">Unmark as Template</button>
                         </div>`;
+                    } else if (paste_source && $.metadata.orphaned) {
+                        bind_to.innerHTML += `<div class="card secondary round flex justify-between items-center gap-2" style="flex-wrap: wrap;" id="field:${field[0]}">
+                            <label for="field_input:${field[0]}">${field[0]}</label>
+                            <span title=\"${paste_source}\">Source Deleted</span>
+                        </div>`;
                     } else if (paste_source) {
                         bind_to.innerHTML += `<div class="card secondary round flex justify-between items-center gap-2" style="flex-wrap: wrap;" id="field:${field[0]}">
                             <label for="field_input:${field[0]}">${field[0]}</label>
@@ -75,7 +144,7 @@
                     <label for="field_input:${field[0]}">${field[0]}</label>
                     <input 
                       id="field_input:${field[0]}" 
-                      type="text" 
+                      type="${field[0] === "view_password" ? "password" : "text"}" 
                       value="${field[1].replace('"', '\\"')}"
                       onchange="globalThis.update_metadata_value('${field[0]}', event.target.value)"
                       style="width: max-content"
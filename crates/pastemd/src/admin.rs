@@ -0,0 +1,178 @@
+//! Instance operator tools
+//!
+//! Nothing here checks passwords, the same way the `ManagePastes` permission skips them.
+//! These are meant for the command line of the instance operator, not for API routes.
+use crate::database::{Database, Result};
+use crate::model::{Paste, PasteError};
+use crate::query::Query;
+
+use dorsal::utility;
+use dorsal::query as sqlquery;
+
+impl Database {
+    /// Find pastes whose url contains `query` or which are owned by `query` (newest first)
+    ///
+    /// # Arguments
+    /// * `query` - part of a url or an owner username (blank to list every paste)
+    /// * `limit` - the maximum number of pastes
+    /// * `offset` - the number of pastes to skip
+    pub async fn find_pastes(&self, query: String, limit: i64, offset: i64) -> Result<Vec<Paste>> {
+        let t = &self.options.table_pastes;
+        let d = self.dialect();
+
+        // `!` escapes LIKE wildcards the same way in every dialect
+        let pattern = format!(
+            "%{}%",
+            query
+                .to_lowercase()
+                .replace("!", "!!")
+                .replace("%", "!%")
+                .replace("_", "!_")
+        );
+
+        let sql = self
+            .select_pastes()
+            .filter_raw(&format!(
+                "{} LIKE ? ESCAPE '!' OR {} = ?",
+                d.ident(&t.url),
+                d.ident(&t.owner)
+            ))
+            .order_by(&t.date_published, true)
+            .limit()
+            .offset()
+            .build();
+
+        let c = &self.base.db.client;
+        let rows = match sqlquery(&sql)
+            .bind::<&String>(&pattern)
+            .bind::<&String>(&query)
+            .bind::<i64>(limit)
+            .bind::<i64>(offset)
+            .fetch_all(c)
+            .await
+        {
+            Ok(r) => r,
+            Err(_) => return Err(PasteError::Other),
+        };
+
+        let mut pastes: Vec<Paste> = Vec::new();
+
+        for row in rows {
+            match self.paste_from_row(self.base.textify_row(row).data) {
+                Ok(p) => pastes.push(p),
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(pastes)
+    }
+
+    /// Move an existing paste to `new_url` without checking its password
+    pub async fn force_rename_paste_by_url(&self, url: String, new_url: String) -> Result<()> {
+        let existing = match self.get_paste_including_trash(url).await {
+            Ok(p) => p,
+            Err(e) => return Err(e),
+        };
+
+        if new_url.is_empty() {
+            return Err(PasteError::ValueError);
+        }
+
+        let (password, content) = (existing.password.clone(), existing.content.clone());
+        self.update_paste(
+            existing,
+            password,
+            String::new(),
+            content,
+            new_url.to_lowercase(),
+            String::new(),
+        )
+        .await
+    }
+
+    /// Replace the edit password of an existing paste with a random one without checking the old one
+    ///
+    /// # Returns
+    /// * Result containing the new (unhashed) edit password
+    pub async fn force_reset_password_by_url(&self, url: String) -> Result<String> {
+        let existing = match self.get_paste_including_trash(url).await {
+            Ok(p) => p,
+            Err(e) => return Err(e),
+        };
+
+        let new_password: String = utility::random_id().chars().take(10).collect();
+
+        let (password, content) = (existing.password.clone(), existing.content.clone());
+        match self
+            .update_paste(
+                existing,
+                password,
+                String::new(),
+                content,
+                String::new(),
+                new_password.clone(),
+            )
+            .await
+        {
+            Ok(_) => Ok(new_password),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Give an existing paste to `owner` (blank to remove its owner) without checking its password
+    pub async fn force_transfer_paste_by_url(&self, url: String, owner: String) -> Result<()> {
+        let existing = match self.get_paste_including_trash(url).await {
+            Ok(p) => p,
+            Err(e) => return Err(e),
+        };
+
+        let mut metadata = existing.metadata.clone();
+        metadata.owner = owner;
+        metadata.pending_owner = String::new();
+
+        self.update_paste_metadata(existing, metadata).await
+    }
+
+    /// Remove everything cached for the paste at `url`
+    ///
+    /// The cached view count is written to the database first, so no views are lost.
+    pub async fn purge_cache_by_url(&self, mut url: String) -> Result<()> {
+        url = idna::punycode::encode_str(&url).unwrap().to_lowercase();
+
+        if url.ends_with("-") {
+            url.pop();
+        }
+
+        // write the cached view count (the server flushing it doesn't help, since we're about
+        // to remove it from the cache)
+        let views_key = format!("{}:{}", self.options.table_views.prefix, url);
+
+        if let Some(views) = self.base.cachedb.get(views_key).await {
+            let t = &self.options.table_pastes;
+            let query = Query::update(self.dialect(), &t.table_name)
+                .column(&t.views)
+                .filter(&t.url, "=")
+                .build();
+
+            if sqlquery(&query)
+                .bind::<i32>(views.parse::<i32>().unwrap_or(0))
+                .bind::<&String>(&url)
+                .execute(&self.base.db.client)
+                .await
+                .is_err()
+            {
+                return Err(PasteError::Other);
+            }
+        }
+
+        for key in [
+            format!("{}:{}", self.options.table_pastes.prefix, url),
+            format!("{}:{}", self.options.table_views.prefix, url),
+            format!("{}:lockout:{}", self.options.rate_limits.prefix, url),
+        ] {
+            self.base.cachedb.remove(key).await;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,95 @@
+//! Attachment file storage
+//!
+//! The contents of attachments are kept out of the database, in a [`BlobStore`]. Blobs are
+//! keyed by the id of the attachment they belong to, so renaming a paste never moves them.
+use crate::database::Result;
+use crate::model::PasteError;
+
+use async_trait::async_trait;
+use std::path::PathBuf;
+
+/// Storage for the contents of attachments
+#[async_trait]
+pub trait BlobStore: Send + Sync {
+    /// Store `data` under `key`, replacing anything already stored there
+    async fn put(&self, key: &str, data: &[u8]) -> Result<()>;
+
+    /// Get the data stored under `key`
+    async fn get(&self, key: &str) -> Result<Vec<u8>>;
+
+    /// Remove the data stored under `key` (removing a missing key is not an error)
+    async fn delete(&self, key: &str) -> Result<()>;
+}
+
+/// Blob store which keeps every blob as a file in a local directory
+#[derive(Clone, Debug)]
+pub struct LocalBlobStore {
+    /// The directory blobs are stored in (created on the first write)
+    pub root: PathBuf,
+}
+
+impl LocalBlobStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Get the path of the file `key` is stored in
+    ///
+    /// Keys can only contain ASCII letters, digits, `-` and `_`, so they can never point
+    /// outside of [`LocalBlobStore::root`].
+    fn path(&self, key: &str) -> Result<PathBuf> {
+        if key.is_empty()
+            | !key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() | (c == '-') | (c == '_'))
+        {
+            return Err(PasteError::ValueError);
+        }
+
+        Ok(self.root.join(key))
+    }
+}
+
+#[async_trait]
+impl BlobStore for LocalBlobStore {
+    async fn put(&self, key: &str, data: &[u8]) -> Result<()> {
+        let path = match self.path(key) {
+            Ok(p) => p,
+            Err(e) => return Err(e),
+        };
+
+        if tokio::fs::create_dir_all(&self.root).await.is_err() {
+            return Err(PasteError::Other);
+        }
+
+        match tokio::fs::write(path, data).await {
+            Ok(_) => Ok(()),
+            Err(_) => Err(PasteError::Other),
+        }
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>> {
+        let path = match self.path(key) {
+            Ok(p) => p,
+            Err(e) => return Err(e),
+        };
+
+        match tokio::fs::read(path).await {
+            Ok(d) => Ok(d),
+            Err(_) => Err(PasteError::NotFound),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        let path = match self.path(key) {
+            Ok(p) => p,
+            Err(e) => return Err(e),
+        };
+
+        match tokio::fs::remove_file(path).await {
+            Ok(_) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(_) => Err(PasteError::Other),
+        }
+    }
+}
@@ -1,18 +1,50 @@
-use crate::model::{PasteCreate, PasteClone, PasteError, Paste, PasteMetadata};
+use crate::model::{
+    PasteCreate, PasteClone, PasteError, Paste, PasteMetadata, PasteRevision, PasteSearchResult,
+    PasteList, PasteSort, PasteSummary, PasteStats, PasteStatsDay, PasteStatsReferrer,
+    PasteAttachment, PasteForks, TemplateSync, PasteTagged, TagCount, Collection, CollectionCreate,
+    ImportConflict, ImportSummary,
+};
+
+use crate::blob::{BlobStore, LocalBlobStore};
+use crate::migrations::MigrationError;
+use crate::password::{self, Verification};
+use crate::query::{Dialect, Query};
+use crate::store::{
+    PasteStore, can_transfer, check_collection, check_variables, fill_variables,
+    hash_view_password, keep_ownership, keep_template_fields, next_lockout, next_window,
+    normalize_tag, normalize_tags, owner_of_new_paste, parse_pair, referrer_host,
+    resolve_variables, sync_template,
+};
 
 use dorsal::utility;
 use dorsal::query as sqlquery;
 use dorsal::db::special::auth_db::{FullUser, UserMetadata};
 
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, Write};
+use std::sync::{Arc, Mutex};
+
 pub type Result<T> = std::result::Result<T, PasteError>;
 
+/// Convert a failed write into a [`PasteError`], reporting unique constraint violations
+/// as [`PasteError::AlreadyExists`]
+fn write_error(e: sqlx::Error) -> PasteError {
+    match e {
+        sqlx::Error::Database(ref err) if err.is_unique_violation() => PasteError::AlreadyExists,
+        _ => PasteError::Other,
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum ViewMode {
     /// Only authenticated users can count as a paste view and only once
     AuthenticatedOnce,
     /// Anybody can count as a paste view multiple times;
-    /// views are only stored in redis when using this mode
+    /// views are counted in redis and periodically flushed to the pastes table
     OpenMultiple,
+    /// Like [`ViewMode::OpenMultiple`], but each anonymous viewer (identified by a salted hash
+    /// of their IP and user agent) only counts once every `window_secs` seconds
+    OpenDeduplicated { window_secs: u64 },
 }
 
 #[derive(Clone, Debug)]
@@ -36,6 +68,18 @@ pub struct PastesTableConfig {
     pub date_edited: String,
     /// Mapping for the `metadata` column
     pub metadata: String,
+    /// Mapping for the `expires_at` column
+    pub expires_at: String,
+    /// Mapping for the `max_views` column
+    pub max_views: String,
+    /// Mapping for the `owner` column
+    pub owner: String,
+    /// Mapping for the `views` column
+    pub views: String,
+    /// Mapping for the `trashed_at` column
+    pub trashed_at: String,
+    /// Mapping for the `template` column (the template a paste is derived from)
+    pub template: String,
 }
 
 impl Default for PastesTableConfig {
@@ -51,6 +95,12 @@ impl Default for PastesTableConfig {
             date_published: "date_published".to_string(),
             date_edited: "date_edited".to_string(),
             metadata: "metadata".to_string(),
+            expires_at: "expires_at".to_string(),
+            max_views: "max_views".to_string(),
+            owner: "owner".to_string(),
+            views: "views".to_string(),
+            trashed_at: "trashed_at".to_string(),
+            template: "template".to_string(),
         }
     }
 }
@@ -72,6 +122,340 @@ impl Default for ViewsTableConfig {
     }
 }
 
+#[derive(Clone, Debug)]
+pub struct RevisionsTableConfig {
+    /// The name of the table
+    pub table_name: String,
+    /// The caching prefix associated with the table
+    pub prefix: String,
+    // columns
+    /// Mapping for the `id` column
+    pub id: String,
+    /// Mapping for the `url` column
+    pub url: String,
+    /// Mapping for the `content` column
+    pub content: String,
+    /// Mapping for the `actor` column
+    pub actor: String,
+    /// Mapping for the `timestamp` column
+    pub timestamp: String,
+}
+
+impl Default for RevisionsTableConfig {
+    fn default() -> Self {
+        Self {
+            table_name: "revisions".to_string(),
+            prefix: "revisions".to_string(),
+            // columns
+            id: "id".to_string(),
+            url: "url".to_string(),
+            content: "content".to_string(),
+            actor: "actor".to_string(),
+            timestamp: "timestamp".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SearchTableConfig {
+    /// The name of the table
+    pub table_name: String,
+    /// The caching prefix associated with the table
+    pub prefix: String,
+}
+
+impl Default for SearchTableConfig {
+    fn default() -> Self {
+        Self {
+            table_name: "search".to_string(),
+            prefix: "search".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct StatsTableConfig {
+    /// The name of the table
+    pub table_name: String,
+    /// The caching prefix associated with the table
+    pub prefix: String,
+}
+
+impl Default for StatsTableConfig {
+    fn default() -> Self {
+        Self {
+            table_name: "view_stats".to_string(),
+            prefix: "view_stats".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AttachmentsTableConfig {
+    /// The name of the table
+    pub table_name: String,
+    /// The caching prefix associated with the table
+    pub prefix: String,
+}
+
+impl Default for AttachmentsTableConfig {
+    fn default() -> Self {
+        Self {
+            table_name: "attachments".to_string(),
+            prefix: "attachments".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TagsTableConfig {
+    /// The name of the table
+    pub table_name: String,
+    /// The caching prefix associated with the table
+    pub prefix: String,
+}
+
+impl Default for TagsTableConfig {
+    fn default() -> Self {
+        Self {
+            table_name: "tags".to_string(),
+            prefix: "tags".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CollectionsTableConfig {
+    /// The name of the table
+    pub table_name: String,
+    /// The name of the table which stores the pastes of each collection
+    pub members_table_name: String,
+    /// The caching prefix associated with the table
+    pub prefix: String,
+}
+
+impl Default for CollectionsTableConfig {
+    fn default() -> Self {
+        Self {
+            table_name: "collections".to_string(),
+            members_table_name: "collection_pastes".to_string(),
+            prefix: "collections".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MigrationsTableConfig {
+    /// The name of the table
+    pub table_name: String,
+    /// The caching prefix associated with the table
+    pub prefix: String,
+}
+
+impl Default for MigrationsTableConfig {
+    fn default() -> Self {
+        Self {
+            table_name: "migrations".to_string(),
+            prefix: "migrations".to_string(),
+        }
+    }
+}
+
+/// A fixed-window request limit
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimit {
+    /// The number of requests allowed in every window (`0` = unlimited)
+    pub requests: u32,
+    /// The length (in seconds) of a window
+    pub window_secs: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct RateLimitConfig {
+    /// The caching prefix associated with rate limits and lockouts
+    pub prefix: String,
+    /// Limit for creating pastes (`/api/new`, `/api/clone`)
+    pub create: RateLimit,
+    /// Limit for routes which change a paste or check its password
+    pub write: RateLimit,
+    /// Limit for every other route
+    pub read: RateLimit,
+    /// The number of incorrect passwords a paste accepts before it is locked (`0` = never)
+    pub lockout_threshold: u32,
+    /// How long (in seconds) the first lockout lasts, doubled with every failure after it
+    pub lockout_secs: u64,
+    /// The longest (in seconds) a lockout can last
+    pub lockout_max_secs: u64,
+    /// The number of reverse proxies in front of the server which append the address they
+    /// were connected from to `X-Forwarded-For` (`0` = ignore the header)
+    ///
+    /// Clients can send their own `X-Forwarded-For`, so only the entries our proxies added
+    /// are trusted.
+    pub trusted_proxies: usize,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            prefix: "rate_limit".to_string(),
+            create: RateLimit {
+                requests: 10,
+                window_secs: 60,
+            },
+            write: RateLimit {
+                requests: 30,
+                window_secs: 60,
+            },
+            read: RateLimit {
+                requests: 300,
+                window_secs: 60,
+            },
+            lockout_threshold: 5,
+            lockout_secs: 30,
+            lockout_max_secs: 60 * 60,
+            trusted_proxies: 0,
+        }
+    }
+}
+
+/// The rules paste content and urls are checked against
+#[derive(Clone, Debug)]
+pub struct ValidationPolicy {
+    /// The smallest paste content (in bytes)
+    pub min_content_size: usize,
+    /// The largest paste content (in bytes)
+    pub max_content_size: usize,
+    /// The shortest paste url (in bytes, after punycode encoding)
+    pub min_url_length: usize,
+    /// The longest paste url (in bytes, after punycode encoding)
+    pub max_url_length: usize,
+    /// The regex every paste url must match
+    pub url_pattern: String,
+    /// Urls which can't be claimed by a paste (route names, checked case-insensitively)
+    pub reserved_urls: Vec<String>,
+}
+
+impl Default for ValidationPolicy {
+    fn default() -> Self {
+        Self {
+            min_content_size: 1,
+            max_content_size: 200_000,
+            min_url_length: 3,
+            max_url_length: 250,
+            url_pattern: "^[\\w\\_\\-\\.\\!\\p{Extended_Pictographic}]+$".to_string(),
+            reserved_urls: vec![
+                "api".to_string(),
+                "static".to_string(),
+                "search".to_string(),
+                "dashboard".to_string(),
+                "collections".to_string(),
+                "tags".to_string(),
+            ],
+        }
+    }
+}
+
+impl ValidationPolicy {
+    /// Check the length and characters of a (normalized) paste url
+    pub fn check_url(&self, url: &str) -> Result<()> {
+        if (url.len() > self.max_url_length) | (url.len() < self.min_url_length) {
+            return Err(PasteError::ValueError);
+        }
+
+        if self.reserved_urls.iter().any(|r| r.to_lowercase() == url) {
+            return Err(PasteError::ValueError);
+        }
+
+        // (characters used)
+        let regex = match regex::RegexBuilder::new(&self.url_pattern)
+            .multi_line(true)
+            .build()
+        {
+            Ok(r) => r,
+            Err(_) => return Err(PasteError::Other),
+        };
+
+        if regex.captures(url).iter().len() < 1 {
+            return Err(PasteError::ValueError);
+        }
+
+        Ok(())
+    }
+
+    /// Check the size of paste content
+    pub fn check_content(&self, content: &str) -> Result<()> {
+        if (content.len() > self.max_content_size) | (content.len() < self.min_content_size) {
+            return Err(PasteError::ValueError);
+        }
+
+        Ok(())
+    }
+}
+
+/// Attachment storage and limits
+#[derive(Clone, Debug)]
+pub struct AttachmentConfig {
+    /// The directory attachments are stored in by the default [`LocalBlobStore`]
+    pub directory: String,
+    /// The largest attachment (in bytes)
+    pub max_size: usize,
+    /// The most attachments a single paste can have (`0` = attachments are disabled)
+    pub max_count: usize,
+    /// The MIME types attachments can have (`type/*` allows every subtype of `type`)
+    pub allowed_types: Vec<String>,
+}
+
+impl Default for AttachmentConfig {
+    fn default() -> Self {
+        Self {
+            directory: "./attachments".to_string(),
+            max_size: 5 * 1024 * 1024,
+            max_count: 20,
+            allowed_types: vec![
+                "image/png".to_string(),
+                "image/jpeg".to_string(),
+                "image/gif".to_string(),
+                "image/webp".to_string(),
+                "text/plain".to_string(),
+            ],
+        }
+    }
+}
+
+impl AttachmentConfig {
+    /// Check the name, MIME type and size of a new attachment
+    pub fn check(&self, name: &str, content_type: &str, size: usize) -> Result<()> {
+        // names end up in urls and markdown links, so they're kept simple
+        if name.is_empty()
+            | (name.len() > 100)
+            | name.starts_with(".")
+            | !name
+                .chars()
+                .all(|c| c.is_alphanumeric() | (c == '-') | (c == '_') | (c == '.'))
+        {
+            return Err(PasteError::ValueError);
+        }
+
+        if (size == 0) | (size > self.max_size) {
+            return Err(PasteError::ValueError);
+        }
+
+        let allowed = self
+            .allowed_types
+            .iter()
+            .any(|t| match t.strip_suffix("/*") {
+                Some(prefix) => content_type.starts_with(&format!("{prefix}/")),
+                None => t == content_type,
+            });
+
+        if !allowed {
+            return Err(PasteError::ValueError);
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct ServerOptions {
     /// If pastes can require a password to be viewed
@@ -86,6 +470,38 @@ pub struct ServerOptions {
     pub table_pastes: PastesTableConfig,
     /// Views table config
     pub table_views: ViewsTableConfig,
+    /// Revisions table config
+    pub table_revisions: RevisionsTableConfig,
+    /// Search index table config
+    pub table_search: SearchTableConfig,
+    /// View analytics table config
+    pub table_stats: StatsTableConfig,
+    /// Attachments table config
+    pub table_attachments: AttachmentsTableConfig,
+    /// Tags table config
+    pub table_tags: TagsTableConfig,
+    /// Collections table config
+    pub table_collections: CollectionsTableConfig,
+    /// Migrations table config
+    pub table_migrations: MigrationsTableConfig,
+    /// How often (in seconds) expired pastes are purged by the background reaper (`0` = never)
+    pub reaper_interval: u64,
+    /// How often (in seconds) cached view counts are flushed to the database (`0` = never)
+    pub view_flush_interval: u64,
+    /// The salt mixed into viewer keys for [`ViewMode::OpenDeduplicated`]
+    pub view_salt: String,
+    /// The secret view password unlock tokens are signed with
+    pub unlock_secret: String,
+    /// How long (in seconds) a paste stays unlocked after entering its view password
+    pub unlock_ttl: u64,
+    /// Rate limit and password lockout config
+    pub rate_limits: RateLimitConfig,
+    /// How long (in seconds) deleted pastes stay in the trash before they're purged (`0` = delete right away)
+    pub trash_retention: u64,
+    /// Content and url validation rules
+    pub validation: ValidationPolicy,
+    /// Attachment storage and limits
+    pub attachments: AttachmentConfig,
 }
 
 impl ServerOptions {
@@ -98,6 +514,22 @@ impl ServerOptions {
             view_mode: ViewMode::OpenMultiple,
             table_pastes: PastesTableConfig::default(),
             table_views: ViewsTableConfig::default(),
+            table_revisions: RevisionsTableConfig::default(),
+            table_search: SearchTableConfig::default(),
+            table_stats: StatsTableConfig::default(),
+            table_attachments: AttachmentsTableConfig::default(),
+            table_tags: TagsTableConfig::default(),
+            table_collections: CollectionsTableConfig::default(),
+            table_migrations: MigrationsTableConfig::default(),
+            reaper_interval: 60,
+            view_flush_interval: 30,
+            view_salt: utility::random_id(),
+            unlock_secret: utility::random_id(),
+            unlock_ttl: 60 * 60,
+            rate_limits: RateLimitConfig::default(),
+            trash_retention: 60 * 60 * 24 * 7,
+            validation: ValidationPolicy::default(),
+            attachments: AttachmentConfig::default(),
         }
     }
 }
@@ -111,6 +543,22 @@ impl Default for ServerOptions {
             view_mode: ViewMode::OpenMultiple,
             table_pastes: PastesTableConfig::default(),
             table_views: ViewsTableConfig::default(),
+            table_revisions: RevisionsTableConfig::default(),
+            table_search: SearchTableConfig::default(),
+            table_stats: StatsTableConfig::default(),
+            table_attachments: AttachmentsTableConfig::default(),
+            table_tags: TagsTableConfig::default(),
+            table_collections: CollectionsTableConfig::default(),
+            table_migrations: MigrationsTableConfig::default(),
+            reaper_interval: 60,
+            view_flush_interval: 30,
+            view_salt: utility::random_id(),
+            unlock_secret: utility::random_id(),
+            unlock_ttl: 60 * 60,
+            rate_limits: RateLimitConfig::default(),
+            trash_retention: 60 * 60 * 24 * 7,
+            validation: ValidationPolicy::default(),
+            attachments: AttachmentConfig::default(),
         }
     }
 }
@@ -121,12 +569,48 @@ pub struct Database {
     pub base: dorsal::StarterDatabase,
     pub auth: dorsal::AuthDatabase,
     pub options: ServerOptions,
+    /// Where the contents of attachments are stored
+    pub blobs: Arc<dyn BlobStore>,
+    /// Urls whose cached view count changed since the last flush
+    unflushed_views: Arc<Mutex<HashSet<String>>>,
+    /// Seen keys written for [`ViewMode::OpenDeduplicated`] by when their window ends
+    seen_windows: Arc<Mutex<HashMap<String, u128>>>,
 }
 
 impl Database {
     pub async fn new(opts: dorsal::DatabaseOpts, opts1: ServerOptions) -> Self {
-        let base = dorsal::StarterDatabase::new(opts).await;
+        Self::from_base(dorsal::StarterDatabase::new(opts).await, opts1).await
+    }
+
+    /// Create a database backed by a private in-memory sqlite database
+    #[cfg(all(test, feature = "sqlite"))]
+    pub(crate) async fn in_memory(options: ServerOptions) -> Self {
+        // every connection would get its own in-memory database
+        let client = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+
+        let base = dorsal::StarterDatabase {
+            db: dorsal::db::sql::Database {
+                client,
+                _type: "sqlite".to_string(),
+            },
+            options: dorsal::DatabaseOpts {
+                _type: Some("sqlite".to_string()),
+                host: None,
+                user: String::new(),
+                pass: String::new(),
+                name: String::new(),
+            },
+            cachedb: dorsal::CacheDB::new().await,
+        };
+
+        Self::from_base(base, options).await
+    }
 
+    async fn from_base(base: dorsal::StarterDatabase, opts1: ServerOptions) -> Self {
         Self {
             base: base.clone(),
             auth: dorsal::AuthDatabase::new(
@@ -134,60 +618,46 @@ impl Database {
                 dorsal::db::special::auth_db::DatabaseOptions::default(),
             )
             .await,
+            blobs: Arc::new(LocalBlobStore::new(&opts1.attachments.directory)),
             options: opts1,
+            unflushed_views: Arc::new(Mutex::new(HashSet::new())),
+            seen_windows: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
-    /// Init database
-    pub async fn init(&self) {
-        // create tables
-        let c = &self.base.db.client;
-
-        let _ = sqlquery(&format!(
-            "CREATE TABLE IF NOT EXISTS \"{}\" (
-                 {} TEXT,
-                 {} TEXT,
-                 {} TEXT,
-                 {} TEXT,
-                 {} TEXT,
-                 {} TEXT,
-                 {} TEXT
-            )",
-            // table
-            self.options.table_pastes.table_name,
-            // columns
-            self.options.table_pastes.url,
-            self.options.table_pastes.id,
-            self.options.table_pastes.password,
-            self.options.table_pastes.date_published,
-            self.options.table_pastes.date_edited,
-            self.options.table_pastes.content,
-            self.options.table_pastes.metadata
-        ))
-        .execute(c)
-        .await;
+    /// Store the contents of attachments in `blobs` instead of the local directory
+    /// from [`AttachmentConfig::directory`]
+    pub fn with_blob_store(mut self, blobs: Arc<dyn BlobStore>) -> Self {
+        self.blobs = blobs;
+        self
+    }
 
-        if self.options.view_mode == ViewMode::AuthenticatedOnce {
-            // create table to track views
-            let _ = sqlquery(&format!(
-                "CREATE TABLE IF NOT EXISTS \"{}\" (
-                    url      TEXT,
-                    username TEXT
-                )",
-                self.options.table_views.table_name
-            ))
-            .execute(c)
-            .await;
-        }
+    /// Init database
+    ///
+    /// Applies all pending [migrations](crate::migrations), refusing to continue if the
+    /// database schema is newer than this version of pastemd supports.
+    ///
+    /// # Returns
+    /// * Result containing the migration versions which were applied
+    pub async fn init(&self) -> std::result::Result<Vec<i64>, MigrationError> {
+        self.migrate().await
     }
 
     // ...
 
-    /// Get an existing paste by `url`
+    /// Get an existing paste by `url` (pastes in the trash are not found)
     ///
     /// # Arguments
     /// * `url` - [`String`] of the paste's `url` field
-    pub async fn get_paste_by_url(&self, mut url: String) -> Result<Paste> {
+    pub async fn get_paste_by_url(&self, url: String) -> Result<Paste> {
+        match self.get_paste_including_trash(url).await {
+            Ok(p) if p.is_trashed() => Err(PasteError::NotFound),
+            r => r,
+        }
+    }
+
+    /// Get an existing paste by `url`, even if it is in the trash
+    pub async fn get_paste_including_trash(&self, mut url: String) -> Result<Paste> {
         url = idna::punycode::encode_str(&url).unwrap().to_lowercase();
 
         if url.ends_with("-") {
@@ -195,25 +665,27 @@ impl Database {
         }
 
         // check in cache
-        match self
+        if let Some(c) = self
             .base
             .cachedb
             .get(format!("{}:{}", self.options.table_pastes.prefix, url))
             .await
         {
-            Some(c) => return Ok(serde_json::from_str::<Paste>(c.as_str()).unwrap()),
-            None => (),
+            let paste = serde_json::from_str::<Paste>(c.as_str()).unwrap();
+
+            if paste.is_expired() {
+                let _ = self.force_delete_paste_by_url(url).await;
+                return Err(PasteError::NotFound);
+            }
+
+            return Ok(paste);
         };
 
         // pull from database
-        let query: String = if (self.base.db._type == "sqlite") | (self.base.db._type == "mysql") {
-            "SELECT * FROM \":t\" WHERE \":url\" = ?"
-        } else {
-            "SELECT * FROM \":t\" WHERE \":url\" = $1"
-        }
-        .to_string()
-        .replace(":t", &self.options.table_pastes.table_name)
-        .replace(":url", &self.options.table_pastes.url);
+        let query = self
+            .select_pastes()
+            .filter(&self.options.table_pastes.url, "=")
+            .build();
 
         let c = &self.base.db.client;
         let res = match sqlquery(&query)
@@ -225,8 +697,54 @@ impl Database {
             Err(_) => return Err(PasteError::NotFound),
         };
 
+        let paste = match self.paste_from_row(res) {
+            Ok(p) => p,
+            Err(e) => return Err(e),
+        };
+
+        // check expiry
+        if paste.is_expired() {
+            let _ = self.force_delete_paste_by_url(url).await;
+            return Err(PasteError::NotFound);
+        }
+
+        // store in cache
+        self.base
+            .cachedb
+            .set(
+                format!("{}:{}", self.options.table_pastes.prefix, url),
+                serde_json::to_string::<Paste>(&paste).unwrap(),
+            )
+            .await;
+
         // return
-        let paste = Paste {
+        Ok(paste)
+    }
+
+    /// The SQL dialect of the database
+    pub(crate) fn dialect(&self) -> Dialect {
+        Dialect::from_type(&self.base.db._type)
+    }
+
+    /// Select from the pastes table, with every typed column cast to text
+    pub(crate) fn select_pastes(&self) -> Query {
+        let t = &self.options.table_pastes;
+        Query::select(self.dialect(), &t.table_name)
+            .columns(&[&t.url, &t.id, &t.password, &t.content, &t.metadata])
+            .column_as_text(&t.date_published)
+            .column_as_text(&t.date_edited)
+            .column_as_text(&t.expires_at)
+            .column_as_text(&t.max_views)
+            .column_as_text(&t.trashed_at)
+            .column(&t.owner)
+    }
+
+    /// Convert a textified paste row into a [`Paste`]
+    pub(crate) fn paste_from_row(
+        &self,
+        res: std::collections::HashMap<String, String>,
+    ) -> Result<Paste> {
+        Ok(Paste {
             id: res.get(&self.options.table_pastes.id).unwrap().to_string(),
             url: res.get(&self.options.table_pastes.url).unwrap().to_string(),
             password: res
@@ -253,29 +771,34 @@ impl Database {
                 Ok(m) => m,
                 Err(_) => return Err(PasteError::ValueError),
             },
-        };
-
-        // store in cache
-        self.base
-            .cachedb
-            .set(
-                format!("{}:{}", self.options.table_pastes.prefix, url),
-                serde_json::to_string::<Paste>(&paste).unwrap(),
-            )
-            .await;
-
-        // return
-        Ok(paste)
+            expires_at: match res.get(&self.options.table_pastes.expires_at) {
+                Some(v) => v.parse::<u128>().unwrap_or(0),
+                None => 0,
+            },
+            max_views: match res.get(&self.options.table_pastes.max_views) {
+                Some(v) => v.parse::<i32>().unwrap_or(0),
+                None => 0,
+            },
+            trashed_at: match res.get(&self.options.table_pastes.trashed_at) {
+                Some(v) => v.parse::<u128>().unwrap_or(0),
+                None => 0,
+            },
+        })
     }
 
     /// Create a new paste
     ///
     /// # Arguments
     /// * `props` - [`PasteCreate`]
+    /// * `editing_as` - the userstate of the user creating the paste (they become its owner)
     ///
     /// # Returns
     /// * Result containing a tuple with the unhashed edit password and the paste
-    pub async fn create_paste(&self, mut props: PasteCreate) -> Result<(String, Paste)> {
+    pub async fn create_paste(
+        &self,
+        mut props: PasteCreate,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<(String, Paste)> {
         props.url = idna::punycode::encode_str(&props.url)
             .unwrap()
             .to_lowercase();
@@ -284,11 +807,6 @@ impl Database {
             props.url.pop();
         }
 
-        // make sure paste doesn't already exist
-        if let Ok(_) = self.get_paste_by_url(props.url.clone()).await {
-            return Err(PasteError::AlreadyExists);
-        }
-
         // create url if not supplied
         if props.url.is_empty() {
             props.url = utility::random_id().chars().take(10).collect();
@@ -299,22 +817,17 @@ impl Database {
             props.password = utility::random_id().chars().take(10).collect();
         }
 
-        // check lengths
-        if (props.url.len() > 250) | (props.url.len() < 3) {
+        // check values
+        self.options.validation.check_url(&props.url)?;
+
+        self.options.validation.check_content(&props.content)?;
+
+        // check expiry
+        if (props.expires_at != 0) && (props.expires_at <= utility::unix_epoch_timestamp()) {
             return Err(PasteError::ValueError);
         }
 
-        if (props.content.len() > 200_000) | (props.content.len() < 1) {
-            return Err(PasteError::ValueError);
-        }
-
-        // (characters used)
-        let regex = regex::RegexBuilder::new("^[\\w\\_\\-\\.\\!\\p{Extended_Pictographic}]+$")
-            .multi_line(true)
-            .build()
-            .unwrap();
-
-        if regex.captures(&props.url).iter().len() < 1 {
+        if props.max_views < 0 {
             return Err(PasteError::ValueError);
         }
 
@@ -323,49 +836,89 @@ impl Database {
             id: utility::random_id(),
             url: props.url,
             content: props.content,
-            password: utility::hash(props.password.clone()),
+            password: password::hash(&props.password).await,
             date_published: utility::unix_epoch_timestamp(),
             date_edited: utility::unix_epoch_timestamp(),
-            metadata: super::model::PasteMetadata::default(),
+            metadata: super::model::PasteMetadata {
+                owner: owner_of_new_paste(&self.options, &editing_as),
+                ..Default::default()
+            },
+            expires_at: props.expires_at,
+            max_views: props.max_views,
+            trashed_at: 0,
         };
 
         // create paste
-        let query: String = if (self.base.db._type == "sqlite") | (self.base.db._type == "mysql") {
-            "INSERT INTO \":t\" VALUES (?, ?, ?, ?, ?, ?, ?)"
-        } else {
-            "INSERT INTO \":t\" VALEUS ($1, $2, $3, $4, $5, $6, $7)"
+        match self.insert_paste(&paste).await {
+            Ok(_) => Ok((props.password, paste)),
+            Err(e) => Err(e),
         }
-        .to_string()
-        .replace(":t", &self.options.table_pastes.table_name);
+    }
+
+    /// Insert a paste exactly as given and add it to the search index
+    async fn insert_paste(&self, paste: &Paste) -> Result<()> {
+        let t = &self.options.table_pastes;
+        let query = Query::insert(self.dialect(), &t.table_name)
+            .columns(&[
+                &t.url,
+                &t.id,
+                &t.password,
+                &t.date_published,
+                &t.date_edited,
+                &t.content,
+                &t.metadata,
+                &t.expires_at,
+                &t.max_views,
+                &t.owner,
+                &t.trashed_at,
+                &t.template,
+            ])
+            .build();
 
         let c = &self.base.db.client;
         match sqlquery(&query)
             .bind::<&String>(&paste.url)
             .bind::<&String>(&paste.id)
             .bind::<&String>(&paste.password)
-            .bind::<&String>(&paste.date_published.to_string())
-            .bind::<&String>(&paste.date_edited.to_string())
+            .bind::<i64>(paste.date_published as i64)
+            .bind::<i64>(paste.date_edited as i64)
             .bind::<&String>(&paste.content)
             .bind::<&String>(match serde_json::to_string(&paste.metadata) {
                 Ok(ref s) => s,
                 Err(_) => return Err(PasteError::ValueError),
             })
+            .bind::<i64>(paste.expires_at as i64)
+            .bind::<i32>(paste.max_views)
+            .bind::<&String>(&paste.metadata.owner)
+            .bind::<i64>(paste.trashed_at as i64)
+            .bind::<&str>(paste.metadata.template_source())
             .execute(c)
             .await
         {
-            Ok(_) => return Ok((props.password, paste)),
-            Err(_) => return Err(PasteError::Other),
-        };
+            Ok(_) => {
+                // add to search index
+                self.index_paste(paste).await?;
+
+                Ok(())
+            }
+            // the unique url constraint makes sure the paste doesn't already exist
+            Err(e) => Err(write_error(e)),
+        }
     }
 
     /// Use an existing paste as a template
     ///
     /// # Arguments
     /// * `props` - [`PasteClone`]
+    /// * `editing_as` - the userstate of the user creating the paste (they become its owner)
     ///
     /// # Returns
     /// * Result containing a tuple with the unhashed edit password and the paste
-    pub async fn clone_paste(&self, mut props: PasteClone) -> Result<(String, Paste)> {
+    pub async fn clone_paste(
+        &self,
+        mut props: PasteClone,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<(String, Paste)> {
         props.url = idna::punycode::encode_str(&props.url)
             .unwrap()
             .to_lowercase();
@@ -374,11 +927,6 @@ impl Database {
             props.url.pop();
         }
 
-        // make sure paste doesn't already exist
-        if let Ok(_) = self.get_paste_by_url(props.url.clone()).await {
-            return Err(PasteError::AlreadyExists);
-        }
-
         // make sure paste source exists
         let source = match self.get_paste_by_url(props.source).await {
             Ok(p) => p,
@@ -395,68 +943,182 @@ impl Database {
             props.password = utility::random_id().chars().take(10).collect();
         }
 
-        // check lengths
-        if (props.url.len() > 250) | (props.url.len() < 3) {
-            return Err(PasteError::ValueError);
-        }
+        // check values
+        self.options.validation.check_url(&props.url)?;
 
-        // (characters used)
-        let regex = regex::RegexBuilder::new("^[\\w\\_\\-\\.\\!\\p{Extended_Pictographic}]+$")
-            .multi_line(true)
-            .build()
-            .unwrap();
+        // fill in template variables
+        let values = match resolve_variables(&source.metadata.variables, &props.values) {
+            Ok(v) => v,
+            Err(e) => return Err(e),
+        };
 
-        if regex.captures(&props.url).iter().len() < 1 {
-            return Err(PasteError::ValueError);
-        }
+        let content = fill_variables(&source.content, &values);
+
+        self.options.validation.check_content(&content)?;
 
         // ...
         let source_c = source.clone();
         let paste = Paste {
             id: utility::random_id(),
             url: props.url,
-            content: source.content,
-            password: utility::hash(props.password.clone()),
+            content,
+            password: password::hash(&props.password).await,
             date_published: utility::unix_epoch_timestamp(),
             date_edited: utility::unix_epoch_timestamp(),
-            metadata: super::model::PasteMetadata::from(source_c), // use other paste as a template
+            // use other paste as a template
+            metadata: super::model::PasteMetadata {
+                owner: owner_of_new_paste(&self.options, &editing_as),
+                template_values: values,
+                ..super::model::PasteMetadata::from(source_c)
+            },
+            expires_at: 0,
+            max_views: 0,
+            trashed_at: 0,
         };
 
         // create paste
-        let query: String = if (self.base.db._type == "sqlite") | (self.base.db._type == "mysql") {
-            "INSERT INTO \":t\" VALUES (?, ?, ?, ?, ?, ?, ?)"
-        } else {
-            "INSERT INTO \":t\" VALEUS ($1, $2, $3, $4, $5, $6, $7)"
+        match self.insert_paste(&paste).await {
+            Ok(_) => Ok((props.password, paste)),
+            Err(e) => Err(e),
         }
-        .to_string()
-        .replace(":t", &self.options.table_pastes.table_name);
+    }
 
-        let c = &self.base.db.client;
-        match sqlquery(&query)
-            .bind::<&String>(&paste.url)
-            .bind::<&String>(&paste.id)
-            .bind::<&String>(&paste.password)
-            .bind::<&String>(&paste.date_published.to_string())
-            .bind::<&String>(&paste.date_edited.to_string())
-            .bind::<&String>(&paste.content)
-            .bind::<&String>(match serde_json::to_string(&paste.metadata) {
-                Ok(ref s) => s,
-                Err(_) => return Err(PasteError::ValueError),
-            })
-            .execute(c)
-            .await
-        {
-            Ok(_) => return Ok((props.password, paste)),
-            Err(_) => return Err(PasteError::Other),
+    /// Check `password` against the edit password of `paste`
+    ///
+    /// Legacy hashes are replaced with an argon2 hash once the password has been verified.
+    ///
+    /// # Returns
+    /// * Result containing the paste's (possibly upgraded) password hash
+    async fn check_password(&self, paste: &Paste, password: String) -> Result<String> {
+        // check lockout
+        self.check_password_lockout(paste.url.clone()).await?;
+
+        let verification = password::verify(&password, &paste.password).await;
+
+        self.record_password_attempt(paste.url.clone(), verification != Verification::Invalid)
+            .await;
+
+        match verification {
+            Verification::Valid => Ok(paste.password.clone()),
+            Verification::Invalid => Err(PasteError::PasswordIncorrect),
+            Verification::ValidLegacy => {
+                let hash = password::hash(&password).await;
+
+                let t = &self.options.table_pastes;
+                let query = Query::update(self.dialect(), &t.table_name)
+                    .column(&t.password)
+                    .filter(&t.url, "=")
+                    .build();
+
+                let c = &self.base.db.client;
+                if sqlquery(&query)
+                    .bind::<&String>(&hash)
+                    .bind::<&String>(&paste.url)
+                    .execute(c)
+                    .await
+                    .is_err()
+                {
+                    return Err(PasteError::Other);
+                }
+
+                // remove from cache
+                self.base
+                    .cachedb
+                    .remove(format!(
+                        "{}:{}",
+                        self.options.table_pastes.prefix, paste.url
+                    ))
+                    .await;
+
+                Ok(hash)
+            }
+        }
+    }
+
+    /// Count a request from a client (`key`) against `limit`
+    ///
+    /// # Arguments
+    /// * `key` - the route class and client the request is counted for
+    /// * `limit` - the limit of the route class
+    pub async fn hit_rate_limit(&self, key: String, limit: RateLimit) -> Result<()> {
+        if limit.requests == 0 {
+            return Ok(());
+        }
+
+        let key = format!("{}:{}", self.options.rate_limits.prefix, key);
+        let window = match self.base.cachedb.get(key.clone()).await {
+            Some(v) => parse_pair(&v),
+            None => None,
+        };
+
+        let (start, requests) = next_window(window, &limit, utility::unix_epoch_timestamp());
+
+        self.base
+            .cachedb
+            .set(key, format!("{start}:{requests}"))
+            .await;
+
+        if requests > limit.requests {
+            return Err(PasteError::RateLimited);
+        }
+
+        Ok(())
+    }
+
+    /// Check if password attempts on the paste at (normalized) `url` are locked out
+    pub async fn check_password_lockout(&self, url: String) -> Result<()> {
+        let key = format!("{}:lockout:{}", self.options.rate_limits.prefix, url);
+
+        if let Some((until, _)) = match self.base.cachedb.get(key).await {
+            Some(v) => parse_pair(&v),
+            None => None,
+        } {
+            if until > utility::unix_epoch_timestamp() {
+                return Err(PasteError::RateLimited);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Record the result of a password attempt on the paste at (normalized) `url`
+    pub async fn record_password_attempt(&self, url: String, success: bool) {
+        let key = format!("{}:lockout:{}", self.options.rate_limits.prefix, url);
+
+        if success {
+            self.base.cachedb.remove(key).await;
+            return;
+        }
+
+        let state = match self.base.cachedb.get(key.clone()).await {
+            Some(v) => parse_pair(&v),
+            None => None,
         };
+
+        let (until, failures) = next_lockout(
+            state,
+            &self.options.rate_limits,
+            utility::unix_epoch_timestamp(),
+        );
+
+        self.base
+            .cachedb
+            .set(key, format!("{until}:{failures}"))
+            .await;
     }
 
-    /// Delete an existing paste by `url`
+    /// Move an existing paste by `url` to the trash
     ///
     /// # Arguments
     /// * `url` - the paste to delete
     /// * `password` - the paste's edit password
-    pub async fn delete_paste_by_url(&self, mut url: String, password: String) -> Result<()> {
+    /// * `editing_as` - the userstate of the user we're deleting the paste as
+    pub async fn delete_paste_by_url(
+        &self,
+        mut url: String,
+        password: String,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()> {
         url = idna::punycode::encode_str(&url).unwrap().to_lowercase();
 
         if url.ends_with("-") {
@@ -470,8 +1132,104 @@ impl Database {
         };
 
         // check password
-        if utility::hash(password) != existing.password {
-            return Err(PasteError::PasswordIncorrect);
+        self.check_edit_access(&existing, password, editing_as)
+            .await?;
+
+        // move paste to the trash (or delete it right away if the trash is disabled)
+        if self.options.trash_retention == 0 {
+            return self.force_delete_paste_by_url(url).await;
+        }
+
+        self.set_trashed_at(existing, utility::unix_epoch_timestamp())
+            .await
+    }
+
+    /// Restore an existing paste from the trash by `url`
+    ///
+    /// # Arguments
+    /// * `url` - the paste to restore
+    /// * `password` - the paste's edit password
+    /// * `editing_as` - the userstate of the user we're restoring the paste as
+    pub async fn restore_paste_by_url(
+        &self,
+        url: String,
+        password: String,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()> {
+        // get paste
+        let existing = match self.get_paste_including_trash(url).await {
+            Ok(p) => p,
+            Err(err) => return Err(err),
+        };
+
+        // check password
+        self.check_edit_access(&existing, password, editing_as)
+            .await?;
+
+        // restore paste
+        self.force_restore_paste_by_url(existing.url).await
+    }
+
+    /// Restore an existing paste from the trash by `url` without checking who is restoring it
+    pub async fn force_restore_paste_by_url(&self, url: String) -> Result<()> {
+        let existing = match self.get_paste_including_trash(url).await {
+            Ok(p) => p,
+            Err(err) => return Err(err),
+        };
+
+        if !existing.is_trashed() {
+            return Err(PasteError::ValueError);
+        }
+
+        self.set_trashed_at(existing, 0).await
+    }
+
+    /// Move a paste in or out of the trash (`trashed_at` of `0` = restore)
+    async fn set_trashed_at(&self, existing: Paste, trashed_at: u128) -> Result<()> {
+        let t = &self.options.table_pastes;
+        let query = Query::update(self.dialect(), &t.table_name)
+            .column(&t.trashed_at)
+            .filter(&t.url, "=")
+            .build();
+
+        let c = &self.base.db.client;
+        if sqlquery(&query)
+            .bind::<i64>(trashed_at as i64)
+            .bind::<&String>(&existing.url)
+            .execute(c)
+            .await
+            .is_err()
+        {
+            return Err(PasteError::Other);
+        }
+
+        // remove from cache
+        self.base
+            .cachedb
+            .remove(format!("{}:{}", t.prefix, existing.url))
+            .await;
+
+        // update search index
+        if trashed_at != 0 {
+            self.unindex_paste(existing.url).await
+        } else {
+            self.index_paste(&Paste {
+                trashed_at,
+                ..existing
+            })
+            .await
+        }
+    }
+
+    /// Delete an existing paste by `url` without checking its password
+    ///
+    /// # Arguments
+    /// * `url` - the paste to delete
+    pub async fn force_delete_paste_by_url(&self, mut url: String) -> Result<()> {
+        url = idna::punycode::encode_str(&url).unwrap().to_lowercase();
+
+        if url.ends_with("-") {
+            url.pop();
         }
 
         // delete paste view count
@@ -481,14 +1239,9 @@ impl Database {
             .await;
 
         // delete paste
-        let query: String = if (self.base.db._type == "sqlite") | (self.base.db._type == "mysql") {
-            "DELETE FROM \":t\" WHERE \":url\" = ?"
-        } else {
-            "DELETE FROM \":t\" WHERE \":url\" = $1"
-        }
-        .to_string()
-        .replace(":t", &self.options.table_pastes.table_name)
-        .replace(":url", &self.options.table_pastes.url);
+        let query = Query::delete(self.dialect(), &self.options.table_pastes.table_name)
+            .filter(&self.options.table_pastes.url, "=")
+            .build();
 
         let c = &self.base.db.client;
         match sqlquery(&query).bind::<&String>(&url).execute(c).await {
@@ -499,26 +1252,81 @@ impl Database {
                     .remove(format!("{}:{}", self.options.table_pastes.prefix, url))
                     .await;
 
+                // remove from search index
+                self.unindex_paste(url.clone()).await?;
+
+                // delete all revisions
+                let query = Query::delete(self.dialect(), &self.options.table_revisions.table_name)
+                    .filter(&self.options.table_revisions.url, "=")
+                    .build();
+
+                if sqlquery(&query)
+                    .bind::<&String>(&url)
+                    .execute(c)
+                    .await
+                    .is_err()
+                {
+                    return Err(PasteError::Other);
+                };
+
+                // delete all attachments
+                self.delete_attachments_by_url(url.clone()).await?;
+
+                // keep forks, but mark them as orphaned
+                self.update_forks(url.clone(), None).await?;
+
+                // remove from collections
+                let query = Query::delete(
+                    self.dialect(),
+                    &self.options.table_collections.members_table_name,
+                )
+                .filter("url", "=")
+                .build();
+
+                if sqlquery(&query)
+                    .bind::<&String>(&url)
+                    .execute(c)
+                    .await
+                    .is_err()
+                {
+                    return Err(PasteError::Other);
+                };
+
                 if self.options.view_mode == ViewMode::AuthenticatedOnce {
                     // delete all view logs
-                    let query: String =
-                        if (self.base.db._type == "sqlite") | (self.base.db._type == "mysql") {
-                            "DELETE FROM \":t\" WHERE \"url\" = ?"
-                        } else {
-                            "DELETE FROM \":t\" WHERE \"url\" = $1"
-                        }
-                        .replace(":t", &self.options.table_views.table_name);
+                    let query = Query::delete(self.dialect(), &self.options.table_views.table_name)
+                        .filter("url", "=")
+                        .build();
 
-                    if let Err(_) = sqlquery(&query).bind::<&String>(&url).execute(c).await {
+                    if sqlquery(&query)
+                        .bind::<&String>(&url)
+                        .execute(c)
+                        .await
+                        .is_err()
+                    {
                         return Err(PasteError::Other);
                     };
                 }
 
+                // delete view analytics
+                let query = Query::delete(self.dialect(), &self.options.table_stats.table_name)
+                    .filter("url", "=")
+                    .build();
+
+                if sqlquery(&query)
+                    .bind::<&String>(&url)
+                    .execute(c)
+                    .await
+                    .is_err()
+                {
+                    return Err(PasteError::Other);
+                };
+
                 // return
-                return Ok(());
+                Ok(())
             }
-            Err(_) => return Err(PasteError::Other),
-        };
+            Err(_) => Err(PasteError::Other),
+        }
     }
 
     /// Edit an existing paste by `url`
@@ -535,8 +1343,8 @@ impl Database {
         mut url: String,
         password: String,
         new_content: String,
-        mut new_url: String,
-        mut new_password: String,
+        new_url: String,
+        new_password: String,
         editing_as: Option<FullUser<UserMetadata>>,
     ) -> Result<()> {
         url = idna::punycode::encode_str(&url).unwrap().to_lowercase();
@@ -552,62 +1360,168 @@ impl Database {
         };
 
         // check password
-        let mut skip_password_check: bool = false;
+        let actor = match editing_as {
+            Some(ref ua) => ua.user.username.clone(),
+            None => String::new(),
+        };
 
-        if let Some(ua) = editing_as {
-            // check if we're the paste owner
-            if ua.user.username == existing.metadata.owner {
-                skip_password_check = true;
-            }
-            // check if we have the "ManagePastes" permission
-            else if ua.level.permissions.contains(&"ManagePastes".to_string()) {
-                skip_password_check = true;
-            }
+        let current_password = match self
+            .check_edit_access(&existing, password, editing_as)
+            .await
+        {
+            Ok(h) => h,
+            Err(e) => return Err(e),
+        };
+
+        // edit paste
+        self.update_paste(
+            existing,
+            current_password,
+            actor,
+            new_content,
+            new_url,
+            new_password,
+        )
+        .await
+    }
+
+    /// Merge the changes made to the template of an existing paste into the paste by `url`
+    ///
+    /// Nothing is saved if the changes conflict with changes made to the paste, the
+    /// conflicting content (with conflict markers) is returned instead.
+    ///
+    /// # Arguments
+    /// * `url` - the paste to sync
+    /// * `password` - the paste's edit password
+    /// * `editing_as` - the userstate of the user we're syncing the paste as
+    pub async fn sync_template_by_url(
+        &self,
+        mut url: String,
+        password: String,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<TemplateSync> {
+        url = idna::punycode::encode_str(&url).unwrap().to_lowercase();
+
+        if url.ends_with("-") {
+            url.pop();
         }
 
-        if skip_password_check == false {
-            if utility::hash(password) != existing.password {
-                return Err(PasteError::PasswordIncorrect);
-            }
+        // get paste
+        let existing = match self.get_paste_by_url(url.clone()).await {
+            Ok(p) => p,
+            Err(err) => return Err(err),
+        };
+
+        // check password
+        let actor = match editing_as {
+            Some(ref ua) => ua.user.username.clone(),
+            None => String::new(),
+        };
+
+        self.check_edit_access(&existing, password, editing_as)
+            .await?;
+
+        // merge
+        let (template, base) = match self.get_template_base(&existing).await {
+            Ok(t) => t,
+            Err(e) => return Err(e),
+        };
+
+        let sync = sync_template(&existing, &template, &base);
+
+        if sync.conflicted {
+            return Ok(sync);
         }
 
-        // hash new password
-        if !new_password.is_empty() {
-            new_password = utility::hash(new_password);
-        } else {
-            new_password = existing.password;
+        // save merged content
+        if sync.content != existing.content {
+            self.update_paste(
+                existing.clone(),
+                existing.password.clone(),
+                actor,
+                sync.content.clone(),
+                String::new(),
+                String::new(),
+            )
+            .await?
+        }
+
+        // record the template revision we synced with
+        let existing = match self.get_paste_by_url(url).await {
+            Ok(p) => p,
+            Err(err) => return Err(err),
+        };
+
+        let mut metadata = existing.metadata.clone();
+        metadata.template_revision = template.date_edited;
+
+        match self.update_paste_metadata(existing, metadata).await {
+            Ok(_) => Ok(sync),
+            Err(e) => Err(e),
         }
+    }
+
+    /// Apply an edit to `existing` without checking its password
+    ///
+    /// # Arguments
+    /// * `existing` - the paste to edit
+    /// * `current_password` - the paste's current password hash (kept if `new_password` is blank)
+    /// * `actor` - the username of the user making the edit (blank if anonymous)
+    /// * `new_content` - the new content of the paste
+    /// * `new_url` - the new url of the paste (kept if blank)
+    /// * `new_password` - the new (unhashed) password of the paste (kept if blank)
+    pub(crate) async fn update_paste(
+        &self,
+        existing: Paste,
+        current_password: String,
+        actor: String,
+        new_content: String,
+        mut new_url: String,
+        mut new_password: String,
+    ) -> Result<()> {
+        let url = existing.url.clone();
 
         // update new_url
         if new_url.is_empty() {
-            new_url = existing.url;
+            new_url = existing.url.clone();
         }
 
-        new_url = idna::punycode::encode_str(&new_url).unwrap();
+        new_url = idna::punycode::encode_str(&new_url).unwrap().to_lowercase();
 
         if new_url.ends_with("-") {
             new_url.pop();
         }
 
-        // edit paste
-        let query: String = if (self.base.db._type == "sqlite") | (self.base.db._type == "mysql") {
-            "UPDATE \":t\" SET \":content\" = ?, \":password\" = ?, \":url\" = ?, \":date_edited\" = ? WHERE \":url\" = ?"
+        // check values (the url is only checked when it changes, so pastes created under an
+        // older policy can still be edited)
+        if new_url != url {
+            self.options.validation.check_url(&new_url)?
+        }
+
+        self.options.validation.check_content(&new_content)?;
+
+        // hash new password
+        if !new_password.is_empty() {
+            new_password = password::hash(&new_password).await;
         } else {
-            "UPDATE \":t\" SET (\":content\" = $1, \":password\" = $2, \":url\" = $3, \":date_edited\" = $4) WHERE \":url\" = $5"
+            new_password = current_password;
         }
-        .to_string()
-        .replace(":t", &self.options.table_pastes.table_name)
-        .replace(":url", &self.options.table_pastes.url)
-        .replace(":content", &self.options.table_pastes.content)
-        .replace(":password", &self.options.table_pastes.password)
-        .replace(":date_edited", &self.options.table_pastes.date_edited);
+
+        // edit paste (the revision shares the edit's timestamp)
+        let now = utility::unix_epoch_timestamp();
+
+        let t = &self.options.table_pastes;
+        let query = Query::update(self.dialect(), &t.table_name)
+            .columns(&[&t.content, &t.password, &t.url, &t.date_edited])
+            .filter(&t.url, "=")
+            .build();
 
         let c = &self.base.db.client;
         match sqlquery(&query)
             .bind::<&String>(&new_content)
             .bind::<&String>(&new_password)
             .bind::<&String>(&new_url)
-            .bind::<&String>(&utility::unix_epoch_timestamp().to_string())
+            .bind::<i64>(now as i64)
             .bind::<&String>(&url)
             .execute(c)
             .await
@@ -619,12 +1533,118 @@ impl Database {
                     .remove(format!("{}:{}", self.options.table_pastes.prefix, url))
                     .await;
 
-                // return
-                return Ok(());
-            }
-            Err(_) => return Err(PasteError::Other),
-        };
-    }
+                // store the content we replaced as a revision (only once the edit went
+                // through, so a rename onto a taken url doesn't leave one behind)
+                if new_content != existing.content {
+                    self.create_revision(url.clone(), existing.content.clone(), actor, now)
+                        .await?;
+                }
+
+                // move revisions to the new url
+                if new_url != url {
+                    let query =
+                        Query::update(self.dialect(), &self.options.table_revisions.table_name)
+                            .column(&self.options.table_revisions.url)
+                            .filter(&self.options.table_revisions.url, "=")
+                            .build();
+
+                    if sqlquery(&query)
+                        .bind::<&String>(&new_url)
+                        .bind::<&String>(&url)
+                        .execute(c)
+                        .await
+                        .is_err()
+                    {
+                        return Err(PasteError::Other);
+                    };
+
+                    // point forks at the new url
+                    self.update_forks(url.clone(), Some(new_url.clone()))
+                        .await?;
+
+                    // move attachments to the new url
+                    let query =
+                        Query::update(self.dialect(), &self.options.table_attachments.table_name)
+                            .column("url")
+                            .filter("url", "=")
+                            .build();
+
+                    if sqlquery(&query)
+                        .bind::<&String>(&new_url)
+                        .bind::<&String>(&url)
+                        .execute(c)
+                        .await
+                        .is_err()
+                    {
+                        return Err(PasteError::Other);
+                    };
+
+                    // move collection entries to the new url
+                    let query = Query::update(
+                        self.dialect(),
+                        &self.options.table_collections.members_table_name,
+                    )
+                    .column("url")
+                    .filter("url", "=")
+                    .build();
+
+                    if sqlquery(&query)
+                        .bind::<&String>(&new_url)
+                        .bind::<&String>(&url)
+                        .execute(c)
+                        .await
+                        .is_err()
+                    {
+                        return Err(PasteError::Other);
+                    };
+
+                    // move view analytics to the new url
+                    let query = Query::update(self.dialect(), &self.options.table_stats.table_name)
+                        .column("url")
+                        .filter("url", "=")
+                        .build();
+
+                    if sqlquery(&query)
+                        .bind::<&String>(&new_url)
+                        .bind::<&String>(&url)
+                        .execute(c)
+                        .await
+                        .is_err()
+                    {
+                        return Err(PasteError::Other);
+                    };
+
+                    // move the view count to the new url
+                    let views_key = format!("{}:{}", self.options.table_views.prefix, url);
+
+                    if let Some(views) = self.base.cachedb.get(views_key.clone()).await {
+                        self.base
+                            .cachedb
+                            .set(
+                                format!("{}:{}", self.options.table_views.prefix, new_url),
+                                views,
+                            )
+                            .await;
+
+                        self.base.cachedb.remove(views_key).await;
+                        self.unflushed_views.lock().unwrap().insert(new_url.clone());
+                    }
+                }
+
+                // update search index
+                self.unindex_paste(url).await?;
+
+                if let Ok(paste) = self.get_paste_by_url(new_url).await {
+                    self.index_paste(&paste).await?
+                }
+
+                // return
+                Ok(())
+            }
+            // the unique url constraint makes sure we aren't renaming over another paste
+            Err(e) => Err(write_error(e)),
+        }
+    }
 
     /// Edit an existing paste's metadata by `url`
     ///
@@ -637,7 +1657,7 @@ impl Database {
         &self,
         mut url: String,
         password: String,
-        metadata: PasteMetadata,
+        mut metadata: PasteMetadata,
         editing_as: Option<FullUser<UserMetadata>>,
     ) -> Result<()> {
         url = idna::punycode::encode_str(&url).unwrap().to_lowercase();
@@ -653,35 +1673,143 @@ impl Database {
         };
 
         // check password
-        let mut skip_password_check: bool = false;
+        self.check_edit_access(&existing, password, editing_as)
+            .await?;
 
-        if let Some(ua) = editing_as {
-            // check if we're the paste owner
-            if ua.user.username == existing.metadata.owner {
-                skip_password_check = true;
-            }
-            // check if we have the "ManagePastes" permission
-            else if ua.level.permissions.contains(&"ManagePastes".to_string()) {
-                skip_password_check = true;
-            }
+        // hash view password (and keep the ownership and template state)
+        hash_view_password(&mut metadata, &existing.metadata).await;
+        keep_ownership(&mut metadata, &existing.metadata);
+        keep_template_fields(&mut metadata, &existing.metadata);
+
+        check_variables(&metadata)?;
+
+        normalize_tags(&mut metadata)?;
+
+        // edit paste
+        self.update_paste_metadata(existing, metadata).await
+    }
+
+    /// Claim an existing paste by `url` which has no owner into the account of `editing_as`
+    ///
+    /// # Arguments
+    /// * `url` - the paste to claim
+    /// * `password` - the paste's edit password
+    /// * `editing_as` - the userstate of the user claiming the paste
+    pub async fn claim_paste_by_url(
+        &self,
+        url: String,
+        password: String,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()> {
+        let owner = owner_of_new_paste(&self.options, &editing_as);
+
+        if owner.is_empty() {
+            return Err(PasteError::ValueError);
         }
 
-        if skip_password_check == false {
-            if utility::hash(password) != existing.password {
-                return Err(PasteError::PasswordIncorrect);
-            }
+        // get paste
+        let existing = match self.get_paste_by_url(url).await {
+            Ok(p) => p,
+            Err(err) => return Err(err),
+        };
+
+        if !existing.metadata.owner.is_empty() {
+            return Err(PasteError::ValueError);
         }
 
-        // edit paste
-        let query: String = if (self.base.db._type == "sqlite") | (self.base.db._type == "mysql") {
-            "UPDATE \":t\" SET \":metadata\" = ? WHERE \":url\" = ?"
-        } else {
-            "UPDATE \":t\" SET (\":metadata\" = $1) WHERE \":url\" = $2"
+        // check password (even paste managers need it, claiming is for the paste's author)
+        self.check_password(&existing, password).await?;
+
+        // claim paste
+        let mut metadata = existing.metadata.clone();
+        metadata.owner = owner;
+        metadata.pending_owner = String::new();
+
+        self.update_paste_metadata(existing, metadata).await
+    }
+
+    /// Offer an existing paste by `url` to `username`, who becomes its owner once they
+    /// accept it (a blank `username` cancels the pending transfer)
+    ///
+    /// # Arguments
+    /// * `url` - the paste to transfer
+    /// * `username` - the user to transfer the paste to
+    /// * `editing_as` - the userstate of the user transferring the paste (must be the owner or have `ManagePastes`)
+    pub async fn transfer_paste_by_url(
+        &self,
+        url: String,
+        username: String,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()> {
+        // get paste
+        let existing = match self.get_paste_by_url(url).await {
+            Ok(p) => p,
+            Err(err) => return Err(err),
+        };
+
+        // check permission
+        if !can_transfer(&existing, &editing_as) {
+            return Err(PasteError::PasswordIncorrect);
+        }
+
+        let username = username.trim().to_string();
+
+        if !username.is_empty() && (username == existing.metadata.owner) {
+            return Err(PasteError::ValueError);
+        }
+
+        // offer paste
+        let mut metadata = existing.metadata.clone();
+        metadata.pending_owner = username;
+
+        self.update_paste_metadata(existing, metadata).await
+    }
+
+    /// Accept the pending transfer of an existing paste by `url`
+    ///
+    /// # Arguments
+    /// * `url` - the paste to accept
+    /// * `editing_as` - the userstate of the user accepting the paste (must be the pending owner)
+    pub async fn accept_transfer_by_url(
+        &self,
+        url: String,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()> {
+        // get paste
+        let existing = match self.get_paste_by_url(url).await {
+            Ok(p) => p,
+            Err(err) => return Err(err),
+        };
+
+        // check permission
+        let owner = owner_of_new_paste(&self.options, &editing_as);
+
+        if owner.is_empty() | (owner != existing.metadata.pending_owner) {
+            return Err(PasteError::PasswordIncorrect);
         }
-        .to_string()
-        .replace(":t", &self.options.table_pastes.table_name)
-        .replace(":url", &self.options.table_pastes.url)
-        .replace(":metadata", &self.options.table_pastes.metadata);
+
+        // transfer paste
+        let mut metadata = existing.metadata.clone();
+        metadata.owner = owner;
+        metadata.pending_owner = String::new();
+
+        self.update_paste_metadata(existing, metadata).await
+    }
+
+    /// Replace the metadata of `existing` without checking its password
+    pub(crate) async fn update_paste_metadata(
+        &self,
+        existing: Paste,
+        metadata: PasteMetadata,
+    ) -> Result<()> {
+        let url = existing.url.clone();
+
+        // edit metadata
+        let t = &self.options.table_pastes;
+        let query = Query::update(self.dialect(), &t.table_name)
+            .columns(&[&t.metadata, &t.owner, &t.template])
+            .filter(&t.url, "=")
+            .build();
 
         let c = &self.base.db.client;
         match sqlquery(&query)
@@ -689,6 +1817,8 @@ impl Database {
                 Ok(ref m) => m,
                 Err(_) => return Err(PasteError::ValueError),
             })
+            .bind::<&String>(&metadata.owner)
+            .bind::<&str>(metadata.template_source())
             .bind::<&String>(&url)
             .execute(c)
             .await
@@ -700,156 +1830,2274 @@ impl Database {
                     .remove(format!("{}:{}", self.options.table_pastes.prefix, url))
                     .await;
 
+                // update search index
+                self.index_paste(&Paste {
+                    metadata,
+                    ..existing
+                })
+                .await?;
+
                 // return
-                return Ok(());
+                Ok(())
             }
-            Err(_) => return Err(PasteError::Other),
-        };
+            Err(_) => Err(PasteError::Other),
+        }
     }
 
-    // views
-
-    /// Get an existing url's view count
+    /// Edit an existing paste's expiry settings by `url`
     ///
     /// # Arguments
-    /// * `url` - the paste to count the view for
-    pub async fn get_views_by_url(&self, mut url: String) -> i32 {
+    /// * `url` - the paste to edit
+    /// * `password` - the paste's edit password
+    /// * `expires_at` - the new expiry time of the paste (`0` = never)
+    /// * `max_views` - the new view limit of the paste (`0` = unlimited)
+    /// * `editing_as` - the userstate of the user we're editing the paste as
+    pub async fn edit_paste_expiry_by_url(
+        &self,
+        mut url: String,
+        password: String,
+        expires_at: u128,
+        max_views: i32,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()> {
         url = idna::punycode::encode_str(&url).unwrap().to_lowercase();
 
         if url.ends_with("-") {
             url.pop();
         }
 
-        // get views
-        match self
-            .base
-            .cachedb
-            .get(format!("{}:{}", self.options.table_views.prefix, url))
-            .await
-        {
-            Some(c) => c.parse::<i32>().unwrap(),
-            None => {
-                // try to count from "views"
-                if self.options.view_mode == ViewMode::AuthenticatedOnce {
-                    let query: String =
-                        if (self.base.db._type == "sqlite") | (self.base.db._type == "mysql") {
-                            "SELECT * FROM \":t\" WHERE \"url\" = ?"
-                        } else {
-                            "SELECT * FROM \":t\" WHERE \"url\" = $1"
-                        }
-                        .to_string()
-                        .replace(":t", &self.options.table_views.table_name);
+        // get paste
+        let existing = match self.get_paste_by_url(url.clone()).await {
+            Ok(p) => p,
+            Err(err) => return Err(err),
+        };
 
-                    let c = &self.base.db.client;
-                    match sqlquery(&query).bind::<&String>(&url).fetch_all(c).await {
-                        Ok(views) => {
-                            let views = views.len();
+        // check password
+        self.check_edit_access(&existing, password, editing_as)
+            .await?;
 
-                            // store in cache
-                            self.base
-                                .cachedb
-                                .set(
-                                    format!("{}:{}", self.options.table_views.prefix, url),
-                                    views.to_string(),
-                                )
-                                .await;
+        // check values
+        if (expires_at != 0) && (expires_at <= utility::unix_epoch_timestamp()) {
+            return Err(PasteError::ValueError);
+        }
 
-                            // return
-                            return views as i32;
-                        }
-                        Err(_) => return 0,
-                    };
-                }
+        if max_views < 0 {
+            return Err(PasteError::ValueError);
+        }
+
+        // edit paste
+        let t = &self.options.table_pastes;
+        let query = Query::update(self.dialect(), &t.table_name)
+            .columns(&[&t.expires_at, &t.max_views])
+            .filter(&t.url, "=")
+            .build();
+
+        let c = &self.base.db.client;
+        match sqlquery(&query)
+            .bind::<i64>(expires_at as i64)
+            .bind::<i32>(max_views)
+            .bind::<&String>(&url)
+            .execute(c)
+            .await
+        {
+            Ok(_) => {
+                // remove from cache
+                self.base
+                    .cachedb
+                    .remove(format!("{}:{}", self.options.table_pastes.prefix, url))
+                    .await;
 
-                // return 0 by default
-                0
+                // return
+                Ok(())
             }
+            Err(_) => Err(PasteError::Other),
         }
     }
 
-    /// Update an existing url's view count
+    // expiry
+
+    /// Delete all pastes which have passed their `expires_at` time
     ///
-    /// # Arguments
-    /// * `url` - the paste to count the view for
-    /// * `as_user` - the userstate of the user viewing this (for [`ViewMode::AuthenticatedOnce`])
-    pub async fn incr_views_by_url(
-        &self,
-        mut url: String,
-        as_user: Option<FullUser<UserMetadata>>,
-    ) -> Result<()> {
-        url = idna::punycode::encode_str(&url).unwrap().to_lowercase();
+    /// # Returns
+    /// * Result containing the number of deleted pastes
+    pub async fn purge_expired_pastes(&self) -> Result<usize> {
+        // pull from database
+        let query = self
+            .select_pastes()
+            .filter(&self.options.table_pastes.expires_at, "!=")
+            .build();
 
-        if url.ends_with("-") {
-            url.pop();
-        }
+        let c = &self.base.db.client;
+        let rows = match sqlquery(&query).bind::<i64>(0).fetch_all(c).await {
+            Ok(r) => r,
+            Err(_) => return Err(PasteError::Other),
+        };
 
-        // handle AuthenticatedOnce
-        if self.options.view_mode == ViewMode::AuthenticatedOnce {
-            match as_user {
-                Some(ua) => {
-                    // check for view
-                    if self
-                        .user_has_viewed_paste(url.clone(), ua.user.username.clone())
-                        .await
-                    {
-                        // can only view once in this mode
-                        return Ok(());
-                    }
+        // delete expired
+        let mut purged: usize = 0;
 
-                    // create view
-                    let query: String =
-                        if (self.base.db._type == "sqlite") | (self.base.db._type == "mysql") {
-                            "INSERT INTO \":t\" VALUES (?, ?)"
-                        } else {
-                            "INSERT INTO \":t\" VALEUS ($1, $2)"
-                        }
-                        .to_string()
-                        .replace(":t", &self.options.table_views.table_name);
+        for row in rows {
+            let paste = match self.paste_from_row(self.base.textify_row(row).data) {
+                Ok(p) => p,
+                Err(_) => continue,
+            };
 
-                    let c = &self.base.db.client;
-                    match sqlquery(&query)
-                        .bind::<&String>(&url)
-                        .bind::<&String>(&ua.user.username)
-                        .execute(c)
-                        .await
-                    {
-                        Ok(_) => (), // do nothing so cache is incremented
-                        Err(_) => return Err(PasteError::Other),
-                    };
-                }
-                None => return Ok(()), // not technically an error, just not allowed
+            if !paste.is_expired() {
+                continue;
+            }
+
+            if self.force_delete_paste_by_url(paste.url).await.is_ok() {
+                purged += 1;
             }
         }
 
-        // add view
-        // views never reach the database, they're only stored in memory
-        match self
-            .base
-            .cachedb
-            .incr(format!("{}:{}", self.options.table_views.prefix, url))
+        // return
+        Ok(purged)
+    }
+
+    /// Delete pastes which have been in the trash for longer than [`ServerOptions::trash_retention`]
+    ///
+    /// # Returns
+    /// * Result containing the number of deleted pastes
+    pub async fn purge_trashed_pastes(&self) -> Result<usize> {
+        let cutoff = utility::unix_epoch_timestamp()
+            .saturating_sub(self.options.trash_retention as u128 * 1000);
+
+        // pull from database
+        let t = &self.options.table_pastes;
+        let query = self
+            .select_pastes()
+            .filter(&t.trashed_at, "!=")
+            .filter(&t.trashed_at, "<=")
+            .build();
+
+        let c = &self.base.db.client;
+        let rows = match sqlquery(&query)
+            .bind::<i64>(0)
+            .bind::<i64>(cutoff as i64)
+            .fetch_all(c)
             .await
         {
-            // swapped for some reason??
-            false => Ok(()),
-            true => Err(PasteError::Other),
+            Ok(r) => r,
+            Err(_) => return Err(PasteError::Other),
+        };
+
+        // delete
+        let mut purged: usize = 0;
+
+        for row in rows {
+            let paste = match self.paste_from_row(self.base.textify_row(row).data) {
+                Ok(p) => p,
+                Err(_) => continue,
+            };
+
+            if self.force_delete_paste_by_url(paste.url).await.is_ok() {
+                purged += 1;
+            }
         }
+
+        // return
+        Ok(purged)
     }
 
-    /// Check if a user has views a paste given the `url` and their `username`
+    /// Remove the seen keys of [`ViewMode::OpenDeduplicated`] whose window has ended
     ///
-    /// # Arguments
+    /// # Returns
+    /// * the number of removed keys
+    pub async fn purge_seen_viewers(&self) -> usize {
+        let now = utility::unix_epoch_timestamp();
+
+        let keys: Vec<String> = {
+            let mut windows = self.seen_windows.lock().unwrap();
+            let ended: Vec<String> = windows
+                .iter()
+                .filter(|(_, until)| **until <= now)
+                .map(|(key, _)| key.clone())
+                .collect();
+
+            for key in &ended {
+                windows.remove(key);
+            }
+
+            ended
+        };
+
+        for key in &keys {
+            self.base.cachedb.remove(key.to_string()).await;
+        }
+
+        keys.len()
+    }
+
+    /// Spawn the background tasks which keep the database tidy
+    ///
+    /// * expired pastes (and pastes past their trash retention) are purged every [`ServerOptions::reaper_interval`] seconds,
+    ///   along with the seen keys of ended view windows
+    /// * view counts are flushed every [`ServerOptions::view_flush_interval`] seconds
+    pub fn spawn_background_tasks(&self) {
+        // expired paste reaper
+        if self.options.reaper_interval != 0 {
+            let database = self.clone();
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+                    database.options.reaper_interval,
+                ));
+
+                loop {
+                    interval.tick().await;
+                    let _ = database.purge_expired_pastes().await;
+                    let _ = database.purge_trashed_pastes().await;
+                    database.purge_seen_viewers().await;
+                }
+            });
+        }
+
+        // view count flusher
+        if (self.options.view_flush_interval != 0)
+            && (self.options.view_mode != ViewMode::AuthenticatedOnce)
+        {
+            let database = self.clone();
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+                    database.options.view_flush_interval,
+                ));
+
+                loop {
+                    interval.tick().await;
+                    let _ = database.flush_views().await;
+                }
+            });
+        }
+    }
+
+    // listings
+
+    /// Get a page of the pastes owned by `owner` (newest first)
+    ///
+    /// # Arguments
+    /// * `owner` - the username of the owner
+    /// * `sort` - the field to sort by
+    /// * `cursor` - the `next_cursor` of the previous page (blank for the first page)
+    /// * `limit` - the maximum number of pastes in the page
+    /// * `include_private` - if pastes with a view password should be listed
+    pub async fn list_pastes_by_owner(
+        &self,
+        owner: String,
+        sort: PasteSort,
+        cursor: String,
+        limit: i64,
+        include_private: bool,
+    ) -> Result<PasteList> {
+        if owner.is_empty() | !(1..=100).contains(&limit) {
+            return Err(PasteError::ValueError);
+        }
+
+        let sort_column = match sort {
+            PasteSort::DatePublished => &self.options.table_pastes.date_published,
+            PasteSort::DateEdited => &self.options.table_pastes.date_edited,
+        };
+
+        // the cursor is the sort value and id of the last paste of the previous page
+        let cursor = match cursor.split_once(":") {
+            Some((value, id)) => match value.parse::<i64>() {
+                Ok(value) => Some((value, id.to_string())),
+                Err(_) => return Err(PasteError::ValueError),
+            },
+            None => {
+                if !cursor.is_empty() {
+                    return Err(PasteError::ValueError);
+                }
+
+                None
+            }
+        };
+
+        // pull from database
+        let mut query = self
+            .select_pastes()
+            .filter(&self.options.table_pastes.owner, "=")
+            .filter(&self.options.table_pastes.trashed_at, "=");
+
+        if cursor.is_some() {
+            let dialect = self.dialect();
+            let (sort, id) = (
+                dialect.ident(sort_column),
+                dialect.ident(&self.options.table_pastes.id),
+            );
+            query = query.filter_raw(&format!("{sort} < ? OR ({sort} = ? AND {id} < ?)"));
+        }
+
+        let query = query
+            .order_by(sort_column, true)
+            .order_by(&self.options.table_pastes.id, true)
+            .limit()
+            .build();
+
+        let mut query = sqlquery(&query).bind::<&String>(&owner).bind::<i64>(0);
+
+        if let Some((value, ref id)) = cursor {
+            query = query
+                .bind::<i64>(value)
+                .bind::<i64>(value)
+                .bind::<&String>(id);
+        }
+
+        let c = &self.base.db.client;
+        let rows = match query.bind::<i64>(limit + 1).fetch_all(c).await {
+            Ok(r) => r,
+            Err(_) => return Err(PasteError::Other),
+        };
+
+        // build page
+        let has_more = rows.len() as i64 > limit;
+        let mut pastes: Vec<PasteSummary> = Vec::new();
+        let mut next_cursor: String = String::new();
+
+        for row in rows.into_iter().take(limit as usize) {
+            let paste = match self.paste_from_row(self.base.textify_row(row).data) {
+                Ok(p) => p,
+                Err(e) => return Err(e),
+            };
+
+            next_cursor = format!(
+                "{}:{}",
+                match sort {
+                    PasteSort::DatePublished => paste.date_published,
+                    PasteSort::DateEdited => paste.date_edited,
+                },
+                paste.id
+            );
+
+            if paste.is_expired() {
+                continue;
+            }
+
+            if !include_private && !paste.metadata.view_password.is_empty() {
+                continue;
+            }
+
+            pastes.push(paste.into());
+        }
+
+        // return
+        Ok(PasteList {
+            pastes,
+            next_cursor: if has_more { next_cursor } else { String::new() },
+        })
+    }
+
+    /// Get a page of the pastes derived from the template at `url` (newest first)
+    ///
+    /// # Arguments
+    /// * `url` - the url of the template
+    /// * `limit` - the maximum number of pastes
+    /// * `offset` - the number of pastes to skip
+    pub async fn get_forks_by_url(
+        &self,
+        url: String,
+        limit: i64,
+        offset: i64,
+    ) -> Result<PasteForks> {
+        if !(1..=100).contains(&limit) | (offset < 0) {
+            return Err(PasteError::ValueError);
+        }
+
+        // make sure template exists
+        let existing = match self.get_paste_by_url(url).await {
+            Ok(p) => p,
+            Err(e) => return Err(e),
+        };
+
+        let t = &self.options.table_pastes;
+        let d = self.dialect();
+        let c = &self.base.db.client;
+
+        // count
+        let query = Query::select(d, &t.table_name)
+            .expr(&format!(
+                "CAST(COUNT(*) AS {}) AS {}",
+                d.text_type(),
+                d.ident("total")
+            ))
+            .filter(&t.template, "=")
+            .filter(&t.trashed_at, "=")
+            .build();
+
+        let count = match sqlquery(&query)
+            .bind::<&String>(&existing.url)
+            .bind::<i64>(0)
+            .fetch_one(c)
+            .await
+        {
+            Ok(row) => match self.base.textify_row(row).data.get("total") {
+                Some(total) => total.parse::<i64>().unwrap_or(0),
+                None => 0,
+            },
+            Err(_) => return Err(PasteError::Other),
+        };
+
+        // pull from database
+        let query = self
+            .select_pastes()
+            .filter(&t.template, "=")
+            .filter(&t.trashed_at, "=")
+            .order_by(&t.date_published, true)
+            .limit()
+            .offset()
+            .build();
+
+        let rows = match sqlquery(&query)
+            .bind::<&String>(&existing.url)
+            .bind::<i64>(0)
+            .bind::<i64>(limit)
+            .bind::<i64>(offset)
+            .fetch_all(c)
+            .await
+        {
+            Ok(r) => r,
+            Err(_) => return Err(PasteError::Other),
+        };
+
+        let mut forks: Vec<PasteSummary> = Vec::new();
+
+        for row in rows {
+            let paste = match self.paste_from_row(self.base.textify_row(row).data) {
+                Ok(p) => p,
+                Err(e) => return Err(e),
+            };
+
+            if paste.is_expired() | !paste.metadata.view_password.is_empty() {
+                continue;
+            }
+
+            forks.push(paste.into());
+        }
+
+        // return
+        Ok(PasteForks { count, forks })
+    }
+
+    /// Point every paste derived from the template at (normalized) `url` at `new_url`,
+    /// or mark them as orphaned if there's no `new_url` (the template was deleted)
+    async fn update_forks(&self, url: String, new_url: Option<String>) -> Result<()> {
+        let t = &self.options.table_pastes;
+        let query = self.select_pastes().filter(&t.template, "=").build();
+
+        let c = &self.base.db.client;
+        let rows = match sqlquery(&query).bind::<&String>(&url).fetch_all(c).await {
+            Ok(r) => r,
+            Err(_) => return Err(PasteError::Other),
+        };
+
+        for row in rows {
+            let fork = match self.paste_from_row(self.base.textify_row(row).data) {
+                Ok(p) => p,
+                Err(e) => return Err(e),
+            };
+
+            let mut metadata = fork.metadata.clone();
+
+            match new_url {
+                Some(ref new_url) => metadata.template = new_url.clone(),
+                None => metadata.orphaned = true,
+            }
+
+            self.update_paste_metadata(fork, metadata).await?
+        }
+
+        Ok(())
+    }
+
+    // search
+
+    /// Add a paste (and its tags) to the search index, replacing its previous entry
+    ///
+    /// Pastes with a view password (or in the trash) are never indexed, so their tags are
+    /// never listed either.
+    ///
+    /// # Arguments
+    /// * `paste` - the paste to index
+    async fn index_paste(&self, paste: &Paste) -> Result<()> {
+        self.unindex_paste(paste.url.clone()).await?;
+
+        if !paste.metadata.view_password.is_empty() | paste.is_trashed() {
+            return Ok(());
+        }
+
+        let query = Query::insert(self.dialect(), &self.options.table_search.table_name)
+            .columns(&["url", "title", "description", "content"])
+            .build();
+
+        let c = &self.base.db.client;
+        if sqlquery(&query)
+            .bind::<&String>(&paste.url)
+            .bind::<&String>(&paste.metadata.title)
+            .bind::<&String>(&paste.metadata.description)
+            .bind::<&String>(&paste.content)
+            .execute(c)
+            .await
+            .is_err()
+        {
+            return Err(PasteError::Other);
+        }
+
+        // index tags
+        let query = Query::insert(self.dialect(), &self.options.table_tags.table_name)
+            .columns(&["url", "tag"])
+            .build();
+
+        for tag in &paste.metadata.tags {
+            if sqlquery(&query)
+                .bind::<&String>(&paste.url)
+                .bind::<&String>(tag)
+                .execute(c)
+                .await
+                .is_err()
+            {
+                return Err(PasteError::Other);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Remove a paste (and its tags) from the search index
+    ///
+    /// # Arguments
+    /// * `url` - the url of the paste to remove
+    async fn unindex_paste(&self, url: String) -> Result<()> {
+        let c = &self.base.db.client;
+
+        for table_name in [
+            &self.options.table_search.table_name,
+            &self.options.table_tags.table_name,
+        ] {
+            let query = Query::delete(self.dialect(), table_name)
+                .filter("url", "=")
+                .build();
+
+            if sqlquery(&query)
+                .bind::<&String>(&url)
+                .execute(c)
+                .await
+                .is_err()
+            {
+                return Err(PasteError::Other);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Rebuild the search index (and the tags table) from every stored paste
+    ///
+    /// # Returns
+    /// * Result containing the number of indexed pastes
+    pub async fn rebuild_search_index(&self) -> Result<usize> {
+        let c = &self.base.db.client;
+
+        // clear index
+        for table_name in [
+            &self.options.table_search.table_name,
+            &self.options.table_tags.table_name,
+        ] {
+            let query = Query::delete(self.dialect(), table_name).build();
+
+            if sqlquery(&query).execute(c).await.is_err() {
+                return Err(PasteError::Other);
+            }
+        }
+
+        // pull from database
+        let query = self.select_pastes().build();
+
+        let rows = match sqlquery(&query).fetch_all(c).await {
+            Ok(r) => r,
+            Err(_) => return Err(PasteError::Other),
+        };
+
+        // index
+        let mut indexed: usize = 0;
+
+        for row in rows {
+            let paste = match self.paste_from_row(self.base.textify_row(row).data) {
+                Ok(p) => p,
+                Err(_) => continue,
+            };
+
+            if paste.metadata.view_password.is_empty() && !paste.is_trashed() {
+                self.index_paste(&paste).await?;
+
+                indexed += 1;
+            }
+        }
+
+        // return
+        Ok(indexed)
+    }
+
+    /// Write every stored paste to `writer` as JSON Lines (one [`Paste`] per line)
+    ///
+    /// Pastes are read a page at a time, so the full table is never held in memory.
+    ///
+    /// # Returns
+    /// * Result containing the number of exported pastes
+    pub async fn export_all<W: Write + Send>(&self, mut writer: W) -> Result<usize> {
+        let t = &self.options.table_pastes;
+        let query = self
+            .select_pastes()
+            .filter(&t.url, ">")
+            .order_by(&t.url, false)
+            .limit()
+            .build();
+
+        let c = &self.base.db.client;
+        let mut last_url = String::new();
+        let mut exported: usize = 0;
+
+        loop {
+            let rows = match sqlquery(&query)
+                .bind::<&String>(&last_url)
+                .bind::<i64>(100)
+                .fetch_all(c)
+                .await
+            {
+                Ok(r) => r,
+                Err(_) => return Err(PasteError::Other),
+            };
+
+            if rows.is_empty() {
+                break;
+            }
+
+            for row in rows {
+                let paste = match self.paste_from_row(self.base.textify_row(row).data) {
+                    Ok(p) => p,
+                    Err(e) => return Err(e),
+                };
+
+                let line = match serde_json::to_string(&paste) {
+                    Ok(l) => l,
+                    Err(_) => return Err(PasteError::ValueError),
+                };
+
+                if writeln!(writer, "{line}").is_err() {
+                    return Err(PasteError::Other);
+                }
+
+                last_url = paste.url;
+                exported += 1;
+            }
+        }
+
+        if writer.flush().is_err() {
+            return Err(PasteError::Other);
+        }
+
+        // return
+        Ok(exported)
+    }
+
+    /// Read pastes written by [`Database::export_all`] from `reader`
+    ///
+    /// Ids, password hashes, timestamps and metadata are kept as they are, except for
+    /// pastes imported under a new url (which get a new id).
+    ///
+    /// # Arguments
+    /// * `reader` - JSON Lines source
+    /// * `conflict` - what to do with pastes whose url is already taken
+    pub async fn import<R: BufRead + Send>(
+        &self,
+        reader: R,
+        conflict: ImportConflict,
+    ) -> Result<ImportSummary> {
+        let mut summary = ImportSummary::default();
+
+        for line in reader.lines() {
+            let line = match line {
+                Ok(l) => l,
+                Err(_) => return Err(PasteError::Other),
+            };
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let mut paste: Paste = match serde_json::from_str(&line) {
+                Ok(p) => p,
+                Err(_) => return Err(PasteError::ValueError),
+            };
+
+            paste.url = idna::punycode::encode_str(&paste.url)
+                .unwrap()
+                .to_lowercase();
+
+            if paste.url.ends_with("-") {
+                paste.url.pop();
+            }
+
+            // check for conflict (pastes in the trash still reserve their url)
+            let exists = match self.get_paste_including_trash(paste.url.clone()).await {
+                Ok(_) => true,
+                Err(PasteError::NotFound) => false,
+                Err(e) => return Err(e),
+            };
+
+            if exists {
+                match conflict {
+                    ImportConflict::Skip => {
+                        summary.skipped += 1;
+                        continue;
+                    }
+                    ImportConflict::Overwrite => {
+                        self.force_delete_paste_by_url(paste.url.clone()).await?;
+
+                        summary.overwritten += 1;
+                    }
+                    ImportConflict::Rename => {
+                        let old_url = paste.url.clone();
+
+                        loop {
+                            paste.url = format!(
+                                "{old_url}-{}",
+                                utility::random_id().chars().take(6).collect::<String>()
+                            );
+
+                            match self.get_paste_including_trash(paste.url.clone()).await {
+                                Ok(_) => continue,
+                                Err(PasteError::NotFound) => break,
+                                Err(e) => return Err(e),
+                            }
+                        }
+
+                        paste.id = utility::random_id();
+                        summary.renamed.push((old_url, paste.url.clone()));
+                    }
+                }
+            } else {
+                summary.imported += 1;
+            }
+
+            // insert
+            self.insert_paste(&paste).await?
+        }
+
+        // return
+        Ok(summary)
+    }
+
+    /// Search all public pastes, best matches first
+    ///
+    /// # Arguments
+    /// * `query` - the search terms
+    /// * `limit` - the maximum number of results
+    /// * `offset` - the number of results to skip
+    pub async fn search_pastes(
+        &self,
+        query: String,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<PasteSearchResult>> {
+        let terms = query.trim().to_string();
+
+        if terms.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        if !(1..=100).contains(&limit) | (offset < 0) {
+            return Err(PasteError::ValueError);
+        }
+
+        // build query
+        let c = &self.base.db.client;
+        let dialect = self.dialect();
+        let table = dialect.ident(&self.options.table_search.table_name);
+
+        let unexpired = self.unexpired_urls();
+        let now = utility::unix_epoch_timestamp() as i64;
+
+        let rows = match dialect {
+            Dialect::Sqlite => {
+                // quote every term so user input can't use fts5 query syntax
+                let terms = terms
+                    .split_whitespace()
+                    .map(|t| format!("\"{}\"", t.replace("\"", "\"\"")))
+                    .collect::<Vec<String>>()
+                    .join(" ");
+
+                sqlquery(&format!(
+                    "SELECT url, title, description, substr(content, 1, 200) AS snippet
+                    FROM {table} WHERE {table} MATCH ? AND {unexpired}
+                    ORDER BY bm25({table}) LIMIT ? OFFSET ?"
+                ))
+                .bind::<&String>(&terms)
+                .bind::<i64>(now)
+                .bind::<i64>(limit)
+                .bind::<i64>(offset)
+                .fetch_all(c)
+                .await
+            }
+            Dialect::MySql => {
+                sqlquery(&format!(
+                    "SELECT url, title, description, SUBSTRING(content, 1, 200) AS snippet
+                    FROM {table} WHERE MATCH (title, description, content) AGAINST (? IN NATURAL LANGUAGE MODE) AND {unexpired}
+                    ORDER BY MATCH (title, description, content) AGAINST (? IN NATURAL LANGUAGE MODE) DESC
+                    LIMIT ? OFFSET ?"
+                ))
+                .bind::<&String>(&terms)
+                .bind::<i64>(now)
+                .bind::<&String>(&terms)
+                .bind::<i64>(limit)
+                .bind::<i64>(offset)
+                .fetch_all(c)
+                .await
+            }
+            Dialect::Postgres => {
+                sqlquery(&dialect.placeholders(&format!(
+                    "SELECT url, title, description, substr(content, 1, 200) AS snippet
+                    FROM {table} WHERE to_tsvector('simple', title || ' ' || description || ' ' || content) @@ plainto_tsquery('simple', ?) AND {unexpired}
+                    ORDER BY ts_rank(to_tsvector('simple', title || ' ' || description || ' ' || content), plainto_tsquery('simple', ?)) DESC
+                    LIMIT ? OFFSET ?"
+                )))
+                .bind::<&String>(&terms)
+                .bind::<i64>(now)
+                .bind::<&String>(&terms)
+                .bind::<i64>(limit)
+                .bind::<i64>(offset)
+                .fetch_all(c)
+                .await
+            }
+        };
+
+        let rows = match rows {
+            Ok(r) => r,
+            Err(_) => return Err(PasteError::Other),
+        };
+
+        // return
+        let mut results: Vec<PasteSearchResult> = Vec::new();
+
+        for row in rows {
+            let res = self.base.textify_row(row).data;
+
+            results.push(PasteSearchResult {
+                url: res.get("url").unwrap().to_string(),
+                title: res.get("title").unwrap().to_string(),
+                description: res.get("description").unwrap().to_string(),
+                snippet: res.get("snippet").unwrap().to_string(),
+            });
+        }
+
+        Ok(results)
+    }
+
+    // revisions
+
+    /// Store a revision of a paste
+    ///
+    /// # Arguments
+    /// * `url` - the paste the revision belongs to
+    /// * `content` - the content being replaced
+    /// * `actor` - the username of the user making the edit
+    /// * `timestamp` - when the edit was made
+    async fn create_revision(
+        &self,
+        url: String,
+        content: String,
+        actor: String,
+        timestamp: u128,
+    ) -> Result<PasteRevision> {
+        let revision = PasteRevision {
+            id: utility::random_id(),
+            url,
+            content,
+            actor,
+            timestamp,
+        };
+
+        let t = &self.options.table_revisions;
+        let query = Query::insert(self.dialect(), &t.table_name)
+            .columns(&[&t.id, &t.url, &t.content, &t.actor, &t.timestamp])
+            .build();
+
+        let c = &self.base.db.client;
+        match sqlquery(&query)
+            .bind::<&String>(&revision.id)
+            .bind::<&String>(&revision.url)
+            .bind::<&String>(&revision.content)
+            .bind::<&String>(&revision.actor)
+            .bind::<&String>(&revision.timestamp.to_string())
+            .execute(c)
+            .await
+        {
+            Ok(_) => Ok(revision),
+            Err(_) => Err(PasteError::Other),
+        }
+    }
+
+    /// Convert a textified revision row into a [`PasteRevision`]
+    fn revision_from_row(
+        &self,
+        res: std::collections::HashMap<String, String>,
+    ) -> Result<PasteRevision> {
+        Ok(PasteRevision {
+            id: res
+                .get(&self.options.table_revisions.id)
+                .unwrap()
+                .to_string(),
+            url: res
+                .get(&self.options.table_revisions.url)
+                .unwrap()
+                .to_string(),
+            content: res
+                .get(&self.options.table_revisions.content)
+                .unwrap()
+                .to_string(),
+            actor: res
+                .get(&self.options.table_revisions.actor)
+                .unwrap()
+                .to_string(),
+            timestamp: match res
+                .get(&self.options.table_revisions.timestamp)
+                .unwrap()
+                .parse::<u128>()
+            {
+                Ok(t) => t,
+                Err(_) => return Err(PasteError::ValueError),
+            },
+        })
+    }
+
+    /// Get all revisions of an existing paste by `url` (newest first)
+    ///
+    /// # Arguments
+    /// * `url` - the paste to get revisions for
+    pub async fn get_revisions_by_url(&self, mut url: String) -> Result<Vec<PasteRevision>> {
+        url = idna::punycode::encode_str(&url).unwrap().to_lowercase();
+
+        if url.ends_with("-") {
+            url.pop();
+        }
+
+        // make sure paste exists
+        self.get_paste_by_url(url.clone()).await?;
+
+        // pull from database
+        let query = Query::select(self.dialect(), &self.options.table_revisions.table_name)
+            .filter(&self.options.table_revisions.url, "=")
+            .order_by(&self.options.table_revisions.timestamp, true)
+            .build();
+
+        let c = &self.base.db.client;
+        let rows = match sqlquery(&query).bind::<&String>(&url).fetch_all(c).await {
+            Ok(r) => r,
+            Err(_) => return Err(PasteError::Other),
+        };
+
+        let mut revisions: Vec<PasteRevision> = Vec::new();
+
+        for row in rows {
+            revisions.push(
+                match self.revision_from_row(self.base.textify_row(row).data) {
+                    Ok(r) => r,
+                    Err(e) => return Err(e),
+                },
+            );
+        }
+
+        // return
+        Ok(revisions)
+    }
+
+    /// Get a single revision of an existing paste by `url` and revision `id`
+    ///
+    /// # Arguments
+    /// * `url` - the paste the revision belongs to
+    /// * `id` - the id of the revision
+    pub async fn get_revision_by_id(&self, mut url: String, id: String) -> Result<PasteRevision> {
+        url = idna::punycode::encode_str(&url).unwrap().to_lowercase();
+
+        if url.ends_with("-") {
+            url.pop();
+        }
+
+        // pull from database
+        let query = Query::select(self.dialect(), &self.options.table_revisions.table_name)
+            .filter(&self.options.table_revisions.url, "=")
+            .filter(&self.options.table_revisions.id, "=")
+            .build();
+
+        let c = &self.base.db.client;
+        match sqlquery(&query)
+            .bind::<&String>(&url)
+            .bind::<&String>(&id)
+            .fetch_one(c)
+            .await
+        {
+            Ok(row) => self.revision_from_row(self.base.textify_row(row).data),
+            Err(_) => Err(PasteError::NotFound),
+        }
+    }
+
+    /// Restore an existing paste's content from one of its revisions
+    ///
+    /// The restore is performed as a regular edit, so the content being replaced
+    /// is stored as a new revision and the restore itself can be undone.
+    ///
+    /// # Arguments
+    /// * `url` - the paste to restore
+    /// * `id` - the id of the revision to restore
+    /// * `password` - the paste's edit password
+    /// * `editing_as` - the userstate of the user we're restoring the paste as
+    pub async fn restore_revision_by_id(
+        &self,
+        url: String,
+        id: String,
+        password: String,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()> {
+        let revision = match self.get_revision_by_id(url.clone(), id).await {
+            Ok(r) => r,
+            Err(e) => return Err(e),
+        };
+
+        self.edit_paste_by_url(
+            url,
+            password,
+            revision.content,
+            String::new(),
+            String::new(),
+            editing_as,
+        )
+        .await
+    }
+
+    // attachments
+
+    /// Attach a file to an existing paste, replacing any attachment with the same name
+    ///
+    /// # Arguments
+    /// * `url` - the paste to attach the file to
+    /// * `password` - the paste's edit password
+    /// * `name` - the file name of the attachment
+    /// * `content_type` - the MIME type of the attachment
+    /// * `data` - the contents of the attachment
+    /// * `editing_as` - the userstate of the user we're editing the paste as
+    pub async fn upload_attachment(
+        &self,
+        url: String,
+        password: String,
+        name: String,
+        content_type: String,
+        data: Vec<u8>,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<PasteAttachment> {
+        let existing = match self.get_paste_by_url(url).await {
+            Ok(p) => p,
+            Err(e) => return Err(e),
+        };
+
+        // check password
+        self.check_edit_access(&existing, password, editing_as)
+            .await?;
+
+        // check values
+        self.options
+            .attachments
+            .check(&name, &content_type, data.len())?;
+
+        let attachments = match self.get_attachments_by_url(existing.url.clone()).await {
+            Ok(a) => a,
+            Err(e) => return Err(e),
+        };
+
+        match attachments.iter().find(|a| a.name == name) {
+            // replace the attachment with the same name
+            Some(a) => self.remove_attachment(a).await?,
+            None => {
+                if attachments.len() >= self.options.attachments.max_count {
+                    return Err(PasteError::ValueError);
+                }
+            }
+        }
+
+        // store contents
+        let attachment = PasteAttachment {
+            id: utility::random_id(),
+            url: existing.url,
+            name,
+            content_type,
+            size: data.len() as i64,
+            date_published: utility::unix_epoch_timestamp(),
+        };
+
+        self.blobs.put(&attachment.id, &data).await?;
+
+        // create attachment
+        let query = Query::insert(self.dialect(), &self.options.table_attachments.table_name)
+            .columns(&[
+                "id",
+                "url",
+                "name",
+                "content_type",
+                "size",
+                "date_published",
+            ])
+            .build();
+
+        let c = &self.base.db.client;
+        match sqlquery(&query)
+            .bind::<&String>(&attachment.id)
+            .bind::<&String>(&attachment.url)
+            .bind::<&String>(&attachment.name)
+            .bind::<&String>(&attachment.content_type)
+            .bind::<i64>(attachment.size)
+            .bind::<i64>(attachment.date_published as i64)
+            .execute(c)
+            .await
+        {
+            Ok(_) => Ok(attachment),
+            Err(e) => {
+                let _ = self.blobs.delete(&attachment.id).await;
+                Err(write_error(e))
+            }
+        }
+    }
+
+    /// Get all attachments of an existing paste by `url` (oldest first)
+    pub async fn get_attachments_by_url(&self, mut url: String) -> Result<Vec<PasteAttachment>> {
+        url = idna::punycode::encode_str(&url).unwrap().to_lowercase();
+
+        if url.ends_with("-") {
+            url.pop();
+        }
+
+        // pull from database
+        let query = self
+            .select_attachments()
+            .filter("url", "=")
+            .order_by("date_published", false)
+            .build();
+
+        let c = &self.base.db.client;
+        let rows = match sqlquery(&query).bind::<&String>(&url).fetch_all(c).await {
+            Ok(r) => r,
+            Err(_) => return Err(PasteError::Other),
+        };
+
+        let mut attachments: Vec<PasteAttachment> = Vec::new();
+
+        for row in rows {
+            attachments.push(
+                match self.attachment_from_row(self.base.textify_row(row).data) {
+                    Ok(a) => a,
+                    Err(e) => return Err(e),
+                },
+            );
+        }
+
+        // return
+        Ok(attachments)
+    }
+
+    /// Get a single attachment of an existing paste by `url` and file `name`
+    ///
+    /// # Returns
+    /// * Result containing a tuple with the attachment and its contents
+    pub async fn get_attachment_by_name(
+        &self,
+        url: String,
+        name: String,
+    ) -> Result<(PasteAttachment, Vec<u8>)> {
+        // make sure paste exists (attachments of pastes in the trash aren't served)
+        let existing = match self.get_paste_by_url(url).await {
+            Ok(p) => p,
+            Err(e) => return Err(e),
+        };
+
+        // pull from database
+        let query = self
+            .select_attachments()
+            .filter("url", "=")
+            .filter("name", "=")
+            .build();
+
+        let c = &self.base.db.client;
+        let attachment = match sqlquery(&query)
+            .bind::<&String>(&existing.url)
+            .bind::<&String>(&name)
+            .fetch_one(c)
+            .await
+        {
+            Ok(row) => match self.attachment_from_row(self.base.textify_row(row).data) {
+                Ok(a) => a,
+                Err(e) => return Err(e),
+            },
+            Err(_) => return Err(PasteError::NotFound),
+        };
+
+        match self.blobs.get(&attachment.id).await {
+            Ok(data) => Ok((attachment, data)),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Delete a single attachment of an existing paste by `url` and file `name`
+    ///
+    /// # Arguments
+    /// * `url` - the paste the attachment belongs to
+    /// * `name` - the file name of the attachment
+    /// * `password` - the paste's edit password
+    /// * `editing_as` - the userstate of the user we're editing the paste as
+    pub async fn delete_attachment_by_name(
+        &self,
+        url: String,
+        name: String,
+        password: String,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()> {
+        let existing = match self.get_paste_by_url(url).await {
+            Ok(p) => p,
+            Err(e) => return Err(e),
+        };
+
+        // check password
+        self.check_edit_access(&existing, password, editing_as)
+            .await?;
+
+        // ...
+        let attachments = match self.get_attachments_by_url(existing.url).await {
+            Ok(a) => a,
+            Err(e) => return Err(e),
+        };
+
+        match attachments.iter().find(|a| a.name == name) {
+            Some(a) => self.remove_attachment(a).await,
+            None => Err(PasteError::NotFound),
+        }
+    }
+
+    /// Delete every attachment of the paste at (normalized) `url` without checking its password
+    async fn delete_attachments_by_url(&self, url: String) -> Result<()> {
+        let attachments = match self.get_attachments_by_url(url).await {
+            Ok(a) => a,
+            Err(e) => return Err(e),
+        };
+
+        for attachment in attachments {
+            self.remove_attachment(&attachment).await?
+        }
+
+        Ok(())
+    }
+
+    /// Delete an attachment and its contents
+    async fn remove_attachment(&self, attachment: &PasteAttachment) -> Result<()> {
+        let query = Query::delete(self.dialect(), &self.options.table_attachments.table_name)
+            .filter("id", "=")
+            .build();
+
+        let c = &self.base.db.client;
+        if sqlquery(&query)
+            .bind::<&String>(&attachment.id)
+            .execute(c)
+            .await
+            .is_err()
+        {
+            return Err(PasteError::Other);
+        }
+
+        self.blobs.delete(&attachment.id).await
+    }
+
+    /// Check the edit password of `paste`, unless `editing_as` is the paste owner or a paste manager
+    ///
+    /// # Returns
+    /// * Result containing the paste's (possibly upgraded) password hash
+    async fn check_edit_access(
+        &self,
+        paste: &Paste,
+        password: String,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<String> {
+        if let Some(ua) = editing_as {
+            if (ua.user.username == paste.metadata.owner)
+                | ua.level.permissions.contains(&"ManagePastes".to_string())
+            {
+                return Ok(paste.password.clone());
+            }
+        }
+
+        self.check_password(paste, password).await
+    }
+
+    /// Select from the attachments table, with every typed column cast to text
+    fn select_attachments(&self) -> Query {
+        Query::select(self.dialect(), &self.options.table_attachments.table_name)
+            .columns(&["id", "url", "name", "content_type"])
+            .column_as_text("size")
+            .column_as_text("date_published")
+    }
+
+    /// Convert a textified attachment row into a [`PasteAttachment`]
+    fn attachment_from_row(
+        &self,
+        res: std::collections::HashMap<String, String>,
+    ) -> Result<PasteAttachment> {
+        Ok(PasteAttachment {
+            id: res.get("id").unwrap().to_string(),
+            url: res.get("url").unwrap().to_string(),
+            name: res.get("name").unwrap().to_string(),
+            content_type: res.get("content_type").unwrap().to_string(),
+            size: match res.get("size").unwrap().parse::<i64>() {
+                Ok(s) => s,
+                Err(_) => return Err(PasteError::ValueError),
+            },
+            date_published: match res.get("date_published").unwrap().parse::<u128>() {
+                Ok(t) => t,
+                Err(_) => return Err(PasteError::ValueError),
+            },
+        })
+    }
+
+    // tags
+
+    /// A condition matching rows of the search index or the tags table whose paste hasn't
+    /// expired (the current time is bound as a parameter)
+    ///
+    /// Expired pastes stay indexed and tagged until they're purged, so they're skipped here.
+    fn unexpired_urls(&self) -> String {
+        let t = &self.options.table_pastes;
+        let d = self.dialect();
+        let expires_at = d.ident(&t.expires_at);
+
+        format!(
+            "{} IN (SELECT {} FROM {} WHERE {expires_at} = 0 OR {expires_at} > ?)",
+            d.ident("url"),
+            d.ident(&t.url),
+            d.ident(&t.table_name),
+        )
+    }
+
+    /// Get a page of the public pastes carrying `tag` (newest first)
+    ///
+    /// # Arguments
+    /// * `tag` - the tag
+    /// * `limit` - the maximum number of pastes
+    /// * `offset` - the number of pastes to skip
+    pub async fn get_pastes_by_tag(
+        &self,
+        tag: String,
+        limit: i64,
+        offset: i64,
+    ) -> Result<PasteTagged> {
+        if !(1..=100).contains(&limit) | (offset < 0) {
+            return Err(PasteError::ValueError);
+        }
+
+        let tag = normalize_tag(&tag);
+
+        let t = &self.options.table_pastes;
+        let d = self.dialect();
+        let c = &self.base.db.client;
+
+        // count
+        let query = Query::select(d, &self.options.table_tags.table_name)
+            .expr(&format!(
+                "CAST(COUNT(*) AS {}) AS {}",
+                d.text_type(),
+                d.ident("total")
+            ))
+            .filter("tag", "=")
+            .filter_raw(&self.unexpired_urls())
+            .build();
+
+        let now = utility::unix_epoch_timestamp() as i64;
+        let count = match sqlquery(&query)
+            .bind::<&String>(&tag)
+            .bind::<i64>(now)
+            .fetch_one(c)
+            .await
+        {
+            Ok(row) => match self.base.textify_row(row).data.get("total") {
+                Some(total) => total.parse::<i64>().unwrap_or(0),
+                None => 0,
+            },
+            Err(_) => return Err(PasteError::Other),
+        };
+
+        // pull from database
+        let expires_at = d.ident(&t.expires_at);
+        let query = self
+            .select_pastes()
+            .filter_raw(&format!(
+                "{} IN (SELECT {} FROM {} WHERE {} = ?)",
+                d.ident(&t.url),
+                d.ident("url"),
+                d.ident(&self.options.table_tags.table_name),
+                d.ident("tag")
+            ))
+            .filter_raw(&format!("{expires_at} = 0 OR {expires_at} > ?"))
+            .order_by(&t.date_published, true)
+            .limit()
+            .offset()
+            .build();
+
+        let rows = match sqlquery(&query)
+            .bind::<&String>(&tag)
+            .bind::<i64>(now)
+            .bind::<i64>(limit)
+            .bind::<i64>(offset)
+            .fetch_all(c)
+            .await
+        {
+            Ok(r) => r,
+            Err(_) => return Err(PasteError::Other),
+        };
+
+        let mut pastes: Vec<PasteSummary> = Vec::new();
+
+        for row in rows {
+            let paste = match self.paste_from_row(self.base.textify_row(row).data) {
+                Ok(p) => p,
+                Err(e) => return Err(e),
+            };
+
+            pastes.push(paste.into());
+        }
+
+        // return
+        Ok(PasteTagged { tag, count, pastes })
+    }
+
+    /// Get the tags carried by the most public pastes (most used first)
+    ///
+    /// # Arguments
+    /// * `limit` - the maximum number of tags
+    pub async fn get_tags(&self, limit: i64) -> Result<Vec<TagCount>> {
+        if !(1..=500).contains(&limit) {
+            return Err(PasteError::ValueError);
+        }
+
+        let d = self.dialect();
+        let query = Query::select(d, &self.options.table_tags.table_name)
+            .column("tag")
+            .expr(&format!(
+                "CAST(COUNT(*) AS {}) AS {}",
+                d.text_type(),
+                d.ident("total")
+            ))
+            .filter_raw(&self.unexpired_urls())
+            .group_by("tag")
+            .order_by_raw("COUNT(*)", true)
+            .order_by("tag", false)
+            .limit()
+            .build();
+
+        let c = &self.base.db.client;
+        let rows = match sqlquery(&query)
+            .bind::<i64>(utility::unix_epoch_timestamp() as i64)
+            .bind::<i64>(limit)
+            .fetch_all(c)
+            .await
+        {
+            Ok(r) => r,
+            Err(_) => return Err(PasteError::Other),
+        };
+
+        let mut tags: Vec<TagCount> = Vec::new();
+
+        for row in rows {
+            let res = self.base.textify_row(row).data;
+
+            tags.push(TagCount {
+                tag: res.get("tag").unwrap().to_string(),
+                count: match res.get("total") {
+                    Some(total) => total.parse::<i64>().unwrap_or(0),
+                    None => 0,
+                },
+            });
+        }
+
+        // return
+        Ok(tags)
+    }
+
+    // collections
+
+    /// Get an existing collection by `name`
+    pub async fn get_collection_by_name(&self, mut name: String) -> Result<Collection> {
+        name = idna::punycode::encode_str(&name).unwrap().to_lowercase();
+
+        if name.ends_with("-") {
+            name.pop();
+        }
+
+        // pull from database
+        let query = self.select_collections().filter("name", "=").build();
+
+        let c = &self.base.db.client;
+        let mut collection = match sqlquery(&query).bind::<&String>(&name).fetch_one(c).await {
+            Ok(row) => match self.collection_from_row(self.base.textify_row(row).data) {
+                Ok(c) => c,
+                Err(e) => return Err(e),
+            },
+            Err(_) => return Err(PasteError::NotFound),
+        };
+
+        // pull pastes
+        let query = Query::select(
+            self.dialect(),
+            &self.options.table_collections.members_table_name,
+        )
+        .column("url")
+        .filter("collection", "=")
+        .order_by("position", false)
+        .build();
+
+        let rows = match sqlquery(&query)
+            .bind::<&String>(&collection.id)
+            .fetch_all(c)
+            .await
+        {
+            Ok(r) => r,
+            Err(_) => return Err(PasteError::Other),
+        };
+
+        for row in rows {
+            let res = self.base.textify_row(row).data;
+            collection.pastes.push(res.get("url").unwrap().to_string());
+        }
+
+        // return
+        Ok(collection)
+    }
+
+    /// Get the collections an existing paste by `url` belongs to
+    pub async fn get_collections_by_url(&self, mut url: String) -> Result<Vec<Collection>> {
+        url = idna::punycode::encode_str(&url).unwrap().to_lowercase();
+
+        if url.ends_with("-") {
+            url.pop();
+        }
+
+        // pull from database
+        let d = self.dialect();
+        let query = self
+            .select_collections()
+            .filter_raw(&format!(
+                "{} IN (SELECT {} FROM {} WHERE {} = ?)",
+                d.ident("id"),
+                d.ident("collection"),
+                d.ident(&self.options.table_collections.members_table_name),
+                d.ident("url")
+            ))
+            .order_by("date_published", false)
+            .build();
+
+        let c = &self.base.db.client;
+        let rows = match sqlquery(&query).bind::<&String>(&url).fetch_all(c).await {
+            Ok(r) => r,
+            Err(_) => return Err(PasteError::Other),
+        };
+
+        let mut collections: Vec<Collection> = Vec::new();
+
+        for row in rows {
+            let name = match self.collection_from_row(self.base.textify_row(row).data) {
+                Ok(c) => c.name,
+                Err(e) => return Err(e),
+            };
+
+            collections.push(match self.get_collection_by_name(name).await {
+                Ok(c) => c,
+                Err(e) => return Err(e),
+            });
+        }
+
+        // return
+        Ok(collections)
+    }
+
+    /// Create a new collection
+    ///
+    /// # Arguments
+    /// * `props` - the collection to create
+    /// * `editing_as` - the userstate of the user creating the collection (they become its owner)
+    ///
+    /// # Returns
+    /// * Result containing a tuple with the unhashed edit password and the collection
+    pub async fn create_collection(
+        &self,
+        mut props: CollectionCreate,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<(String, Collection)> {
+        props.name = idna::punycode::encode_str(&props.name)
+            .unwrap()
+            .to_lowercase();
+
+        if props.name.ends_with("-") {
+            props.name.pop();
+        }
+
+        // create name if not supplied
+        if props.name.is_empty() {
+            props.name = utility::random_id().chars().take(10).collect();
+        }
+
+        // create random password if not supplied
+        if props.password.is_empty() {
+            props.password = utility::random_id().chars().take(10).collect();
+        }
+
+        // check values
+        self.options.validation.check_url(&props.name)?;
+
+        let pastes = match self.resolve_collection_pastes(props.pastes).await {
+            Ok(p) => p,
+            Err(e) => return Err(e),
+        };
+
+        // ...
+        let collection = Collection {
+            id: utility::random_id(),
+            name: props.name,
+            title: props.title,
+            description: props.description,
+            owner: match editing_as {
+                Some(ua) if self.options.paste_ownership => ua.user.username,
+                _ => String::new(),
+            },
+            password: password::hash(&props.password).await,
+            pastes,
+            date_published: utility::unix_epoch_timestamp(),
+            date_edited: utility::unix_epoch_timestamp(),
+        };
+
+        check_collection(&collection)?;
+
+        // create collection
+        let query = Query::insert(self.dialect(), &self.options.table_collections.table_name)
+            .columns(&[
+                "id",
+                "name",
+                "title",
+                "description",
+                "owner",
+                "password",
+                "date_published",
+                "date_edited",
+            ])
+            .build();
+
+        let c = &self.base.db.client;
+        if let Err(e) = sqlquery(&query)
+            .bind::<&String>(&collection.id)
+            .bind::<&String>(&collection.name)
+            .bind::<&String>(&collection.title)
+            .bind::<&String>(&collection.description)
+            .bind::<&String>(&collection.owner)
+            .bind::<&String>(&collection.password)
+            .bind::<i64>(collection.date_published as i64)
+            .bind::<i64>(collection.date_edited as i64)
+            .execute(c)
+            .await
+        {
+            return Err(write_error(e));
+        }
+
+        match self
+            .set_collection_pastes(&collection.id, &collection.pastes)
+            .await
+        {
+            Ok(_) => Ok((props.password, collection)),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Edit an existing collection by `name`
+    ///
+    /// # Arguments
+    /// * `name` - the collection to edit
+    /// * `password` - the collection's edit password
+    /// * `title` - the new title of the collection
+    /// * `description` - the new description of the collection
+    /// * `new_password` - the new password of the collection (kept if blank)
+    /// * `pastes` - the new urls of the pastes in the collection, in order
+    /// * `editing_as` - the userstate of the user we're editing the collection as
+    #[allow(clippy::too_many_arguments)]
+    pub async fn edit_collection_by_name(
+        &self,
+        name: String,
+        password: String,
+        title: String,
+        description: String,
+        new_password: String,
+        pastes: Vec<String>,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()> {
+        let existing = match self.get_collection_by_name(name).await {
+            Ok(c) => c,
+            Err(e) => return Err(e),
+        };
+
+        // check password
+        self.check_collection_access(&existing, password, &editing_as)
+            .await?;
+
+        // check values
+        let pastes = match self.resolve_collection_pastes(pastes).await {
+            Ok(p) => p,
+            Err(e) => return Err(e),
+        };
+
+        let collection = Collection {
+            title,
+            description,
+            password: if new_password.is_empty() {
+                existing.password.clone()
+            } else {
+                password::hash(&new_password).await
+            },
+            pastes,
+            date_edited: utility::unix_epoch_timestamp(),
+            ..existing
+        };
+
+        check_collection(&collection)?;
+
+        // edit collection
+        let query = Query::update(self.dialect(), &self.options.table_collections.table_name)
+            .columns(&["title", "description", "password", "date_edited"])
+            .filter("id", "=")
+            .build();
+
+        let c = &self.base.db.client;
+        if sqlquery(&query)
+            .bind::<&String>(&collection.title)
+            .bind::<&String>(&collection.description)
+            .bind::<&String>(&collection.password)
+            .bind::<i64>(collection.date_edited as i64)
+            .bind::<&String>(&collection.id)
+            .execute(c)
+            .await
+            .is_err()
+        {
+            return Err(PasteError::Other);
+        }
+
+        self.set_collection_pastes(&collection.id, &collection.pastes)
+            .await
+    }
+
+    /// Delete an existing collection by `name` (its pastes are kept)
+    ///
+    /// # Arguments
+    /// * `name` - the collection to delete
+    /// * `password` - the collection's edit password
+    /// * `editing_as` - the userstate of the user we're deleting the collection as
+    pub async fn delete_collection_by_name(
+        &self,
+        name: String,
+        password: String,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()> {
+        let existing = match self.get_collection_by_name(name).await {
+            Ok(c) => c,
+            Err(e) => return Err(e),
+        };
+
+        // check password
+        self.check_collection_access(&existing, password, &editing_as)
+            .await?;
+
+        // delete collection
+        self.set_collection_pastes(&existing.id, &[]).await?;
+
+        let query = Query::delete(self.dialect(), &self.options.table_collections.table_name)
+            .filter("id", "=")
+            .build();
+
+        let c = &self.base.db.client;
+        match sqlquery(&query)
+            .bind::<&String>(&existing.id)
+            .execute(c)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(_) => Err(PasteError::Other),
+        }
+    }
+
+    /// Normalize the given paste urls, making sure every paste exists
+    async fn resolve_collection_pastes(&self, urls: Vec<String>) -> Result<Vec<String>> {
+        let mut pastes: Vec<String> = Vec::new();
+
+        for url in urls {
+            match self.get_paste_by_url(url).await {
+                Ok(p) => pastes.push(p.url),
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(pastes)
+    }
+
+    /// Replace the pastes of the collection with the given `id` (in order)
+    async fn set_collection_pastes(&self, id: &String, pastes: &[String]) -> Result<()> {
+        let t = &self.options.table_collections;
+
+        let query = Query::delete(self.dialect(), &t.members_table_name)
+            .filter("collection", "=")
+            .build();
+
+        let c = &self.base.db.client;
+        if sqlquery(&query)
+            .bind::<&String>(id)
+            .execute(c)
+            .await
+            .is_err()
+        {
+            return Err(PasteError::Other);
+        }
+
+        let query = Query::insert(self.dialect(), &t.members_table_name)
+            .columns(&["collection", "url", "position"])
+            .build();
+
+        for (position, url) in pastes.iter().enumerate() {
+            if let Err(e) = sqlquery(&query)
+                .bind::<&String>(id)
+                .bind::<&String>(url)
+                .bind::<i64>(position as i64)
+                .execute(c)
+                .await
+            {
+                return Err(write_error(e));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Select from the collections table, with every typed column cast to text
+    fn select_collections(&self) -> Query {
+        Query::select(self.dialect(), &self.options.table_collections.table_name)
+            .columns(&["id", "name", "title", "description", "owner", "password"])
+            .column_as_text("date_published")
+            .column_as_text("date_edited")
+    }
+
+    /// Convert a textified collection row into a [`Collection`] (without its pastes)
+    fn collection_from_row(
+        &self,
+        res: std::collections::HashMap<String, String>,
+    ) -> Result<Collection> {
+        Ok(Collection {
+            id: res.get("id").unwrap().to_string(),
+            name: res.get("name").unwrap().to_string(),
+            title: res.get("title").unwrap().to_string(),
+            description: res.get("description").unwrap().to_string(),
+            owner: res.get("owner").unwrap().to_string(),
+            password: res.get("password").unwrap().to_string(),
+            pastes: Vec::new(),
+            date_published: match res.get("date_published").unwrap().parse::<u128>() {
+                Ok(t) => t,
+                Err(_) => return Err(PasteError::ValueError),
+            },
+            date_edited: match res.get("date_edited").unwrap().parse::<u128>() {
+                Ok(t) => t,
+                Err(_) => return Err(PasteError::ValueError),
+            },
+        })
+    }
+
+    // views
+
+    /// Get an existing url's view count
+    ///
+    /// # Arguments
+    /// * `url` - the paste to count the view for
+    pub async fn get_views_by_url(&self, mut url: String) -> i32 {
+        url = idna::punycode::encode_str(&url).unwrap().to_lowercase();
+
+        if url.ends_with("-") {
+            url.pop();
+        }
+
+        // get views
+        match self
+            .base
+            .cachedb
+            .get(format!("{}:{}", self.options.table_views.prefix, url))
+            .await
+        {
+            Some(c) => c.parse::<i32>().unwrap(),
+            None => {
+                // try to count from "views"
+                if self.options.view_mode == ViewMode::AuthenticatedOnce {
+                    let query = Query::select(self.dialect(), &self.options.table_views.table_name)
+                        .filter("url", "=")
+                        .build();
+
+                    let c = &self.base.db.client;
+                    match sqlquery(&query).bind::<&String>(&url).fetch_all(c).await {
+                        Ok(views) => {
+                            let views = views.len();
+
+                            // store in cache
+                            self.base
+                                .cachedb
+                                .set(
+                                    format!("{}:{}", self.options.table_views.prefix, url),
+                                    views.to_string(),
+                                )
+                                .await;
+
+                            // return
+                            return views as i32;
+                        }
+                        Err(_) => return 0,
+                    };
+                }
+
+                // fall back to the count persisted by the last flush
+                let t = &self.options.table_pastes;
+                let query = Query::select(self.dialect(), &t.table_name)
+                    .column_as_text(&t.views)
+                    .filter(&t.url, "=")
+                    .build();
+
+                let c = &self.base.db.client;
+                match sqlquery(&query).bind::<&String>(&url).fetch_one(c).await {
+                    Ok(row) => {
+                        let views = match self.base.textify_row(row).data.get(&t.views) {
+                            Some(v) => v.parse::<i32>().unwrap_or(0),
+                            None => 0,
+                        };
+
+                        // store in cache
+                        self.base
+                            .cachedb
+                            .set(
+                                format!("{}:{}", self.options.table_views.prefix, url),
+                                views.to_string(),
+                            )
+                            .await;
+
+                        // return
+                        views
+                    }
+                    Err(_) => 0,
+                }
+            }
+        }
+    }
+
+    /// Update an existing url's view count
+    ///
+    /// # Arguments
+    /// * `url` - the paste to count the view for
+    /// * `as_user` - the userstate of the user viewing this (for [`ViewMode::AuthenticatedOnce`])
+    /// * `viewer` - the viewer key of the user viewing this (for [`ViewMode::OpenDeduplicated`])
+    /// * `referrer` - the `Referer` header of the request (blank if there isn't one)
+    ///
+    /// Every read of a paste with a view limit is counted, whatever the view mode.
+    pub async fn incr_views_by_url(
+        &self,
+        mut url: String,
+        as_user: Option<FullUser<UserMetadata>>,
+        viewer: String,
+        referrer: String,
+    ) -> Result<()> {
+        url = idna::punycode::encode_str(&url).unwrap().to_lowercase();
+
+        if url.ends_with("-") {
+            url.pop();
+        }
+
+        // pastes with a view limit count every read, so they can't be read past it
+        let max_views = match self.get_paste_by_url(url.clone()).await {
+            Ok(p) => p.max_views,
+            Err(_) => 0,
+        };
+
+        // handle AuthenticatedOnce
+        if self.options.view_mode == ViewMode::AuthenticatedOnce {
+            let username = match as_user {
+                Some(ua) => ua.user.username,
+                None if max_views > 0 => String::new(),
+                None => return Ok(()), // not technically an error, just not allowed
+            };
+
+            // check for view
+            if (max_views == 0)
+                && self
+                    .user_has_viewed_paste(url.clone(), username.clone())
+                    .await
+            {
+                // can only view once in this mode
+                return Ok(());
+            }
+
+            // create view
+            let query = Query::insert(self.dialect(), &self.options.table_views.table_name)
+                .columns(&["url", "username"])
+                .build();
+
+            let c = &self.base.db.client;
+            match sqlquery(&query)
+                .bind::<&String>(&url)
+                .bind::<&String>(&username)
+                .execute(c)
+                .await
+            {
+                Ok(_) => (), // do nothing so cache is incremented
+                Err(_) => return Err(PasteError::Other),
+            };
+        }
+
+        // handle OpenDeduplicated
+        if let ViewMode::OpenDeduplicated { window_secs } = self.options.view_mode {
+            if !viewer.is_empty() && (max_views == 0) {
+                // the cache can't expire keys, so the key holds the time its window ends
+                let seen_key = format!(
+                    "{}:seen:{}:{}",
+                    self.options.table_views.prefix, url, viewer
+                );
+                let now = utility::unix_epoch_timestamp();
+
+                if let Some(until) = self.base.cachedb.get(seen_key.clone()).await {
+                    if until.parse::<u128>().unwrap_or(0) > now {
+                        // already counted in this window
+                        return Ok(());
+                    }
+                }
+
+                let until = now + (window_secs as u128 * 1000);
+                self.base
+                    .cachedb
+                    .set(seen_key.clone(), until.to_string())
+                    .await;
+
+                // remember the key so the reaper can remove it once the window ends
+                self.seen_windows.lock().unwrap().insert(seen_key, until);
+            }
+        }
+
+        // make sure the cache holds the persisted count before adding to it
+        if self.options.view_mode != ViewMode::AuthenticatedOnce {
+            self.get_views_by_url(url.clone()).await;
+        }
+
+        // add view
+        // the cached count is written to the database by the next flush
+        if self
+            .base
+            .cachedb
+            .incr(format!("{}:{}", self.options.table_views.prefix, url))
+            .await
+        {
+            // swapped for some reason??
+            return Err(PasteError::Other);
+        }
+
+        if self.options.view_mode != ViewMode::AuthenticatedOnce {
+            self.unflushed_views.lock().unwrap().insert(url.clone());
+        }
+
+        // record view event
+        self.record_view_event(&url, referrer_host(&referrer))
+            .await?;
+
+        // burn paste if it has reached its view limit
+        if (max_views > 0) && (self.get_views_by_url(url.clone()).await >= max_views) {
+            return self.force_delete_paste_by_url(url).await;
+        }
+
+        Ok(())
+    }
+
+    /// Add a view to a paste's analytics, bucketed by day and referrer host
+    async fn record_view_event(&self, url: &String, referrer: String) -> Result<()> {
+        let day = (utility::unix_epoch_timestamp() / 86_400_000) * 86_400_000;
+
+        let query = Query::insert(self.dialect(), &self.options.table_stats.table_name)
+            .columns(&["url", "day", "referrer", "views"])
+            .on_conflict_add(&["url", "day", "referrer"], "views")
+            .build();
+
+        let c = &self.base.db.client;
+        match sqlquery(&query)
+            .bind::<&String>(url)
+            .bind::<i64>(day as i64)
+            .bind::<&String>(&referrer)
+            .bind::<i32>(1)
+            .execute(c)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(_) => Err(PasteError::Other),
+        }
+    }
+
+    /// Get the view analytics of an existing paste by `url`
+    ///
+    /// # Arguments
+    /// * `url` - the paste to get the analytics of
+    /// * `days` - how many days back the analytics go
+    pub async fn get_stats_by_url(&self, url: String, days: i64) -> Result<PasteStats> {
+        let existing = match self.get_paste_by_url(url).await {
+            Ok(p) => p,
+            Err(e) => return Err(e),
+        };
+
+        if !(1..=365).contains(&days) {
+            return Err(PasteError::ValueError);
+        }
+
+        let today = (utility::unix_epoch_timestamp() / 86_400_000) * 86_400_000;
+        let since = today as i64 - ((days - 1) * 86_400_000);
+
+        let dialect = self.dialect();
+        let total = format!(
+            "CAST(SUM({}) AS {}) AS {}",
+            dialect.ident("views"),
+            dialect.text_type(),
+            dialect.ident("total")
+        );
+
+        let c = &self.base.db.client;
+
+        // daily series
+        let query = Query::select(dialect, &self.options.table_stats.table_name)
+            .column_as_text("day")
+            .expr(&total)
+            .filter("url", "=")
+            .filter("day", ">=")
+            .group_by("day")
+            .order_by("day", false)
+            .build();
+
+        let rows = match sqlquery(&query)
+            .bind::<&String>(&existing.url)
+            .bind::<i64>(since)
+            .fetch_all(c)
+            .await
+        {
+            Ok(r) => r,
+            Err(_) => return Err(PasteError::Other),
+        };
+
+        let mut series: Vec<PasteStatsDay> = Vec::new();
+
+        for row in rows {
+            let res = self.base.textify_row(row).data;
+            series.push(PasteStatsDay {
+                day: res.get("day").unwrap().parse::<u128>().unwrap_or(0),
+                views: res.get("total").unwrap().parse::<i64>().unwrap_or(0),
+            });
+        }
+
+        // top referrers
+        let query = Query::select(dialect, &self.options.table_stats.table_name)
+            .column("referrer")
+            .expr(&total)
+            .filter("url", "=")
+            .filter("day", ">=")
+            .group_by("referrer")
+            // the total is selected as text, so order by the sum itself
+            .order_by_raw(&format!("SUM({})", dialect.ident("views")), true)
+            .order_by("referrer", false)
+            .limit()
+            .build();
+
+        let rows = match sqlquery(&query)
+            .bind::<&String>(&existing.url)
+            .bind::<i64>(since)
+            .bind::<i64>(10)
+            .fetch_all(c)
+            .await
+        {
+            Ok(r) => r,
+            Err(_) => return Err(PasteError::Other),
+        };
+
+        let mut referrers: Vec<PasteStatsReferrer> = Vec::new();
+
+        for row in rows {
+            let res = self.base.textify_row(row).data;
+            referrers.push(PasteStatsReferrer {
+                host: res.get("referrer").unwrap().to_string(),
+                views: res.get("total").unwrap().parse::<i64>().unwrap_or(0),
+            });
+        }
+
+        // return
+        Ok(PasteStats {
+            views: self.get_views_by_url(existing.url).await,
+            days: series,
+            referrers,
+        })
+    }
+
+    /// Write the cached view counts which changed since the last flush to the database
+    ///
+    /// # Returns
+    /// * Result containing the number of pastes whose view count was written
+    pub async fn flush_views(&self) -> Result<usize> {
+        let urls: Vec<String> = self.unflushed_views.lock().unwrap().drain().collect();
+
+        let t = &self.options.table_pastes;
+        let query = Query::update(self.dialect(), &t.table_name)
+            .column(&t.views)
+            .filter(&t.url, "=")
+            .build();
+
+        let c = &self.base.db.client;
+        let mut flushed: usize = 0;
+
+        for url in urls {
+            let views = match self
+                .base
+                .cachedb
+                .get(format!("{}:{}", self.options.table_views.prefix, url))
+                .await
+            {
+                Some(v) => v.parse::<i32>().unwrap_or(0),
+                None => continue, // paste was deleted
+            };
+
+            if sqlquery(&query)
+                .bind::<i32>(views)
+                .bind::<&String>(&url)
+                .execute(c)
+                .await
+                .is_err()
+            {
+                // try again on the next flush
+                self.unflushed_views.lock().unwrap().insert(url);
+                continue;
+            }
+
+            flushed += 1;
+        }
+
+        // return
+        Ok(flushed)
+    }
+
+    /// Check if a user has views a paste given the `url` and their `username`
+    ///
+    /// # Arguments
     /// * `url` - the paste url
     /// * `username` - the username of the user
     pub async fn user_has_viewed_paste(&self, url: String, username: String) -> bool {
         if self.options.view_mode == ViewMode::AuthenticatedOnce {
-            let query: String =
-                if (self.base.db._type == "sqlite") | (self.base.db._type == "mysql") {
-                    "SELECT * FROM \":t\" WHERE \"url\" = ? AND \"username\" = ?"
-                } else {
-                    "SELECT * FROM \":t\" WHERE \"url\" = $1 AND \"username\" = ?"
-                }
-                .to_string()
-                .replace(":t", &self.options.table_views.table_name);
+            let query = Query::select(self.dialect(), &self.options.table_views.table_name)
+                .filter("url", "=")
+                .filter("username", "=")
+                .build();
 
             let c = &self.base.db.client;
             match sqlquery(&query)
@@ -866,3 +4114,319 @@ impl Database {
         false
     }
 }
+
+#[async_trait::async_trait]
+impl PasteStore for Database {
+    fn options(&self) -> &ServerOptions {
+        &self.options
+    }
+
+    async fn get_user_by_token(&self, token: String) -> Result<FullUser<UserMetadata>> {
+        match self.auth.get_user_by_unhashed(token).await {
+            Ok(ua) => Ok(ua),
+            Err(_) => Err(PasteError::Other),
+        }
+    }
+
+    // pastes
+
+    async fn get_paste_by_url(&self, url: String) -> Result<Paste> {
+        Database::get_paste_by_url(self, url).await
+    }
+
+    async fn create_paste(
+        &self,
+        props: PasteCreate,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<(String, Paste)> {
+        Database::create_paste(self, props, editing_as).await
+    }
+
+    async fn clone_paste(
+        &self,
+        props: PasteClone,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<(String, Paste)> {
+        Database::clone_paste(self, props, editing_as).await
+    }
+
+    async fn delete_paste_by_url(
+        &self,
+        url: String,
+        password: String,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()> {
+        Database::delete_paste_by_url(self, url, password, editing_as).await
+    }
+
+    async fn restore_paste_by_url(
+        &self,
+        url: String,
+        password: String,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()> {
+        Database::restore_paste_by_url(self, url, password, editing_as).await
+    }
+
+    async fn edit_paste_by_url(
+        &self,
+        url: String,
+        password: String,
+        new_content: String,
+        new_url: String,
+        new_password: String,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()> {
+        Database::edit_paste_by_url(
+            self,
+            url,
+            password,
+            new_content,
+            new_url,
+            new_password,
+            editing_as,
+        )
+        .await
+    }
+
+    async fn edit_paste_metadata_by_url(
+        &self,
+        url: String,
+        password: String,
+        metadata: PasteMetadata,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()> {
+        Database::edit_paste_metadata_by_url(self, url, password, metadata, editing_as).await
+    }
+
+    async fn edit_paste_expiry_by_url(
+        &self,
+        url: String,
+        password: String,
+        expires_at: u128,
+        max_views: i32,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()> {
+        Database::edit_paste_expiry_by_url(self, url, password, expires_at, max_views, editing_as)
+            .await
+    }
+
+    // rate limits
+
+    async fn hit_rate_limit(&self, key: String, limit: RateLimit) -> Result<()> {
+        Database::hit_rate_limit(self, key, limit).await
+    }
+
+    async fn check_password_lockout(&self, url: String) -> Result<()> {
+        Database::check_password_lockout(self, url).await
+    }
+
+    async fn record_password_attempt(&self, url: String, success: bool) {
+        Database::record_password_attempt(self, url, success).await
+    }
+
+    // ownership
+
+    async fn claim_paste_by_url(
+        &self,
+        url: String,
+        password: String,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()> {
+        Database::claim_paste_by_url(self, url, password, editing_as).await
+    }
+
+    async fn transfer_paste_by_url(
+        &self,
+        url: String,
+        username: String,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()> {
+        Database::transfer_paste_by_url(self, url, username, editing_as).await
+    }
+
+    async fn accept_transfer_by_url(
+        &self,
+        url: String,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()> {
+        Database::accept_transfer_by_url(self, url, editing_as).await
+    }
+
+    // listings
+
+    async fn list_pastes_by_owner(
+        &self,
+        owner: String,
+        sort: PasteSort,
+        cursor: String,
+        limit: i64,
+        include_private: bool,
+    ) -> Result<PasteList> {
+        Database::list_pastes_by_owner(self, owner, sort, cursor, limit, include_private).await
+    }
+
+    async fn get_forks_by_url(&self, url: String, limit: i64, offset: i64) -> Result<PasteForks> {
+        Database::get_forks_by_url(self, url, limit, offset).await
+    }
+
+    async fn search_pastes(
+        &self,
+        query: String,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<PasteSearchResult>> {
+        Database::search_pastes(self, query, limit, offset).await
+    }
+
+    // revisions
+
+    async fn get_revisions_by_url(&self, url: String) -> Result<Vec<PasteRevision>> {
+        Database::get_revisions_by_url(self, url).await
+    }
+
+    async fn get_revision_by_id(&self, url: String, id: String) -> Result<PasteRevision> {
+        Database::get_revision_by_id(self, url, id).await
+    }
+
+    async fn restore_revision_by_id(
+        &self,
+        url: String,
+        id: String,
+        password: String,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()> {
+        Database::restore_revision_by_id(self, url, id, password, editing_as).await
+    }
+
+    // attachments
+
+    async fn upload_attachment(
+        &self,
+        url: String,
+        password: String,
+        name: String,
+        content_type: String,
+        data: Vec<u8>,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<PasteAttachment> {
+        Database::upload_attachment(self, url, password, name, content_type, data, editing_as).await
+    }
+
+    async fn get_attachments_by_url(&self, url: String) -> Result<Vec<PasteAttachment>> {
+        Database::get_attachments_by_url(self, url).await
+    }
+
+    async fn get_attachment_by_name(
+        &self,
+        url: String,
+        name: String,
+    ) -> Result<(PasteAttachment, Vec<u8>)> {
+        Database::get_attachment_by_name(self, url, name).await
+    }
+
+    async fn delete_attachment_by_name(
+        &self,
+        url: String,
+        name: String,
+        password: String,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()> {
+        Database::delete_attachment_by_name(self, url, name, password, editing_as).await
+    }
+
+    // templates
+
+    async fn sync_template_by_url(
+        &self,
+        url: String,
+        password: String,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<TemplateSync> {
+        Database::sync_template_by_url(self, url, password, editing_as).await
+    }
+
+    // tags
+
+    async fn get_pastes_by_tag(&self, tag: String, limit: i64, offset: i64) -> Result<PasteTagged> {
+        Database::get_pastes_by_tag(self, tag, limit, offset).await
+    }
+
+    async fn get_tags(&self, limit: i64) -> Result<Vec<TagCount>> {
+        Database::get_tags(self, limit).await
+    }
+
+    // collections
+
+    async fn get_collection_by_name(&self, name: String) -> Result<Collection> {
+        Database::get_collection_by_name(self, name).await
+    }
+
+    async fn get_collections_by_url(&self, url: String) -> Result<Vec<Collection>> {
+        Database::get_collections_by_url(self, url).await
+    }
+
+    async fn create_collection(
+        &self,
+        props: CollectionCreate,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<(String, Collection)> {
+        Database::create_collection(self, props, editing_as).await
+    }
+
+    async fn edit_collection_by_name(
+        &self,
+        name: String,
+        password: String,
+        title: String,
+        description: String,
+        new_password: String,
+        pastes: Vec<String>,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()> {
+        Database::edit_collection_by_name(
+            self,
+            name,
+            password,
+            title,
+            description,
+            new_password,
+            pastes,
+            editing_as,
+        )
+        .await
+    }
+
+    async fn delete_collection_by_name(
+        &self,
+        name: String,
+        password: String,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()> {
+        Database::delete_collection_by_name(self, name, password, editing_as).await
+    }
+
+    // views
+
+    async fn get_views_by_url(&self, url: String) -> i32 {
+        Database::get_views_by_url(self, url).await
+    }
+
+    async fn incr_views_by_url(
+        &self,
+        url: String,
+        as_user: Option<FullUser<UserMetadata>>,
+        viewer: String,
+        referrer: String,
+    ) -> Result<()> {
+        Database::incr_views_by_url(self, url, as_user, viewer, referrer).await
+    }
+
+    async fn get_stats_by_url(&self, url: String, days: i64) -> Result<PasteStats> {
+        Database::get_stats_by_url(self, url, days).await
+    }
+
+    async fn user_has_viewed_paste(&self, url: String, username: String) -> bool {
+        Database::user_has_viewed_paste(self, url, username).await
+    }
+}
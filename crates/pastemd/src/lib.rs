@@ -1,5 +1,11 @@
+pub mod admin;
+pub mod blob;
 pub mod database;
+pub mod migrations;
 pub mod model;
+pub mod password;
+mod query;
 pub mod routing;
+pub mod store;
 
 pub use dorsal::DatabaseOpts;
@@ -0,0 +1,895 @@
+//! Versioned schema migrations
+//!
+//! Every schema change ships as a numbered [`Migration`]. The versions which have been
+//! applied are recorded in the migrations table, so each migration only ever runs once.
+use crate::database::Database;
+use crate::password;
+use crate::query::{Dialect, Query};
+
+use dorsal::utility;
+use dorsal::query as sqlquery;
+
+/// A single step of a [`Migration`]
+pub enum Step {
+    /// Execute a SQL statement
+    Sql(String),
+    /// Rebuild the full-text search index once the migration has been applied
+    RebuildSearchIndex,
+    /// Hash plaintext view passwords (inside the migration's transaction, so the migration is
+    /// only recorded once every view password has been hashed)
+    HashViewPasswords,
+}
+
+/// A numbered schema change
+pub struct Migration {
+    /// The schema version this migration upgrades the database to
+    pub version: i64,
+    /// A short explanation of what the migration changes
+    pub description: &'static str,
+    /// Build the (dialect-specific) steps of this migration
+    pub steps: fn(&Database) -> Vec<Step>,
+}
+
+/// Migration errors
+#[derive(Debug)]
+pub enum MigrationError {
+    /// The database was migrated by a newer version of pastemd
+    NewerSchema { found: i64, supported: i64 },
+    /// The database has migrations which have not been applied yet
+    Pending { found: i64, supported: i64 },
+    /// A migration failed to apply
+    Failed { version: i64, error: String },
+    /// The migrations table could not be read
+    Other,
+}
+
+impl std::fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use MigrationError::*;
+        match self {
+            NewerSchema { found, supported } => write!(
+                f,
+                "Database schema version {found} is newer than the latest supported version ({supported})."
+            ),
+            Pending { found, supported } => write!(
+                f,
+                "Database schema version {found} is out of date (latest is {supported}). Please run migrations."
+            ),
+            Failed { version, error } => write!(f, "Migration {version} failed: {error}"),
+            Other => write!(f, "Failed to read the migrations table."),
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {}
+
+/// All migrations, in order
+pub fn all() -> Vec<Migration> {
+    vec![
+        Migration {
+            version: 1,
+            description: "create pastes and views tables",
+            steps: |db| {
+                let d = db.dialect();
+                let t = &db.options.table_pastes;
+                vec![
+                    Step::Sql(format!(
+                        "CREATE TABLE IF NOT EXISTS {} (
+                            {} TEXT,
+                            {} TEXT,
+                            {} TEXT,
+                            {} TEXT,
+                            {} TEXT,
+                            {} TEXT,
+                            {} TEXT
+                        )",
+                        d.ident(&t.table_name),
+                        d.ident(&t.url),
+                        d.ident(&t.id),
+                        d.ident(&t.password),
+                        d.ident(&t.date_published),
+                        d.ident(&t.date_edited),
+                        d.ident(&t.content),
+                        d.ident(&t.metadata)
+                    )),
+                    Step::Sql(format!(
+                        "CREATE TABLE IF NOT EXISTS {} (
+                            url      TEXT,
+                            username TEXT
+                        )",
+                        d.ident(&db.options.table_views.table_name)
+                    )),
+                ]
+            },
+        },
+        Migration {
+            version: 2,
+            description: "create revisions table",
+            steps: |db| {
+                let d = db.dialect();
+                let t = &db.options.table_revisions;
+                vec![Step::Sql(format!(
+                    "CREATE TABLE IF NOT EXISTS {} (
+                        {} TEXT,
+                        {} TEXT,
+                        {} TEXT,
+                        {} TEXT,
+                        {} TEXT
+                    )",
+                    d.ident(&t.table_name),
+                    d.ident(&t.id),
+                    d.ident(&t.url),
+                    d.ident(&t.content),
+                    d.ident(&t.actor),
+                    d.ident(&t.timestamp)
+                ))]
+            },
+        },
+        Migration {
+            version: 3,
+            description: "add paste expiry columns",
+            steps: |db| {
+                let d = db.dialect();
+                let t = &db.options.table_pastes;
+                vec![
+                    Step::Sql(format!(
+                        "ALTER TABLE {} ADD COLUMN {} TEXT DEFAULT '0'",
+                        d.ident(&t.table_name),
+                        d.ident(&t.expires_at)
+                    )),
+                    Step::Sql(format!(
+                        "ALTER TABLE {} ADD COLUMN {} TEXT DEFAULT '0'",
+                        d.ident(&t.table_name),
+                        d.ident(&t.max_views)
+                    )),
+                ]
+            },
+        },
+        Migration {
+            version: 4,
+            description: "create full-text search index",
+            steps: |db| {
+                let d = db.dialect();
+                let t = d.ident(&db.options.table_search.table_name);
+                let index = d.ident(&format!("{}_fts", db.options.table_search.table_name));
+                match d {
+                    Dialect::Sqlite => vec![
+                        Step::Sql(format!(
+                            "CREATE VIRTUAL TABLE IF NOT EXISTS {t} USING fts5(
+                                url UNINDEXED,
+                                title,
+                                description,
+                                content
+                            )"
+                        )),
+                        Step::RebuildSearchIndex,
+                    ],
+                    Dialect::MySql => vec![
+                        Step::Sql(format!(
+                            "CREATE TABLE IF NOT EXISTS {t} (
+                                url         TEXT,
+                                title       TEXT,
+                                description TEXT,
+                                content     LONGTEXT,
+                                FULLTEXT (title, description, content)
+                            )"
+                        )),
+                        Step::RebuildSearchIndex,
+                    ],
+                    Dialect::Postgres => vec![
+                        Step::Sql(format!(
+                            "CREATE TABLE IF NOT EXISTS {t} (
+                                url         TEXT,
+                                title       TEXT,
+                                description TEXT,
+                                content     TEXT
+                            )"
+                        )),
+                        Step::Sql(format!(
+                            "CREATE INDEX IF NOT EXISTS {index} ON {t} USING GIN (
+                                to_tsvector('simple', title || ' ' || description || ' ' || content)
+                            )"
+                        )),
+                        Step::RebuildSearchIndex,
+                    ],
+                }
+            },
+        },
+        Migration {
+            version: 5,
+            description: "add indexed owner column",
+            steps: |db| {
+                let d = db.dialect();
+                let t = &db.options.table_pastes;
+                let metadata = d.ident(&t.metadata);
+                let owner_from_metadata = match d {
+                    Dialect::Sqlite => format!("json_extract({metadata}, '$.owner')"),
+                    Dialect::MySql => format!("JSON_UNQUOTE(JSON_EXTRACT({metadata}, '$.owner'))"),
+                    Dialect::Postgres => format!("CAST({metadata} AS json)->>'owner'"),
+                };
+
+                vec![
+                    Step::Sql(format!(
+                        "ALTER TABLE {} ADD COLUMN {} TEXT",
+                        d.ident(&t.table_name),
+                        d.ident(&t.owner)
+                    )),
+                    Step::Sql(format!(
+                        "UPDATE {} SET {} = COALESCE({}, '')",
+                        d.ident(&t.table_name),
+                        d.ident(&t.owner),
+                        owner_from_metadata
+                    )),
+                    Step::Sql(format!(
+                        "CREATE INDEX {} ON {} ({})",
+                        d.ident(&format!("{}_{}", t.table_name, t.owner)),
+                        d.ident(&t.table_name),
+                        // mysql can only index a prefix of TEXT columns
+                        if d == Dialect::MySql {
+                            format!("{}(191)", d.ident(&t.owner))
+                        } else {
+                            d.ident(&t.owner)
+                        }
+                    )),
+                ]
+            },
+        },
+        Migration {
+            version: 6,
+            description: "type paste columns and add paste keys",
+            steps: |db| {
+                let d = db.dialect();
+                let t = &db.options.table_pastes;
+                let typed = d.ident(&format!("{}_typed", t.table_name));
+                let table = d.ident(&t.table_name);
+                let (url, id, password) = (d.ident(&t.url), d.ident(&t.id), d.ident(&t.password));
+                let (date_published, date_edited) =
+                    (d.ident(&t.date_published), d.ident(&t.date_edited));
+                let (content, metadata) = (d.ident(&t.content), d.ident(&t.metadata));
+                let (expires_at, max_views) = (d.ident(&t.expires_at), d.ident(&t.max_views));
+                let owner = d.ident(&t.owner);
+
+                // creating pastes used to be racy, so a url can belong to more than one paste;
+                // the oldest paste keeps it and every other one is moved to "{url}-{id}"
+                let dedupe = match d {
+                    // mysql can't select from the table it's updating in a subquery
+                    Dialect::MySql => format!(
+                        "UPDATE {table} AS newer JOIN {table} AS older
+                            ON older.{url} = newer.{url}
+                            AND (
+                                CAST(older.{date_published} AS SIGNED) < CAST(newer.{date_published} AS SIGNED)
+                                OR (
+                                    CAST(older.{date_published} AS SIGNED) = CAST(newer.{date_published} AS SIGNED)
+                                    AND older.{id} < newer.{id}
+                                )
+                            )
+                        SET newer.{url} = CONCAT(newer.{url}, '-', newer.{id})"
+                    ),
+                    _ => format!(
+                        "UPDATE {table} SET {url} = {url} || '-' || {id} WHERE EXISTS (
+                            SELECT 1 FROM {table} AS older
+                            WHERE older.{url} = {table}.{url}
+                            AND (
+                                CAST(older.{date_published} AS BIGINT) < CAST({table}.{date_published} AS BIGINT)
+                                OR (
+                                    CAST(older.{date_published} AS BIGINT) = CAST({table}.{date_published} AS BIGINT)
+                                    AND older.{id} < {table}.{id}
+                                )
+                            )
+                        )"
+                    ),
+                };
+
+                match d {
+                    // sqlite can't change column types, so the table is rebuilt
+                    Dialect::Sqlite => vec![
+                        Step::Sql(dedupe),
+                        Step::Sql(format!(
+                            "CREATE TABLE {typed} (
+                                {url} TEXT NOT NULL UNIQUE,
+                                {id} TEXT NOT NULL PRIMARY KEY,
+                                {password} TEXT NOT NULL,
+                                {date_published} INTEGER NOT NULL,
+                                {date_edited} INTEGER NOT NULL,
+                                {content} TEXT NOT NULL,
+                                {metadata} TEXT NOT NULL,
+                                {expires_at} INTEGER NOT NULL DEFAULT 0,
+                                {max_views} INTEGER NOT NULL DEFAULT 0,
+                                {owner} TEXT NOT NULL DEFAULT ''
+                            )"
+                        )),
+                        Step::Sql(format!(
+                            "INSERT INTO {typed} SELECT
+                                {url},
+                                {id},
+                                {password},
+                                CAST({date_published} AS INTEGER),
+                                CAST({date_edited} AS INTEGER),
+                                {content},
+                                {metadata},
+                                CAST(COALESCE({expires_at}, '0') AS INTEGER),
+                                CAST(COALESCE({max_views}, '0') AS INTEGER),
+                                COALESCE({owner}, '')
+                            FROM {table}"
+                        )),
+                        Step::Sql(format!("DROP TABLE {table}")),
+                        Step::Sql(format!("ALTER TABLE {typed} RENAME TO {table}")),
+                        Step::Sql(format!(
+                            "CREATE INDEX {} ON {table} ({owner})",
+                            d.ident(&format!("{}_{}", t.table_name, t.owner)),
+                        )),
+                    ],
+                    Dialect::MySql => vec![
+                        Step::Sql(dedupe),
+                        Step::Sql(format!(
+                            "ALTER TABLE {table}
+                                MODIFY {url} VARCHAR(255) NOT NULL,
+                                MODIFY {id} VARCHAR(64) NOT NULL,
+                                MODIFY {date_published} BIGINT NOT NULL,
+                                MODIFY {date_edited} BIGINT NOT NULL,
+                                MODIFY {expires_at} BIGINT NOT NULL DEFAULT 0,
+                                MODIFY {max_views} INT NOT NULL DEFAULT 0,
+                                ADD PRIMARY KEY ({id}),
+                                ADD UNIQUE ({url})"
+                        )),
+                    ],
+                    Dialect::Postgres => vec![
+                        Step::Sql(dedupe),
+                        Step::Sql(format!(
+                            "ALTER TABLE {table}
+                                ALTER COLUMN {date_published} TYPE BIGINT USING CAST({date_published} AS BIGINT),
+                                ALTER COLUMN {date_edited} TYPE BIGINT USING CAST({date_edited} AS BIGINT),
+                                ALTER COLUMN {expires_at} DROP DEFAULT,
+                                ALTER COLUMN {expires_at} TYPE BIGINT USING CAST(COALESCE({expires_at}, '0') AS BIGINT),
+                                ALTER COLUMN {expires_at} SET DEFAULT 0,
+                                ALTER COLUMN {max_views} DROP DEFAULT,
+                                ALTER COLUMN {max_views} TYPE INTEGER USING CAST(COALESCE({max_views}, '0') AS INTEGER),
+                                ALTER COLUMN {max_views} SET DEFAULT 0"
+                        )),
+                        Step::Sql(format!("ALTER TABLE {table} ADD PRIMARY KEY ({id})")),
+                        Step::Sql(format!(
+                            "CREATE UNIQUE INDEX {} ON {table} ({url})",
+                            d.ident(&format!("{}_{}", t.table_name, t.url)),
+                        )),
+                    ],
+                }
+            },
+        },
+        Migration {
+            version: 7,
+            description: "add persisted view count column",
+            steps: |db| {
+                let d = db.dialect();
+                let t = &db.options.table_pastes;
+                vec![Step::Sql(format!(
+                    "ALTER TABLE {} ADD COLUMN {} {} NOT NULL DEFAULT 0",
+                    d.ident(&t.table_name),
+                    d.ident(&t.views),
+                    match d {
+                        Dialect::MySql => "INT",
+                        _ => "INTEGER",
+                    }
+                ))]
+            },
+        },
+        Migration {
+            version: 8,
+            description: "create view analytics table",
+            steps: |db| {
+                let d = db.dialect();
+                let t = d.ident(&db.options.table_stats.table_name);
+                vec![Step::Sql(match d {
+                    // mysql can't put TEXT columns in a unique key
+                    Dialect::MySql => format!(
+                        "CREATE TABLE IF NOT EXISTS {t} (
+                            url      VARCHAR(255) NOT NULL,
+                            day      BIGINT       NOT NULL,
+                            referrer VARCHAR(255) NOT NULL,
+                            views    INT          NOT NULL DEFAULT 0,
+                            UNIQUE (url, day, referrer)
+                        )"
+                    ),
+                    _ => format!(
+                        "CREATE TABLE IF NOT EXISTS {t} (
+                            url      TEXT    NOT NULL,
+                            day      BIGINT  NOT NULL,
+                            referrer TEXT    NOT NULL,
+                            views    INTEGER NOT NULL DEFAULT 0,
+                            UNIQUE (url, day, referrer)
+                        )"
+                    ),
+                })]
+            },
+        },
+        Migration {
+            version: 9,
+            description: "hash plaintext view passwords",
+            steps: |_| vec![Step::HashViewPasswords],
+        },
+        Migration {
+            version: 10,
+            description: "add trash column",
+            steps: |db| {
+                let d = db.dialect();
+                let t = &db.options.table_pastes;
+                vec![Step::Sql(format!(
+                    "ALTER TABLE {} ADD COLUMN {} BIGINT NOT NULL DEFAULT 0",
+                    d.ident(&t.table_name),
+                    d.ident(&t.trashed_at)
+                ))]
+            },
+        },
+        Migration {
+            version: 11,
+            description: "create attachments table",
+            steps: |db| {
+                let d = db.dialect();
+                let t = d.ident(&db.options.table_attachments.table_name);
+                vec![Step::Sql(match d {
+                    // mysql can't put TEXT columns in a unique key
+                    Dialect::MySql => format!(
+                        "CREATE TABLE IF NOT EXISTS {t} (
+                            id             VARCHAR(255) NOT NULL PRIMARY KEY,
+                            url            VARCHAR(255) NOT NULL,
+                            name           VARCHAR(255) NOT NULL,
+                            content_type   VARCHAR(255) NOT NULL,
+                            size           BIGINT       NOT NULL,
+                            date_published BIGINT       NOT NULL,
+                            UNIQUE (url, name)
+                        )"
+                    ),
+                    _ => format!(
+                        "CREATE TABLE IF NOT EXISTS {t} (
+                            id             TEXT   NOT NULL PRIMARY KEY,
+                            url            TEXT   NOT NULL,
+                            name           TEXT   NOT NULL,
+                            content_type   TEXT   NOT NULL,
+                            size           BIGINT NOT NULL,
+                            date_published BIGINT NOT NULL,
+                            UNIQUE (url, name)
+                        )"
+                    ),
+                })]
+            },
+        },
+        Migration {
+            version: 12,
+            description: "add indexed template column",
+            steps: |db| {
+                let d = db.dialect();
+                let t = &db.options.table_pastes;
+                let metadata = d.ident(&t.metadata);
+                let template_from_metadata = match d {
+                    Dialect::Sqlite => format!("json_extract({metadata}, '$.template')"),
+                    Dialect::MySql => {
+                        format!("JSON_UNQUOTE(JSON_EXTRACT({metadata}, '$.template'))")
+                    }
+                    Dialect::Postgres => format!("CAST({metadata} AS json)->>'template'"),
+                };
+
+                vec![
+                    Step::Sql(format!(
+                        "ALTER TABLE {} ADD COLUMN {} TEXT",
+                        d.ident(&t.table_name),
+                        d.ident(&t.template)
+                    )),
+                    // templates themselves (`@`) aren't derived from anything
+                    Step::Sql(format!(
+                        "UPDATE {} SET {} = COALESCE(NULLIF({}, '@'), '')",
+                        d.ident(&t.table_name),
+                        d.ident(&t.template),
+                        template_from_metadata
+                    )),
+                    Step::Sql(format!(
+                        "CREATE INDEX {} ON {} ({})",
+                        d.ident(&format!("{}_{}", t.table_name, t.template)),
+                        d.ident(&t.table_name),
+                        // mysql can only index a prefix of TEXT columns
+                        if d == Dialect::MySql {
+                            format!("{}(191)", d.ident(&t.template))
+                        } else {
+                            d.ident(&t.template)
+                        }
+                    )),
+                ]
+            },
+        },
+        Migration {
+            version: 13,
+            description: "create collections tables",
+            steps: |db| {
+                let d = db.dialect();
+                let t = d.ident(&db.options.table_collections.table_name);
+                let m = d.ident(&db.options.table_collections.members_table_name);
+                vec![
+                    Step::Sql(match d {
+                        // mysql can't put TEXT columns in a unique key
+                        Dialect::MySql => format!(
+                            "CREATE TABLE IF NOT EXISTS {t} (
+                                id             VARCHAR(255) NOT NULL PRIMARY KEY,
+                                name           VARCHAR(255) NOT NULL UNIQUE,
+                                title          TEXT         NOT NULL,
+                                description    TEXT         NOT NULL,
+                                owner          TEXT         NOT NULL,
+                                password       TEXT         NOT NULL,
+                                date_published BIGINT       NOT NULL,
+                                date_edited    BIGINT       NOT NULL
+                            )"
+                        ),
+                        _ => format!(
+                            "CREATE TABLE IF NOT EXISTS {t} (
+                                id             TEXT   NOT NULL PRIMARY KEY,
+                                name           TEXT   NOT NULL UNIQUE,
+                                title          TEXT   NOT NULL,
+                                description    TEXT   NOT NULL,
+                                owner          TEXT   NOT NULL,
+                                password       TEXT   NOT NULL,
+                                date_published BIGINT NOT NULL,
+                                date_edited    BIGINT NOT NULL
+                            )"
+                        ),
+                    }),
+                    Step::Sql(match d {
+                        Dialect::MySql => format!(
+                            "CREATE TABLE IF NOT EXISTS {m} (
+                                collection VARCHAR(255) NOT NULL,
+                                url        VARCHAR(255) NOT NULL,
+                                position   BIGINT       NOT NULL,
+                                UNIQUE (collection, url)
+                            )"
+                        ),
+                        _ => format!(
+                            "CREATE TABLE IF NOT EXISTS {m} (
+                                collection TEXT   NOT NULL,
+                                url        TEXT   NOT NULL,
+                                position   BIGINT NOT NULL,
+                                UNIQUE (collection, url)
+                            )"
+                        ),
+                    }),
+                    Step::Sql(format!(
+                        "CREATE INDEX {} ON {m} (url)",
+                        d.ident(&format!(
+                            "{}_url",
+                            db.options.table_collections.members_table_name
+                        ))
+                    )),
+                ]
+            },
+        },
+        Migration {
+            version: 14,
+            description: "create tags table",
+            steps: |db| {
+                let d = db.dialect();
+                let t = &db.options.table_tags.table_name;
+                vec![
+                    Step::Sql(match d {
+                        // mysql can only index a prefix of TEXT columns
+                        Dialect::MySql => format!(
+                            "CREATE TABLE IF NOT EXISTS {} (
+                                url VARCHAR(255) NOT NULL,
+                                tag VARCHAR(255) NOT NULL
+                            )",
+                            d.ident(t)
+                        ),
+                        _ => format!(
+                            "CREATE TABLE IF NOT EXISTS {} (
+                                url TEXT NOT NULL,
+                                tag TEXT NOT NULL
+                            )",
+                            d.ident(t)
+                        ),
+                    }),
+                    Step::Sql(format!(
+                        "CREATE INDEX {} ON {} (tag)",
+                        d.ident(&format!("{t}_tag")),
+                        d.ident(t)
+                    )),
+                    Step::Sql(format!(
+                        "CREATE INDEX {} ON {} (url)",
+                        d.ident(&format!("{t}_url")),
+                        d.ident(t)
+                    )),
+                ]
+            },
+        },
+    ]
+}
+
+/// The latest schema version known to this version of pastemd
+pub fn latest_version() -> i64 {
+    all().iter().map(|m| m.version).max().unwrap_or(0)
+}
+
+impl Database {
+    /// Get the schema version of the database (`0` if no migrations have been applied)
+    pub async fn schema_version(&self) -> Result<i64, MigrationError> {
+        let c = &self.base.db.client;
+
+        // make sure the migrations table exists
+        if sqlquery(&format!(
+            "CREATE TABLE IF NOT EXISTS {} (
+                version     TEXT,
+                description TEXT,
+                applied_at  TEXT
+            )",
+            self.dialect()
+                .ident(&self.options.table_migrations.table_name)
+        ))
+        .execute(c)
+        .await
+        .is_err()
+        {
+            return Err(MigrationError::Other);
+        }
+
+        // pull from database
+        let query =
+            Query::select(self.dialect(), &self.options.table_migrations.table_name).build();
+
+        let rows = match sqlquery(&query).fetch_all(c).await {
+            Ok(r) => r,
+            Err(_) => return Err(MigrationError::Other),
+        };
+
+        let mut version: i64 = 0;
+
+        for row in rows {
+            let res = self.base.textify_row(row).data;
+
+            match res.get("version").unwrap().parse::<i64>() {
+                Ok(v) => version = version.max(v),
+                Err(_) => return Err(MigrationError::Other),
+            }
+        }
+
+        Ok(version)
+    }
+
+    /// Make sure the database schema is exactly the latest version, without changing it
+    pub async fn check_schema(&self) -> Result<i64, MigrationError> {
+        let found = match self.schema_version().await {
+            Ok(v) => v,
+            Err(e) => return Err(e),
+        };
+
+        let supported = latest_version();
+
+        if found > supported {
+            return Err(MigrationError::NewerSchema { found, supported });
+        } else if found < supported {
+            return Err(MigrationError::Pending { found, supported });
+        }
+
+        Ok(found)
+    }
+
+    /// Apply all pending migrations, in order
+    ///
+    /// # Returns
+    /// * Result containing the versions which were applied
+    pub async fn migrate(&self) -> Result<Vec<i64>, MigrationError> {
+        let found = match self.schema_version().await {
+            Ok(v) => v,
+            Err(e) => return Err(e),
+        };
+
+        let supported = latest_version();
+
+        if found > supported {
+            // refuse to touch a schema we don't understand
+            return Err(MigrationError::NewerSchema { found, supported });
+        }
+
+        // apply
+        let c = &self.base.db.client;
+        let mut applied: Vec<i64> = Vec::new();
+        let mut rebuild_search_index: bool = false;
+
+        for migration in all().into_iter().filter(|m| m.version > found) {
+            let mut tx = match c.begin().await {
+                Ok(tx) => tx,
+                Err(e) => {
+                    return Err(MigrationError::Failed {
+                        version: migration.version,
+                        error: e.to_string(),
+                    })
+                }
+            };
+
+            for step in (migration.steps)(self) {
+                match step {
+                    Step::Sql(query) => {
+                        if let Err(e) = sqlquery(&query).execute(&mut *tx).await {
+                            return Err(MigrationError::Failed {
+                                version: migration.version,
+                                error: e.to_string(),
+                            });
+                        }
+                    }
+                    Step::RebuildSearchIndex => rebuild_search_index = true,
+                    Step::HashViewPasswords => {
+                        // only the url and metadata columns are read, since later migrations
+                        // might not have added the rest yet
+                        let t = &self.options.table_pastes;
+                        let query = Query::select(self.dialect(), &t.table_name)
+                            .columns(&[&t.url, &t.metadata])
+                            .build();
+
+                        let rows = match sqlquery(&query).fetch_all(&mut *tx).await {
+                            Ok(r) => r,
+                            Err(e) => {
+                                return Err(MigrationError::Failed {
+                                    version: migration.version,
+                                    error: e.to_string(),
+                                })
+                            }
+                        };
+
+                        let query = Query::update(self.dialect(), &t.table_name)
+                            .column(&t.metadata)
+                            .filter(&t.url, "=")
+                            .build();
+
+                        for row in rows {
+                            let res = self.base.textify_row(row).data;
+                            let url = res.get(&t.url).unwrap().to_string();
+
+                            let mut metadata = match serde_json::from_str::<serde_json::Value>(
+                                res.get(&t.metadata).unwrap(),
+                            ) {
+                                Ok(m) => m,
+                                Err(_) => continue,
+                            };
+
+                            let view_password = match metadata["view_password"].as_str() {
+                                Some(p) if !p.is_empty() && !p.starts_with("$argon2") => {
+                                    p.to_string()
+                                }
+                                _ => continue,
+                            };
+
+                            metadata["view_password"] =
+                                serde_json::Value::String(password::hash(&view_password).await);
+
+                            if let Err(e) = sqlquery(&query)
+                                .bind::<&String>(&metadata.to_string())
+                                .bind::<&String>(&url)
+                                .execute(&mut *tx)
+                                .await
+                            {
+                                return Err(MigrationError::Failed {
+                                    version: migration.version,
+                                    error: e.to_string(),
+                                });
+                            }
+
+                            // remove from cache
+                            self.base
+                                .cachedb
+                                .remove(format!("{}:{}", t.prefix, url))
+                                .await;
+                        }
+                    }
+                }
+            }
+
+            // record version
+            let query = Query::insert(self.dialect(), &self.options.table_migrations.table_name)
+                .columns(&["version", "description", "applied_at"])
+                .build();
+
+            if let Err(e) = sqlquery(&query)
+                .bind::<&String>(&migration.version.to_string())
+                .bind::<&String>(&migration.description.to_string())
+                .bind::<&String>(&utility::unix_epoch_timestamp().to_string())
+                .execute(&mut *tx)
+                .await
+            {
+                return Err(MigrationError::Failed {
+                    version: migration.version,
+                    error: e.to_string(),
+                });
+            }
+
+            if let Err(e) = tx.commit().await {
+                return Err(MigrationError::Failed {
+                    version: migration.version,
+                    error: e.to_string(),
+                });
+            }
+
+            applied.push(migration.version);
+        }
+
+        // run deferred steps
+        if rebuild_search_index {
+            if let Err(e) = self.rebuild_search_index().await {
+                return Err(MigrationError::Failed {
+                    version: supported,
+                    error: e.to_string(),
+                });
+            }
+        }
+
+        // return
+        Ok(applied)
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::{all, Step};
+    use crate::database::{Database, ServerOptions};
+    use dorsal::query as sqlquery;
+
+    #[tokio::test]
+    async fn duplicate_urls_are_moved_aside() {
+        let db = Database::in_memory(ServerOptions::default()).await;
+        let c = &db.base.db.client;
+
+        // apply the schema from before migration 6
+        for migration in all().into_iter().filter(|m| m.version <= 5) {
+            for step in (migration.steps)(&db) {
+                if let Step::Sql(query) = step {
+                    sqlquery(&query).execute(c).await.unwrap();
+                }
+            }
+        }
+
+        db.schema_version().await.unwrap();
+
+        for version in 1..=5 {
+            sqlquery(&format!(
+                "INSERT INTO \"{}\" VALUES (?, '', '0')",
+                db.options.table_migrations.table_name
+            ))
+            .bind::<String>(version.to_string())
+            .execute(c)
+            .await
+            .unwrap();
+        }
+
+        // the oldest paste keeps the url, ties go to the lowest id
+        for (url, id, date_published) in [
+            ("dup", "b", "200"),
+            ("dup", "a", "100"),
+            ("dup", "c", "100"),
+            ("other", "d", "300"),
+        ] {
+            sqlquery(
+                "INSERT INTO \"pastes\" (url, id, password, date_published, date_edited, content, metadata)
+                VALUES (?, ?, '', ?, ?, '', '{}')",
+            )
+            .bind::<&str>(url)
+            .bind::<&str>(id)
+            .bind::<&str>(date_published)
+            .bind::<&str>(date_published)
+            .execute(c)
+            .await
+            .unwrap();
+        }
+
+        assert_eq!(db.migrate().await.unwrap().first(), Some(&6));
+
+        let rows = sqlquery("SELECT url, id FROM \"pastes\" ORDER BY id")
+            .fetch_all(c)
+            .await
+            .unwrap();
+
+        let pastes: Vec<(String, String)> = rows
+            .into_iter()
+            .map(|row| {
+                let res = db.base.textify_row(row).data;
+                (res["url"].clone(), res["id"].clone())
+            })
+            .collect();
+
+        assert_eq!(
+            pastes,
+            [("dup", "a"), ("dup-b", "b"), ("dup-c", "c"), ("other", "d")]
+                .map(|(url, id)| (url.to_string(), id.to_string()))
+        );
+    }
+}
@@ -6,6 +6,7 @@ use axum::{
 
 use serde::{Deserialize, Serialize};
 use dorsal::DefaultReturn;
+use std::collections::HashMap;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Paste {
@@ -16,10 +17,32 @@ pub struct Paste {
     pub date_published: u128,
     pub date_edited: u128,
     pub metadata: PasteMetadata,
+    /// The time the paste expires at (`0` = never)
+    #[serde(default)]
+    pub expires_at: u128,
+    /// The number of views the paste is deleted after (`0` = unlimited)
+    #[serde(default)]
+    pub max_views: i32,
+    /// The time the paste was moved to the trash (`0` = not trashed)
+    #[serde(default)]
+    pub trashed_at: u128,
+}
+
+impl Paste {
+    /// Check if the paste has passed its `expires_at` time
+    pub fn is_expired(&self) -> bool {
+        (self.expires_at != 0) && (self.expires_at <= dorsal::utility::unix_epoch_timestamp())
+    }
+
+    /// Check if the paste is in the trash
+    pub fn is_trashed(&self) -> bool {
+        self.trashed_at != 0
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 /// All of these fields are
+#[derive(Default)]
 pub struct PasteMetadata {
     /// Paste page title
     #[serde(default)]
@@ -33,12 +56,22 @@ pub struct PasteMetadata {
     /// Paste favicon link
     #[serde(default, deserialize_with = "dumb_property")]
     pub favicon: String,
-    /// Paste view password (can be disabled)
+    /// Paste view password hash (can be disabled)
+    ///
+    /// Never send this to clients, use [`PasteMetadata::redacted`] instead. When editing
+    /// metadata, [`VIEW_PASSWORD_UNCHANGED`] keeps the current view password and anything
+    /// else (besides a blank value) replaces it.
     #[serde(default, deserialize_with = "dumb_property")]
     pub view_password: String,
     /// Paste owner username
+    ///
+    /// Only changed by claiming or transferring the paste, metadata edits keep it.
     #[serde(default)]
     pub owner: String,
+    /// The username the owner is transferring the paste to, until they accept it (see
+    /// [`PasteStore::accept_transfer_by_url`](crate::store::PasteStore::accept_transfer_by_url))
+    #[serde(default)]
+    pub pending_owner: String,
     /// Paste template settings
     ///
     /// * blank/no value = not a template and not using a template
@@ -46,6 +79,49 @@ pub struct PasteMetadata {
     /// * anything else = the URL of the template paste this paste is derived from
     #[serde(default)]
     pub template: String,
+    /// If the template this paste is derived from has been deleted
+    ///
+    /// [`PasteMetadata::template`] keeps the URL of the deleted template, so it can still
+    /// be shown, but the paste is no longer counted as one of its forks.
+    #[serde(default)]
+    pub orphaned: bool,
+    /// The `date_edited` of the template when this paste was derived from it (or last
+    /// synced with it, see [`PasteStore::sync_template_by_url`](crate::store::PasteStore::sync_template_by_url))
+    ///
+    /// `0` if it isn't known (pastes derived from a template before this was recorded).
+    #[serde(default)]
+    pub template_revision: u128,
+    /// Tags used to categorize the paste (normalized when the metadata is saved)
+    ///
+    /// Tags are lowercase and can only contain letters, digits, `-` and `_`.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// The variables filled in when this paste is used as a template
+    ///
+    /// Every `{{ name }}` in the content is replaced by the value given for `name` in
+    /// [`PasteClone::values`].
+    #[serde(default)]
+    pub variables: Vec<TemplateVariable>,
+    /// The values the variables of [`PasteMetadata::template`] were filled in with when this
+    /// paste was derived from it (defaults included)
+    ///
+    /// Changes to the template are filled in with the same values before they're merged
+    /// into the paste.
+    #[serde(default)]
+    pub template_values: HashMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+/// A placeholder in the content of a template
+pub struct TemplateVariable {
+    /// The name of the variable (letters, digits, `_` and `-`)
+    pub name: String,
+    /// The value used when none is given
+    #[serde(default)]
+    pub default: String,
+    /// If a (non-blank) value must be given when there's no default
+    #[serde(default)]
+    pub required: bool,
 }
 
 impl From<Paste> for PasteMetadata {
@@ -53,54 +129,277 @@ impl From<Paste> for PasteMetadata {
     fn from(value: Paste) -> Self {
         Self {
             template: value.url,
+            template_revision: value.date_edited,
             ..Default::default()
         }
     }
 }
 
+/// The placeholder which stands in for a set view password in [`PasteMetadata::redacted`]
+pub const VIEW_PASSWORD_UNCHANGED: &str = "********";
+
+impl PasteMetadata {
+    /// Get the URL of the template this paste is derived from (blank if this paste is a
+    /// template, isn't derived from one, or its template has been deleted)
+    pub fn template_source(&self) -> &str {
+        if (self.template == "@") | self.orphaned {
+            return "";
+        }
+
+        &self.template
+    }
+
+    /// Replace the view password hash with [`VIEW_PASSWORD_UNCHANGED`] (if it is set)
+    /// so the metadata can be sent to clients
+    pub fn redacted(mut self) -> Self {
+        if !self.view_password.is_empty() {
+            self.view_password = VIEW_PASSWORD_UNCHANGED.to_string();
+        }
+
+        self
+    }
+}
+
 fn dumb_property<'de, D>(deserializer: D) -> Result<String, D::Error>
 where
     D: serde::Deserializer<'de>,
 {
     let opt = Option::deserialize(deserializer)?;
-    Ok(opt.unwrap_or_else(|| String::default()))
+    Ok(opt.unwrap_or_else(String::default))
 }
 
-impl Default for PasteMetadata {
-    fn default() -> Self {
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PublicPaste {
+    pub url: String,
+    pub content: String,
+    pub date_published: u128,
+    pub date_edited: u128,
+    pub metadata: PasteMetadata,
+    pub expires_at: u128,
+    pub max_views: i32,
+}
+
+impl From<Paste> for PublicPaste {
+    fn from(value: Paste) -> Self {
         Self {
-            title: String::new(),
-            description: String::new(),
-            theme_color: String::new(),
-            favicon: String::new(),
-            view_password: String::new(),
-            owner: String::new(),
-            template: String::new(),
+            url: value.url,
+            content: value.content,
+            date_published: value.date_published,
+            date_edited: value.date_edited,
+            metadata: value.metadata.redacted(),
+            expires_at: value.expires_at,
+            max_views: value.max_views,
         }
     }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct PublicPaste {
+/// A snapshot of a paste's content, taken right before an edit replaced it
+pub struct PasteRevision {
+    pub id: String,
+    /// The url of the paste this revision belongs to
     pub url: String,
+    /// The content of the paste before the edit
     pub content: String,
+    /// The username of the user who made the edit (blank if anonymous)
+    pub actor: String,
+    /// The time the edit was made
+    pub timestamp: u128,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+/// A file attached to a paste (its contents are kept in a [`BlobStore`](crate::blob::BlobStore))
+pub struct PasteAttachment {
+    /// The id of the attachment (also the key of its blob)
+    pub id: String,
+    /// The url of the paste this attachment belongs to
+    pub url: String,
+    /// The file name of the attachment, unique within its paste
+    pub name: String,
+    /// The MIME type of the attachment
+    pub content_type: String,
+    /// The size (in bytes) of the attachment
+    pub size: i64,
+    /// The time the attachment was uploaded
+    pub date_published: u128,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+/// A single full-text search match
+pub struct PasteSearchResult {
+    pub url: String,
+    pub title: String,
+    pub description: String,
+    /// The beginning of the paste's content
+    pub snippet: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+/// A lightweight view of a paste used in listings
+pub struct PasteSummary {
+    pub url: String,
+    pub title: String,
+    pub description: String,
     pub date_published: u128,
     pub date_edited: u128,
-    pub metadata: PasteMetadata,
+    /// If the paste requires a view password
+    pub private: bool,
 }
 
-impl From<Paste> for PublicPaste {
+impl From<Paste> for PasteSummary {
     fn from(value: Paste) -> Self {
         Self {
             url: value.url,
-            content: value.content,
+            title: value.metadata.title,
+            description: value.metadata.description,
             date_published: value.date_published,
             date_edited: value.date_edited,
-            metadata: value.metadata,
+            private: !value.metadata.view_password.is_empty(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+/// A single page of a paste listing
+pub struct PasteList {
+    pub pastes: Vec<PasteSummary>,
+    /// The cursor to request the next page with (blank if this is the last page)
+    pub next_cursor: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+/// A page of the pastes derived from a template
+pub struct PasteForks {
+    /// The number of pastes derived from the template (including private ones)
+    pub count: i64,
+    /// The public pastes of this page
+    pub forks: Vec<PasteSummary>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+/// A page of the public pastes carrying a tag
+pub struct PasteTagged {
+    pub tag: String,
+    /// The number of public pastes carrying the tag
+    pub count: i64,
+    /// The pastes of this page
+    pub pastes: Vec<PasteSummary>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+/// A tag along with the number of public pastes carrying it
+pub struct TagCount {
+    pub tag: String,
+    pub count: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+/// A named, ordered set of pastes
+pub struct Collection {
+    pub id: String,
+    /// The unique name of the collection (used in its url)
+    pub name: String,
+    pub title: String,
+    pub description: String,
+    /// Collection owner username (blank if anonymous)
+    pub owner: String,
+    /// Hashed edit password
+    pub password: String,
+    /// The urls of the pastes in the collection, in order
+    pub pastes: Vec<String>,
+    pub date_published: u128,
+    pub date_edited: u128,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+/// A [`Collection`] without its password, listing only the pastes anyone can view
+pub struct PublicCollection {
+    pub name: String,
+    pub title: String,
+    pub description: String,
+    pub owner: String,
+    /// The public pastes of the collection, in order
+    pub pastes: Vec<PasteSummary>,
+    pub date_published: u128,
+    pub date_edited: u128,
+}
+
+impl PublicCollection {
+    pub fn new(collection: Collection, pastes: Vec<PasteSummary>) -> Self {
+        Self {
+            name: collection.name,
+            title: collection.title,
+            description: collection.description,
+            owner: collection.owner,
+            pastes,
+            date_published: collection.date_published,
+            date_edited: collection.date_edited,
         }
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+/// View analytics of a paste
+pub struct PasteStats {
+    /// Total view count
+    pub views: i32,
+    /// Views per day (oldest first), only days with views are included
+    pub days: Vec<PasteStatsDay>,
+    /// The hosts which referred the most views (most views first)
+    pub referrers: Vec<PasteStatsReferrer>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PasteStatsDay {
+    /// The start of the day (UTC)
+    pub day: u128,
+    pub views: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PasteStatsReferrer {
+    /// The referrer host (blank for direct views)
+    pub host: String,
+    pub views: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+/// The field paste listings are sorted by (newest first)
+#[derive(Default)]
+pub enum PasteSort {
+    DatePublished,
+    #[default]
+    DateEdited,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+/// What an import does with a paste whose url is already taken
+#[derive(Default)]
+pub enum ImportConflict {
+    /// Keep the existing paste and leave out the imported one
+    #[default]
+    Skip,
+    /// Replace the existing paste with the imported one
+    Overwrite,
+    /// Import the paste under a new url
+    Rename,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+/// The outcome of an import
+pub struct ImportSummary {
+    /// Pastes which were imported under their own url
+    pub imported: usize,
+    /// Pastes which were left out because their url was taken
+    pub skipped: usize,
+    /// Existing pastes which were replaced
+    pub overwritten: usize,
+    /// `(old url, new url)` of pastes which were imported under a new url
+    pub renamed: Vec<(String, String)>,
+}
+
 // props
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -113,6 +412,12 @@ pub struct PasteCreate {
     /// The paste edit password
     #[serde(default)]
     pub password: String,
+    /// The time the paste expires at (`0` = never)
+    #[serde(default)]
+    pub expires_at: u128,
+    /// The number of views the paste is deleted after (`0` = unlimited)
+    #[serde(default)]
+    pub max_views: i32,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -125,11 +430,15 @@ pub struct PasteClone {
     /// The paste edit password
     #[serde(default)]
     pub password: String,
+    /// Values of the template's [variables](PasteMetadata::variables) by name
+    #[serde(default)]
+    pub values: HashMap<String, String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct PasteDelete {
     /// The password of the paste
+    #[serde(default)]
     pub password: String,
 }
 
@@ -155,24 +464,130 @@ pub struct PasteEditMetadata {
     pub metadata: PasteMetadata,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PasteEditExpiry {
+    /// The password of the paste
+    #[serde(default)]
+    pub password: String,
+    /// The updated expiry time of the paste (`0` = never)
+    #[serde(default)]
+    pub expires_at: u128,
+    /// The updated view limit of the paste (`0` = unlimited)
+    #[serde(default)]
+    pub max_views: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PasteSyncTemplate {
+    /// The password of the paste
+    #[serde(default)]
+    pub password: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+/// The result of merging the changes of a template into a paste derived from it
+pub struct TemplateSync {
+    /// If the changes of the template conflicted with changes of the paste (nothing is saved then)
+    pub conflicted: bool,
+    /// The merged content (with conflict markers if there were conflicts)
+    pub content: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PasteClaim {
+    /// The password of the paste
+    pub password: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PasteTransfer {
+    /// The username to transfer the paste to (blank to cancel a pending transfer)
+    #[serde(default)]
+    pub username: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PasteRestore {
+    /// The password of the paste
+    #[serde(default)]
+    pub password: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CollectionCreate {
+    /// The collection name
+    #[serde(default)]
+    pub name: String,
+    /// The title of the collection
+    #[serde(default)]
+    pub title: String,
+    /// The description of the collection
+    #[serde(default)]
+    pub description: String,
+    /// The collection edit password
+    #[serde(default)]
+    pub password: String,
+    /// The urls of the pastes in the collection, in order
+    #[serde(default)]
+    pub pastes: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CollectionEdit {
+    /// The password of the collection
+    #[serde(default)]
+    pub password: String,
+    /// The updated title of the collection
+    #[serde(default)]
+    pub title: String,
+    /// The updated description of the collection
+    #[serde(default)]
+    pub description: String,
+    /// The updated password of the collection
+    #[serde(default)]
+    pub new_password: String,
+    /// The updated urls of the pastes in the collection, in order
+    #[serde(default)]
+    pub pastes: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CollectionAddPaste {
+    /// The password of the collection
+    #[serde(default)]
+    pub password: String,
+    /// The url of the paste to add to the end of the collection
+    pub url: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CollectionDelete {
+    /// The password of the collection
+    #[serde(default)]
+    pub password: String,
+}
+
 /// General API errors
+#[derive(Debug)]
 pub enum PasteError {
     PasswordIncorrect,
     AlreadyExists,
     ValueError,
     NotFound,
+    RateLimited,
     Other,
 }
 
-impl PasteError {
-    pub fn to_string(&self) -> String {
+impl std::fmt::Display for PasteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         use crate::model::PasteError::*;
         match self {
-            PasswordIncorrect => String::from("The given password is invalid."),
-            AlreadyExists => String::from("A paste with this URL already exists."),
-            ValueError => String::from("One of the field values given is invalid."),
-            NotFound => String::from("No paste with this URL has been found."),
-            _ => String::from("An unspecified error has occured"),
+            PasswordIncorrect => write!(f, "The given password is invalid."),
+            AlreadyExists => write!(f, "A paste with this URL already exists."),
+            ValueError => write!(f, "One of the field values given is invalid."),
+            NotFound => write!(f, "No paste with this URL has been found."),
+            RateLimited => write!(f, "Too many requests, please try again later."),
+            _ => write!(f, "An unspecified error has occured"),
         }
     }
 }
@@ -208,6 +623,15 @@ impl IntoResponse for PasteError {
                 }),
             )
                 .into_response(),
+            RateLimited => (
+                StatusCode::TOO_MANY_REQUESTS,
+                Json(DefaultReturn::<u16> {
+                    success: false,
+                    message: self.to_string(),
+                    payload: 429,
+                }),
+            )
+                .into_response(),
             _ => (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 Json(DefaultReturn::<u16> {
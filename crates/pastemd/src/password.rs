@@ -0,0 +1,240 @@
+//! Edit password hashing
+//!
+//! Passwords are hashed with Argon2id and a random per-paste salt, stored as PHC strings.
+//! Pastes created before this stored an unsalted `dorsal::utility::hash`; those hashes are
+//! still accepted and should be replaced with [`hash`] once they've been verified.
+//!
+//! View passwords are hashed the same way, and are checked once through [`verify_view`]
+//! before handing out a signed unlock token (see [`sign_unlock`]).
+//!
+//! Argon2id is slow on purpose, so hashing and verifying run on tokio's blocking thread pool
+//! instead of holding up the async workers.
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use dorsal::utility;
+use hmac::{Hmac, Mac};
+use rand_core::OsRng;
+use sha2::Sha256;
+
+/// The result of checking a password against a stored hash
+#[derive(Debug, PartialEq)]
+pub enum Verification {
+    /// The password doesn't match
+    Invalid,
+    /// The password matches
+    Valid,
+    /// The password matches a legacy hash, which should be replaced
+    ValidLegacy,
+}
+
+/// Run `f` on the blocking thread pool
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    tokio::task::spawn_blocking(f).await.unwrap()
+}
+
+/// Hash a password with Argon2id and a new random salt
+pub async fn hash(password: &str) -> String {
+    let password = password.to_string();
+    blocking(move || hash_blocking(&password)).await
+}
+
+fn hash_blocking(password: &str) -> String {
+    let salt = SaltString::generate(&mut OsRng);
+
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .unwrap()
+        .to_string()
+}
+
+/// Check a password against a stored hash (in constant time)
+pub async fn verify(password: &str, stored: &str) -> Verification {
+    // argon2 hashes are PHC strings
+    if stored.starts_with("$argon2") {
+        let (password, stored) = (password.to_string(), stored.to_string());
+        return blocking(move || verify_phc(&password, &stored)).await;
+    }
+
+    // legacy hash
+    if constant_time_eq(
+        utility::hash(password.to_string()).as_bytes(),
+        stored.as_bytes(),
+    ) {
+        Verification::ValidLegacy
+    } else {
+        Verification::Invalid
+    }
+}
+
+/// Check a view password against a stored view password hash
+///
+/// View passwords never had a legacy hash (plaintext ones are hashed by migration 9),
+/// so anything which isn't a PHC string is rejected.
+pub async fn verify_view(password: &str, stored: &str) -> Verification {
+    if !stored.starts_with("$argon2") {
+        return Verification::Invalid;
+    }
+
+    let (password, stored) = (password.to_string(), stored.to_string());
+    blocking(move || verify_phc(&password, &stored)).await
+}
+
+/// Check a password against an argon2 PHC string
+fn verify_phc(password: &str, stored: &str) -> Verification {
+    let parsed = match PasswordHash::new(stored) {
+        Ok(h) => h,
+        Err(_) => return Verification::Invalid,
+    };
+
+    match Argon2::default().verify_password(password.as_bytes(), &parsed) {
+        Ok(_) => Verification::Valid,
+        Err(_) => Verification::Invalid,
+    }
+}
+
+/// Create a signed unlock token for a paste with a view password
+///
+/// The token is bound to the paste's current view password hash, so changing the view
+/// password invalidates every token handed out for the old one.
+///
+/// # Arguments
+/// * `secret` - the server's unlock signing secret
+/// * `url` - the url of the paste
+/// * `stored` - the paste's view password hash
+/// * `expires_at` - the time the token stops being accepted
+pub fn sign_unlock(secret: &str, url: &str, stored: &str, expires_at: u128) -> String {
+    format!(
+        "{expires_at}.{}",
+        unlock_signature(secret, url, stored, expires_at)
+    )
+}
+
+/// Check a token created by [`sign_unlock`]
+pub fn verify_unlock(secret: &str, url: &str, stored: &str, token: &str) -> bool {
+    let (expires_at, signature) = match token.split_once(".") {
+        Some(t) => t,
+        None => return false,
+    };
+
+    let expires_at = match expires_at.parse::<u128>() {
+        Ok(e) => e,
+        Err(_) => return false,
+    };
+
+    if expires_at <= utility::unix_epoch_timestamp() {
+        return false;
+    }
+
+    constant_time_eq(
+        unlock_signature(secret, url, stored, expires_at).as_bytes(),
+        signature.as_bytes(),
+    )
+}
+
+/// HMAC-SHA256 of an unlock token's fields, hex encoded
+fn unlock_signature(secret: &str, url: &str, stored: &str, expires_at: u128) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(format!("{url}\n{expires_at}\n{stored}").as_bytes());
+
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Compare two byte strings without stopping at the first difference
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hash, sign_unlock, verify, verify_unlock, verify_view, Verification};
+    use dorsal::utility;
+
+    #[tokio::test]
+    async fn hash_and_verify() {
+        let stored = hash("password").await;
+
+        assert!(stored.starts_with("$argon2id"));
+        assert_ne!(stored, hash("password").await); // salted
+        assert_eq!(verify("password", &stored).await, Verification::Valid);
+        assert_eq!(verify("wrong", &stored).await, Verification::Invalid);
+    }
+
+    #[tokio::test]
+    async fn legacy_hash() {
+        let legacy = utility::hash("password".to_string());
+
+        // legacy hashes are accepted, but flagged so they can be upgraded
+        assert_eq!(verify("password", &legacy).await, Verification::ValidLegacy);
+        assert_eq!(verify("wrong", &legacy).await, Verification::Invalid);
+
+        // the upgraded hash is a regular argon2 hash
+        let upgraded = hash("password").await;
+        assert_eq!(verify("password", &upgraded).await, Verification::Valid);
+    }
+
+    #[tokio::test]
+    async fn view_passwords_are_never_plaintext() {
+        assert_eq!(
+            verify_view("password", "password").await,
+            Verification::Invalid
+        );
+        assert_eq!(
+            verify_view("password", &utility::hash("password".to_string())).await,
+            Verification::Invalid
+        );
+
+        let stored = hash("password").await;
+        assert_eq!(verify_view("password", &stored).await, Verification::Valid);
+    }
+
+    #[test]
+    fn unlock_tokens() {
+        let expires_at = utility::unix_epoch_timestamp() + 60_000;
+        let token = sign_unlock("secret", "hello", "stored", expires_at);
+
+        assert!(verify_unlock("secret", "hello", "stored", &token));
+
+        // bound to the secret, the paste and its view password
+        assert!(!verify_unlock("other", "hello", "stored", &token));
+        assert!(!verify_unlock("secret", "other", "stored", &token));
+        assert!(!verify_unlock("secret", "hello", "changed", &token));
+    }
+
+    #[test]
+    fn expired_unlock_tokens() {
+        let expires_at = utility::unix_epoch_timestamp() - 1;
+        let token = sign_unlock("secret", "hello", "stored", expires_at);
+
+        assert!(!verify_unlock("secret", "hello", "stored", &token));
+    }
+
+    #[test]
+    fn tampered_unlock_tokens() {
+        let expires_at = utility::unix_epoch_timestamp() + 60_000;
+        let token = sign_unlock("secret", "hello", "stored", expires_at);
+        let (_, signature) = token.split_once(".").unwrap();
+
+        // extending the expiry breaks the signature
+        let extended = format!("{}.{signature}", expires_at + 60_000);
+        assert!(!verify_unlock("secret", "hello", "stored", &extended));
+
+        // so does changing the signature
+        let mut forged = token.clone();
+        let last = forged.pop().unwrap();
+        forged.push(if last == '0' { '1' } else { '0' });
+        assert!(!verify_unlock("secret", "hello", "stored", &forged));
+
+        // and malformed tokens are rejected
+        assert!(!verify_unlock("secret", "hello", "stored", ""));
+        assert!(!verify_unlock("secret", "hello", "stored", signature));
+        assert!(!verify_unlock("secret", "hello", "stored", "soon.abc"));
+    }
+}
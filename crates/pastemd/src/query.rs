@@ -0,0 +1,474 @@
+//! Dialect-aware SQL query builder
+//!
+//! Statements are described once and rendered for the backend they run on: identifiers
+//! are quoted the way the backend expects and placeholders are numbered for Postgres.
+//! Parameters are bound in the order their columns and filters were added.
+
+/// The SQL dialect spoken by a database backend
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Dialect {
+    Sqlite,
+    MySql,
+    Postgres,
+}
+
+impl Dialect {
+    /// Get the dialect of a dorsal database type (`sqlite`, `mysql` or `postgres`)
+    pub fn from_type(_type: &str) -> Self {
+        match _type {
+            "mysql" => Dialect::MySql,
+            "postgres" => Dialect::Postgres,
+            _ => Dialect::Sqlite,
+        }
+    }
+
+    /// Quote an identifier (table or column name)
+    pub fn ident(&self, name: &str) -> String {
+        match self {
+            Dialect::MySql => format!("`{}`", name.replace('`', "``")),
+            _ => format!("\"{}\"", name.replace('"', "\"\"")),
+        }
+    }
+
+    /// The type columns are cast to when they're selected as text
+    pub fn text_type(&self) -> &'static str {
+        match self {
+            Dialect::MySql => "CHAR",
+            _ => "TEXT",
+        }
+    }
+
+    /// Rewrite the `?` placeholders in `sql` into the form this dialect expects
+    ///
+    /// Placeholders inside quoted identifiers or strings are left alone.
+    pub fn placeholders(&self, sql: &str) -> String {
+        if *self != Dialect::Postgres {
+            return sql.to_string();
+        }
+
+        let mut out = String::with_capacity(sql.len());
+        let mut quote: Option<char> = None;
+        let mut n = 0;
+
+        for c in sql.chars() {
+            match quote {
+                Some(q) if c == q => quote = None,
+                Some(_) => (),
+                None if (c == '"') | (c == '\'') | (c == '`') => quote = Some(c),
+                None if c == '?' => {
+                    n += 1;
+                    out.push_str(&format!("${n}"));
+                    continue;
+                }
+                None => (),
+            }
+
+            out.push(c);
+        }
+
+        out
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Kind {
+    Select,
+    Insert,
+    Update,
+    Delete,
+}
+
+/// A single SQL statement
+#[derive(Clone, Debug)]
+pub(crate) struct Query {
+    dialect: Dialect,
+    kind: Kind,
+    table: String,
+    columns: Vec<String>,
+    conditions: Vec<String>,
+    group: Vec<String>,
+    order: Vec<String>,
+    limit: bool,
+    offset: bool,
+    conflict: Option<(Vec<String>, String)>,
+}
+
+impl Query {
+    fn new(dialect: Dialect, kind: Kind, table: &str) -> Self {
+        Self {
+            dialect,
+            kind,
+            table: dialect.ident(table),
+            columns: Vec::new(),
+            conditions: Vec::new(),
+            group: Vec::new(),
+            order: Vec::new(),
+            limit: false,
+            offset: false,
+            conflict: None,
+        }
+    }
+
+    /// `SELECT ... FROM table` (selects `*` when no columns are added)
+    pub fn select(dialect: Dialect, table: &str) -> Self {
+        Self::new(dialect, Kind::Select, table)
+    }
+
+    /// `INSERT INTO table (...) VALUES (...)`
+    pub fn insert(dialect: Dialect, table: &str) -> Self {
+        Self::new(dialect, Kind::Insert, table)
+    }
+
+    /// `UPDATE table SET ...`
+    pub fn update(dialect: Dialect, table: &str) -> Self {
+        Self::new(dialect, Kind::Update, table)
+    }
+
+    /// `DELETE FROM table`
+    pub fn delete(dialect: Dialect, table: &str) -> Self {
+        Self::new(dialect, Kind::Delete, table)
+    }
+
+    /// Add a column; selected for `SELECT`, given a value for `INSERT` and `UPDATE`
+    pub fn column(mut self, name: &str) -> Self {
+        self.columns.push(self.dialect.ident(name));
+        self
+    }
+
+    /// Add several columns, see [`Query::column`]
+    pub fn columns(mut self, names: &[&str]) -> Self {
+        for name in names {
+            self = self.column(name);
+        }
+
+        self
+    }
+
+    /// Select a column cast to text, keeping its name
+    pub fn column_as_text(mut self, name: &str) -> Self {
+        let name = self.dialect.ident(name);
+        self.columns.push(format!(
+            "CAST({name} AS {}) AS {name}",
+            self.dialect.text_type()
+        ));
+        self
+    }
+
+    /// Select a raw expression
+    pub fn expr(mut self, expr: &str) -> Self {
+        self.columns.push(expr.to_string());
+        self
+    }
+
+    /// Only match rows where `column <op> ?`
+    pub fn filter(mut self, column: &str, op: &str) -> Self {
+        let condition = format!("{} {op} ?", self.dialect.ident(column));
+        self.conditions.push(condition);
+        self
+    }
+
+    /// Only match rows where the raw `condition` is true
+    ///
+    /// Identifiers in `condition` must already be quoted with [`Dialect::ident`].
+    pub fn filter_raw(mut self, condition: &str) -> Self {
+        self.conditions.push(format!("({condition})"));
+        self
+    }
+
+    /// Group the selected rows by `column`
+    pub fn group_by(mut self, column: &str) -> Self {
+        self.group.push(self.dialect.ident(column));
+        self
+    }
+
+    /// When the inserted row conflicts with an existing row on the unique `keys`,
+    /// add the inserted value of `column` to the existing row instead
+    pub fn on_conflict_add(mut self, keys: &[&str], column: &str) -> Self {
+        self.conflict = Some((
+            keys.iter().map(|k| self.dialect.ident(k)).collect(),
+            self.dialect.ident(column),
+        ));
+
+        self
+    }
+
+    /// Order the selected rows by `column`
+    pub fn order_by(mut self, column: &str, descending: bool) -> Self {
+        let order = format!(
+            "{} {}",
+            self.dialect.ident(column),
+            if descending { "DESC" } else { "ASC" }
+        );
+
+        self.order.push(order);
+        self
+    }
+
+    /// Order the selected rows by the raw `expr`
+    ///
+    /// Identifiers in `expr` must already be quoted with [`Dialect::ident`].
+    pub fn order_by_raw(mut self, expr: &str, descending: bool) -> Self {
+        self.order.push(format!(
+            "{expr} {}",
+            if descending { "DESC" } else { "ASC" }
+        ));
+        self
+    }
+
+    /// Limit the number of selected rows (bound as a parameter)
+    pub fn limit(mut self) -> Self {
+        self.limit = true;
+        self
+    }
+
+    /// Skip a number of selected rows (bound as a parameter)
+    pub fn offset(mut self) -> Self {
+        self.offset = true;
+        self
+    }
+
+    /// Render the statement
+    pub fn build(&self) -> String {
+        let mut sql = match self.kind {
+            Kind::Select => format!(
+                "SELECT {} FROM {}",
+                if self.columns.is_empty() {
+                    "*".to_string()
+                } else {
+                    self.columns.join(", ")
+                },
+                self.table
+            ),
+            Kind::Insert => format!(
+                "INSERT INTO {} ({}) VALUES ({})",
+                self.table,
+                self.columns.join(", "),
+                vec!["?"; self.columns.len()].join(", ")
+            ),
+            Kind::Update => format!(
+                "UPDATE {} SET {}",
+                self.table,
+                self.columns
+                    .iter()
+                    .map(|c| format!("{c} = ?"))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            Kind::Delete => format!("DELETE FROM {}", self.table),
+        };
+
+        if !self.conditions.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&self.conditions.join(" AND "));
+        }
+
+        if !self.group.is_empty() {
+            sql.push_str(" GROUP BY ");
+            sql.push_str(&self.group.join(", "));
+        }
+
+        if !self.order.is_empty() {
+            sql.push_str(" ORDER BY ");
+            sql.push_str(&self.order.join(", "));
+        }
+
+        if self.limit {
+            sql.push_str(" LIMIT ?");
+        }
+
+        if self.offset {
+            sql.push_str(" OFFSET ?");
+        }
+
+        if let Some((ref keys, ref column)) = self.conflict {
+            sql.push_str(&match self.dialect {
+                Dialect::MySql => {
+                    format!(" ON DUPLICATE KEY UPDATE {column} = {column} + VALUES({column})")
+                }
+                _ => format!(
+                    " ON CONFLICT ({}) DO UPDATE SET {column} = {}.{column} + excluded.{column}",
+                    keys.join(", "),
+                    self.table
+                ),
+            });
+        }
+
+        self.dialect.placeholders(&sql)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Dialect, Query};
+
+    const DIALECTS: [Dialect; 3] = [Dialect::Sqlite, Dialect::MySql, Dialect::Postgres];
+
+    /// Render `query` for every dialect and compare against `expected` (sqlite, mysql, postgres)
+    fn snapshot(query: fn(Dialect) -> Query, expected: [&str; 3]) {
+        for (dialect, expected) in DIALECTS.iter().zip(expected) {
+            assert_eq!(query(*dialect).build(), expected, "{dialect:?}");
+        }
+    }
+
+    #[test]
+    fn select() {
+        snapshot(
+            |d| {
+                Query::select(d, "pastes")
+                    .columns(&["url", "id"])
+                    .column_as_text("date_published")
+                    .filter("url", "=")
+            },
+            [
+                "SELECT \"url\", \"id\", CAST(\"date_published\" AS TEXT) AS \"date_published\" FROM \"pastes\" WHERE \"url\" = ?",
+                "SELECT `url`, `id`, CAST(`date_published` AS CHAR) AS `date_published` FROM `pastes` WHERE `url` = ?",
+                "SELECT \"url\", \"id\", CAST(\"date_published\" AS TEXT) AS \"date_published\" FROM \"pastes\" WHERE \"url\" = $1",
+            ],
+        );
+    }
+
+    #[test]
+    fn select_all_ordered() {
+        snapshot(
+            |d| {
+                Query::select(d, "revisions")
+                    .filter("url", "=")
+                    .filter("id", "=")
+                    .order_by("timestamp", true)
+                    .limit()
+                    .offset()
+            },
+            [
+                "SELECT * FROM \"revisions\" WHERE \"url\" = ? AND \"id\" = ? ORDER BY \"timestamp\" DESC LIMIT ? OFFSET ?",
+                "SELECT * FROM `revisions` WHERE `url` = ? AND `id` = ? ORDER BY `timestamp` DESC LIMIT ? OFFSET ?",
+                "SELECT * FROM \"revisions\" WHERE \"url\" = $1 AND \"id\" = $2 ORDER BY \"timestamp\" DESC LIMIT $3 OFFSET $4",
+            ],
+        );
+    }
+
+    #[test]
+    fn select_raw_filter() {
+        snapshot(
+            |d| {
+                let (sort, id) = (d.ident("edit_date"), d.ident("id"));
+                Query::select(d, "pastes")
+                    .filter("owner", "=")
+                    .filter_raw(&format!("{sort} < ? OR ({sort} = ? AND {id} < ?)"))
+                    .order_by("edit_date", true)
+                    .order_by("id", true)
+                    .limit()
+            },
+            [
+                "SELECT * FROM \"pastes\" WHERE \"owner\" = ? AND (\"edit_date\" < ? OR (\"edit_date\" = ? AND \"id\" < ?)) ORDER BY \"edit_date\" DESC, \"id\" DESC LIMIT ?",
+                "SELECT * FROM `pastes` WHERE `owner` = ? AND (`edit_date` < ? OR (`edit_date` = ? AND `id` < ?)) ORDER BY `edit_date` DESC, `id` DESC LIMIT ?",
+                "SELECT * FROM \"pastes\" WHERE \"owner\" = $1 AND (\"edit_date\" < $2 OR (\"edit_date\" = $3 AND \"id\" < $4)) ORDER BY \"edit_date\" DESC, \"id\" DESC LIMIT $5",
+            ],
+        );
+    }
+
+    #[test]
+    fn insert() {
+        snapshot(
+            |d| Query::insert(d, "views").columns(&["url", "username"]),
+            [
+                "INSERT INTO \"views\" (\"url\", \"username\") VALUES (?, ?)",
+                "INSERT INTO `views` (`url`, `username`) VALUES (?, ?)",
+                "INSERT INTO \"views\" (\"url\", \"username\") VALUES ($1, $2)",
+            ],
+        );
+    }
+
+    #[test]
+    fn select_grouped() {
+        snapshot(
+            |d| {
+                Query::select(d, "view_stats")
+                    .column("referrer")
+                    .expr("SUM(views) AS total")
+                    .filter("url", "=")
+                    .group_by("referrer")
+                    .limit()
+            },
+            [
+                "SELECT \"referrer\", SUM(views) AS total FROM \"view_stats\" WHERE \"url\" = ? GROUP BY \"referrer\" LIMIT ?",
+                "SELECT `referrer`, SUM(views) AS total FROM `view_stats` WHERE `url` = ? GROUP BY `referrer` LIMIT ?",
+                "SELECT \"referrer\", SUM(views) AS total FROM \"view_stats\" WHERE \"url\" = $1 GROUP BY \"referrer\" LIMIT $2",
+            ],
+        );
+    }
+
+    #[test]
+    fn select_grouped_ordered() {
+        snapshot(
+            |d| {
+                Query::select(d, "tags")
+                    .column("tag")
+                    .group_by("tag")
+                    .order_by_raw("COUNT(*)", true)
+                    .order_by("tag", false)
+                    .limit()
+            },
+            [
+                "SELECT \"tag\" FROM \"tags\" GROUP BY \"tag\" ORDER BY COUNT(*) DESC, \"tag\" ASC LIMIT ?",
+                "SELECT `tag` FROM `tags` GROUP BY `tag` ORDER BY COUNT(*) DESC, `tag` ASC LIMIT ?",
+                "SELECT \"tag\" FROM \"tags\" GROUP BY \"tag\" ORDER BY COUNT(*) DESC, \"tag\" ASC LIMIT $1",
+            ],
+        );
+    }
+
+    #[test]
+    fn upsert() {
+        snapshot(
+            |d| {
+                Query::insert(d, "view_stats")
+                    .columns(&["url", "day", "views"])
+                    .on_conflict_add(&["url", "day"], "views")
+            },
+            [
+                "INSERT INTO \"view_stats\" (\"url\", \"day\", \"views\") VALUES (?, ?, ?) ON CONFLICT (\"url\", \"day\") DO UPDATE SET \"views\" = \"view_stats\".\"views\" + excluded.\"views\"",
+                "INSERT INTO `view_stats` (`url`, `day`, `views`) VALUES (?, ?, ?) ON DUPLICATE KEY UPDATE `views` = `views` + VALUES(`views`)",
+                "INSERT INTO \"view_stats\" (\"url\", \"day\", \"views\") VALUES ($1, $2, $3) ON CONFLICT (\"url\", \"day\") DO UPDATE SET \"views\" = \"view_stats\".\"views\" + excluded.\"views\"",
+            ],
+        );
+    }
+
+    #[test]
+    fn update() {
+        snapshot(
+            |d| {
+                Query::update(d, "pastes")
+                    .columns(&["content", "edit_date"])
+                    .filter("url", "=")
+            },
+            [
+                "UPDATE \"pastes\" SET \"content\" = ?, \"edit_date\" = ? WHERE \"url\" = ?",
+                "UPDATE `pastes` SET `content` = ?, `edit_date` = ? WHERE `url` = ?",
+                "UPDATE \"pastes\" SET \"content\" = $1, \"edit_date\" = $2 WHERE \"url\" = $3",
+            ],
+        );
+    }
+
+    #[test]
+    fn delete() {
+        snapshot(
+            |d| Query::delete(d, "pastes").filter("expires_at", "!="),
+            [
+                "DELETE FROM \"pastes\" WHERE \"expires_at\" != ?",
+                "DELETE FROM `pastes` WHERE `expires_at` != ?",
+                "DELETE FROM \"pastes\" WHERE \"expires_at\" != $1",
+            ],
+        );
+    }
+
+    #[test]
+    fn quoting() {
+        snapshot(
+            |d| Query::delete(d, "we\"ird`?").filter("url", "="),
+            [
+                "DELETE FROM \"we\"\"ird`?\" WHERE \"url\" = ?",
+                "DELETE FROM `we\"ird``?` WHERE `url` = ?",
+                "DELETE FROM \"we\"\"ird`?\" WHERE \"url\" = $1",
+            ],
+        );
+    }
+}
@@ -1,41 +1,115 @@
 //! Responds to API requests
 use crate::model::{
     PasteCreate, PasteClone, PasteDelete, PasteEdit, PasteError, PasteEditMetadata, Paste,
-    PublicPaste,
+    PublicPaste, PasteRevision, PasteRestore, PasteClaim, PasteTransfer, PasteEditExpiry,
+    PasteSearchResult, PasteList, PasteSort, PasteStats, PasteAttachment, PasteForks,
+    PasteSyncTemplate, TemplateSync, PasteTagged, TagCount, CollectionCreate, CollectionEdit,
+    CollectionAddPaste, CollectionDelete, PublicCollection,
 };
-use crate::database::Database;
+use crate::store::{can_view_stats, PasteStore};
+use super::ratelimit::{rate_limit, RouteClass};
 use dorsal::DefaultReturn;
 
 use axum::response::IntoResponse;
 use axum::{
-    extract::{Path, State, Query},
+    extract::{DefaultBodyLimit, Multipart, Path, State, Query},
+    middleware,
     routing::{get, post},
     Json, Router,
 };
 use axum_extra::extract::cookie::CookieJar;
+use dorsal::db::special::auth_db::{FullUser, UserMetadata};
+
+pub fn routes<S: PasteStore>(database: S) -> Router {
+    let limit = |class| middleware::from_fn_with_state((database.clone(), class), rate_limit::<S>);
 
-pub fn routes(database: Database) -> Router {
     Router::new()
-        .route("/new", post(create_paste))
-        .route("/clone", post(clone_paste))
-        // pastes
-        .route("/:url", get(get_paste_by_url))
-        .route("/:url/delete", post(delete_paste_by_url))
-        .route("/:url/edit", post(edit_paste_by_url))
-        .route("/:url/metadata", post(edit_paste_metadata_by_url))
+        // creating pastes
+        .merge(
+            Router::new()
+                .route("/new", post(create_paste::<S>))
+                .route("/clone", post(clone_paste::<S>))
+                .route("/collections/new", post(create_collection::<S>))
+                .route_layer(limit(RouteClass::Create)),
+        )
+        // changing pastes (and checking their passwords)
+        .merge(
+            Router::new()
+                .route("/:url/delete", post(delete_paste_by_url::<S>))
+                .route("/:url/restore", post(restore_paste_by_url::<S>))
+                .route("/:url/edit", post(edit_paste_by_url::<S>))
+                .route("/:url/metadata", post(edit_paste_metadata_by_url::<S>))
+                .route("/:url/expiry", post(edit_paste_expiry_by_url::<S>))
+                .route("/:url/claim", post(claim_paste_by_url::<S>))
+                .route("/:url/transfer", post(transfer_paste_by_url::<S>))
+                .route("/:url/transfer/accept", post(accept_transfer_by_url::<S>))
+                .route(
+                    "/:url/revisions/:rev/restore",
+                    post(restore_revision_by_id::<S>),
+                )
+                .route("/:url/sync-template", post(sync_template_by_url::<S>))
+                .route(
+                    "/:url/attachments",
+                    // leave room for the rest of the form
+                    post(upload_attachment::<S>).layer(DefaultBodyLimit::max(
+                        database.options().attachments.max_size + 64 * 1024,
+                    )),
+                )
+                .route(
+                    "/:url/attachments/:name/delete",
+                    post(delete_attachment_by_name::<S>),
+                )
+                .route(
+                    "/collections/:name/edit",
+                    post(edit_collection_by_name::<S>),
+                )
+                .route("/collections/:name/add", post(add_paste_to_collection::<S>))
+                .route(
+                    "/collections/:name/delete",
+                    post(delete_collection_by_name::<S>),
+                )
+                .route_layer(limit(RouteClass::Write)),
+        )
+        // everything else
+        .merge(
+            Router::new()
+                .route("/search", get(search_pastes::<S>))
+                .route("/tags", get(get_tags::<S>))
+                .route("/tags/:tag", get(get_pastes_by_tag::<S>))
+                .route("/user/:username/pastes", get(list_pastes_by_owner::<S>))
+                // pastes
+                .route("/:url", get(get_paste_by_url::<S>))
+                .route("/:url/stats", get(get_stats_by_url::<S>))
+                .route("/:url/revisions", get(get_revisions_by_url::<S>))
+                .route("/:url/revisions/:rev", get(get_revision_by_id::<S>))
+                .route("/:url/attachments", get(get_attachments_by_url::<S>))
+                .route("/:url/forks", get(get_forks_by_url::<S>))
+                .route("/collections/:name", get(get_collection_by_name::<S>))
+                .route_layer(limit(RouteClass::Read)),
+        )
         // auth
-        .route("/auth/callback", get(callback_request))
-        .route("/auth/logout", get(logout_request))
+        .route("/auth/callback", get(callback_request::<S>))
+        .route("/auth/logout", get(logout_request::<S>))
         // ...
         .with_state(database)
 }
 
 /// Create a new paste (`/api/new`)
-async fn create_paste(
-    State(database): State<Database>,
+async fn create_paste<S: PasteStore>(
+    jar: CookieJar,
+    State(database): State<S>,
     Json(paste_to_create): Json<PasteCreate>,
 ) -> Result<Json<DefaultReturn<(String, Paste)>>, PasteError> {
-    let res = database.create_paste(paste_to_create).await;
+    let res = database
+        .create_paste(
+            paste_to_create,
+            // get editing_as
+            match get_editing_as(&database, &jar).await {
+                Ok(ua) => ua,
+                Err(e) => return Err(e),
+            },
+        )
+        .await;
 
     match res {
         Ok(paste) => Ok(Json(DefaultReturn {
@@ -48,11 +122,21 @@ async fn create_paste(
 }
 
 /// Clone an existing paste (`/api/clone`)
-async fn clone_paste(
-    State(database): State<Database>,
+async fn clone_paste<S: PasteStore>(
+    jar: CookieJar,
+    State(database): State<S>,
     Json(paste_to_create): Json<PasteClone>,
 ) -> Result<Json<DefaultReturn<(String, Paste)>>, PasteError> {
-    let res = database.clone_paste(paste_to_create).await;
+    let res = database
+        .clone_paste(
+            paste_to_create,
+            // get editing_as
+            match get_editing_as(&database, &jar).await {
+                Ok(ua) => ua,
+                Err(e) => return Err(e),
+            },
+        )
+        .await;
 
     match res {
         Ok(paste) => Ok(Json(DefaultReturn {
@@ -65,13 +149,22 @@ async fn clone_paste(
 }
 
 /// Delete an existing paste (`/api/:url/delete`)
-async fn delete_paste_by_url(
-    State(database): State<Database>,
+async fn delete_paste_by_url<S: PasteStore>(
+    jar: CookieJar,
+    State(database): State<S>,
     Path(url): Path<String>,
     Json(paste_to_delete): Json<PasteDelete>,
 ) -> Result<Json<DefaultReturn<()>>, PasteError> {
     match database
-        .delete_paste_by_url(url, paste_to_delete.password)
+        .delete_paste_by_url(
+            url,
+            paste_to_delete.password,
+            // get editing_as
+            match get_editing_as(&database, &jar).await {
+                Ok(ua) => ua,
+                Err(e) => return Err(e),
+            },
+        )
         .await
     {
         Ok(_) => Ok(Json(DefaultReturn {
@@ -83,10 +176,42 @@ async fn delete_paste_by_url(
     }
 }
 
+/// Restore an existing paste from the trash (`/api/:url/restore`)
+async fn restore_paste_by_url<S: PasteStore>(
+    jar: CookieJar,
+    State(database): State<S>,
+    Path(url): Path<String>,
+    // owners don't need to send a password (or a body at all)
+    paste_to_restore: Option<Json<PasteRestore>>,
+) -> Result<Json<DefaultReturn<()>>, PasteError> {
+    match database
+        .restore_paste_by_url(
+            url,
+            match paste_to_restore {
+                Some(Json(p)) => p.password,
+                None => String::new(),
+            },
+            // get editing_as
+            match get_editing_as(&database, &jar).await {
+                Ok(ua) => ua,
+                Err(e) => return Err(e),
+            },
+        )
+        .await
+    {
+        Ok(_) => Ok(Json(DefaultReturn {
+            success: true,
+            message: String::from("Paste restored"),
+            payload: (),
+        })),
+        Err(e) => Err(e),
+    }
+}
+
 /// Edit an existing paste (`/api/:url/edit`)
-async fn edit_paste_by_url(
+async fn edit_paste_by_url<S: PasteStore>(
     jar: CookieJar,
-    State(database): State<Database>,
+    State(database): State<S>,
     Path(url): Path<String>,
     Json(paste_to_edit): Json<PasteEdit>,
 ) -> Result<Json<DefaultReturn<()>>, PasteError> {
@@ -98,19 +223,9 @@ async fn edit_paste_by_url(
             paste_to_edit.new_url,
             paste_to_edit.new_password,
             // get editing_as
-            if let Some(cookie) = jar.get("__Secure-Token") {
-                let value = cookie.value_trimmed();
-
-                if database.options.guppy == true {
-                    match database.auth.get_user_by_unhashed(value.to_string()).await {
-                        Ok(ua) => Option::Some(ua),
-                        Err(_) => return Err(PasteError::Other),
-                    }
-                } else {
-                    Option::None
-                }
-            } else {
-                Option::None
+            match get_editing_as(&database, &jar).await {
+                Ok(ua) => ua,
+                Err(e) => return Err(e),
             },
         )
         .await
@@ -125,48 +240,21 @@ async fn edit_paste_by_url(
 }
 
 /// Edit an existing paste's metadata (`/api/:url/metadata`)
-async fn edit_paste_metadata_by_url(
+async fn edit_paste_metadata_by_url<S: PasteStore>(
     jar: CookieJar,
-    State(database): State<Database>,
+    State(database): State<S>,
     Path(url): Path<String>,
-    Json(mut paste_to_edit): Json<PasteEditMetadata>,
+    Json(paste_to_edit): Json<PasteEditMetadata>,
 ) -> Result<Json<DefaultReturn<()>>, PasteError> {
-    // if we've been given an authentication cookie (and it's allowed),
-    // we'll check the user and then set metadata.owner
-    if let Some(cookie) = jar.get("__Secure-Token") {
-        let value = cookie.value_trimmed();
-
-        if (database.options.guppy == true) && (database.options.paste_ownership == true) {
-            match database.auth.get_user_by_unhashed(value.to_string()).await {
-                Ok(ua) => paste_to_edit.metadata.owner = ua.user.username,
-                Err(_) => paste_to_edit.metadata.owner = "".to_string(),
-            }
-        }
-    } else {
-        // clear owner field if paste is edited by an anonymous user
-        paste_to_edit.metadata.owner = "".to_string();
-    }
-
-    // ...
     match database
         .edit_paste_metadata_by_url(
             url,
             paste_to_edit.password,
             paste_to_edit.metadata,
             // get editing_as
-            if let Some(cookie) = jar.get("__Secure-Token") {
-                let value = cookie.value_trimmed();
-
-                if database.options.guppy == true {
-                    match database.auth.get_user_by_unhashed(value.to_string()).await {
-                        Ok(ua) => Option::Some(ua),
-                        Err(_) => return Err(PasteError::Other),
-                    }
-                } else {
-                    Option::None
-                }
-            } else {
-                Option::None
+            match get_editing_as(&database, &jar).await {
+                Ok(ua) => ua,
+                Err(e) => return Err(e),
             },
         )
         .await
@@ -180,9 +268,127 @@ async fn edit_paste_metadata_by_url(
     }
 }
 
+/// Edit an existing paste's expiry settings (`/api/:url/expiry`)
+async fn edit_paste_expiry_by_url<S: PasteStore>(
+    jar: CookieJar,
+    State(database): State<S>,
+    Path(url): Path<String>,
+    Json(paste_to_edit): Json<PasteEditExpiry>,
+) -> Result<Json<DefaultReturn<()>>, PasteError> {
+    match database
+        .edit_paste_expiry_by_url(
+            url,
+            paste_to_edit.password,
+            paste_to_edit.expires_at,
+            paste_to_edit.max_views,
+            // get editing_as
+            match get_editing_as(&database, &jar).await {
+                Ok(ua) => ua,
+                Err(e) => return Err(e),
+            },
+        )
+        .await
+    {
+        Ok(_) => Ok(Json(DefaultReturn {
+            success: true,
+            message: String::from("Paste updated"),
+            payload: (),
+        })),
+        Err(e) => Err(e),
+    }
+}
+
+/// Claim an existing paste which has no owner (`/api/:url/claim`)
+async fn claim_paste_by_url<S: PasteStore>(
+    jar: CookieJar,
+    State(database): State<S>,
+    Path(url): Path<String>,
+    Json(paste_to_claim): Json<PasteClaim>,
+) -> Result<Json<DefaultReturn<()>>, PasteError> {
+    match database
+        .claim_paste_by_url(
+            url,
+            paste_to_claim.password,
+            // get editing_as
+            match get_editing_as(&database, &jar).await {
+                Ok(ua) => ua,
+                Err(e) => return Err(e),
+            },
+        )
+        .await
+    {
+        Ok(_) => Ok(Json(DefaultReturn {
+            success: true,
+            message: String::from("Paste claimed"),
+            payload: (),
+        })),
+        Err(e) => Err(e),
+    }
+}
+
+/// Offer an existing paste to another user (`/api/:url/transfer`)
+async fn transfer_paste_by_url<S: PasteStore>(
+    jar: CookieJar,
+    State(database): State<S>,
+    Path(url): Path<String>,
+    Json(paste_to_transfer): Json<PasteTransfer>,
+) -> Result<Json<DefaultReturn<()>>, PasteError> {
+    let cancelled = paste_to_transfer.username.trim().is_empty();
+
+    match database
+        .transfer_paste_by_url(
+            url,
+            paste_to_transfer.username,
+            // get editing_as
+            match get_editing_as(&database, &jar).await {
+                Ok(ua) => ua,
+                Err(e) => return Err(e),
+            },
+        )
+        .await
+    {
+        Ok(_) => Ok(Json(DefaultReturn {
+            success: true,
+            message: String::from(if cancelled {
+                "Transfer cancelled"
+            } else {
+                "Transfer requested"
+            }),
+            payload: (),
+        })),
+        Err(e) => Err(e),
+    }
+}
+
+/// Accept the pending transfer of an existing paste (`/api/:url/transfer/accept`)
+async fn accept_transfer_by_url<S: PasteStore>(
+    jar: CookieJar,
+    State(database): State<S>,
+    Path(url): Path<String>,
+) -> Result<Json<DefaultReturn<()>>, PasteError> {
+    match database
+        .accept_transfer_by_url(
+            url,
+            // get editing_as
+            match get_editing_as(&database, &jar).await {
+                Ok(ua) => ua,
+                Err(e) => return Err(e),
+            },
+        )
+        .await
+    {
+        Ok(_) => Ok(Json(DefaultReturn {
+            success: true,
+            message: String::from("Transfer accepted"),
+            payload: (),
+        })),
+        Err(e) => Err(e),
+    }
+}
+
 /// Get an existing paste by url (`/api/:url`)
-pub async fn get_paste_by_url(
-    State(database): State<Database>,
+pub async fn get_paste_by_url<S: PasteStore>(
+    State(database): State<S>,
     Path(url): Path<String>,
 ) -> Result<Json<DefaultReturn<PublicPaste>>, PasteError> {
     match database.get_paste_by_url(url).await {
@@ -191,6 +397,13 @@ pub async fn get_paste_by_url(
                 return Err(PasteError::Other);
             }
 
+            // reads through the api count towards the view limit too
+            if p.max_views > 0 {
+                database
+                    .incr_views_by_url(p.url.clone(), None, String::new(), String::new())
+                    .await?
+            }
+
             Ok(Json(DefaultReturn {
                 success: true,
                 message: String::from("Paste exists"),
@@ -201,6 +414,587 @@ pub async fn get_paste_by_url(
     }
 }
 
+#[derive(serde::Deserialize)]
+pub struct StatsQueryProps {
+    #[serde(default = "default_stats_days")]
+    pub days: i64,
+}
+
+fn default_stats_days() -> i64 {
+    30
+}
+
+/// Get the view analytics of an existing paste (`/api/:url/stats`)
+pub async fn get_stats_by_url<S: PasteStore>(
+    jar: CookieJar,
+    State(database): State<S>,
+    Path(url): Path<String>,
+    Query(params): Query<StatsQueryProps>,
+) -> Result<Json<DefaultReturn<PasteStats>>, PasteError> {
+    // analytics are only visible to the paste owner and paste managers
+    let paste = match database.get_paste_by_url(url.clone()).await {
+        Ok(p) => p,
+        Err(e) => return Err(e),
+    };
+
+    match get_editing_as(&database, &jar).await {
+        Ok(ua) => {
+            if !can_view_stats(&paste, &ua) {
+                return Err(PasteError::PasswordIncorrect);
+            }
+        }
+        Err(e) => return Err(e),
+    }
+
+    // ...
+    match database.get_stats_by_url(url, params.days).await {
+        Ok(s) => Ok(Json(DefaultReturn {
+            success: true,
+            message: String::from("Stats exist"),
+            payload: s,
+        })),
+        Err(e) => Err(e),
+    }
+}
+
+/// Get the revisions of an existing paste (`/api/:url/revisions`)
+pub async fn get_revisions_by_url<S: PasteStore>(
+    State(database): State<S>,
+    Path(url): Path<String>,
+) -> Result<Json<DefaultReturn<Vec<PasteRevision>>>, PasteError> {
+    // make sure paste isn't private
+    match database.get_paste_by_url(url.clone()).await {
+        Ok(p) => {
+            if !p.metadata.view_password.is_empty() {
+                return Err(PasteError::Other);
+            }
+        }
+        Err(e) => return Err(e),
+    }
+
+    // ...
+    match database.get_revisions_by_url(url).await {
+        Ok(r) => Ok(Json(DefaultReturn {
+            success: true,
+            message: String::from("Revisions exist"),
+            payload: r,
+        })),
+        Err(e) => Err(e),
+    }
+}
+
+/// Get a single revision of an existing paste (`/api/:url/revisions/:rev`)
+pub async fn get_revision_by_id<S: PasteStore>(
+    State(database): State<S>,
+    Path((url, rev)): Path<(String, String)>,
+) -> Result<Json<DefaultReturn<PasteRevision>>, PasteError> {
+    // make sure paste isn't private
+    match database.get_paste_by_url(url.clone()).await {
+        Ok(p) => {
+            if !p.metadata.view_password.is_empty() {
+                return Err(PasteError::Other);
+            }
+        }
+        Err(e) => return Err(e),
+    }
+
+    // ...
+    match database.get_revision_by_id(url, rev).await {
+        Ok(r) => Ok(Json(DefaultReturn {
+            success: true,
+            message: String::from("Revision exists"),
+            payload: r,
+        })),
+        Err(e) => Err(e),
+    }
+}
+
+/// Restore an existing paste from one of its revisions (`/api/:url/revisions/:rev/restore`)
+async fn restore_revision_by_id<S: PasteStore>(
+    jar: CookieJar,
+    State(database): State<S>,
+    Path((url, rev)): Path<(String, String)>,
+    Json(paste_to_restore): Json<PasteRestore>,
+) -> Result<Json<DefaultReturn<()>>, PasteError> {
+    let editing_as = match get_editing_as(&database, &jar).await {
+        Ok(ua) => ua,
+        Err(e) => return Err(e),
+    };
+
+    match database
+        .restore_revision_by_id(url, rev, paste_to_restore.password, editing_as)
+        .await
+    {
+        Ok(_) => Ok(Json(DefaultReturn {
+            success: true,
+            message: String::from("Paste restored"),
+            payload: (),
+        })),
+        Err(e) => Err(e),
+    }
+}
+
+/// Merge the changes of a template into a paste derived from it (`/api/:url/sync-template`)
+///
+/// Conflicts are reported (with the conflicting content) and nothing is saved.
+async fn sync_template_by_url<S: PasteStore>(
+    jar: CookieJar,
+    State(database): State<S>,
+    Path(url): Path<String>,
+    Json(paste_to_sync): Json<PasteSyncTemplate>,
+) -> Result<Json<DefaultReturn<TemplateSync>>, PasteError> {
+    let editing_as = match get_editing_as(&database, &jar).await {
+        Ok(ua) => ua,
+        Err(e) => return Err(e),
+    };
+
+    match database
+        .sync_template_by_url(url, paste_to_sync.password, editing_as)
+        .await
+    {
+        Ok(sync) => Ok(Json(DefaultReturn {
+            success: !sync.conflicted,
+            message: String::from(if sync.conflicted {
+                "Template changes conflict with changes to the paste"
+            } else {
+                "Template synced"
+            }),
+            payload: sync,
+        })),
+        Err(e) => Err(e),
+    }
+}
+
+/// Attach a file to an existing paste (`/api/:url/attachments`)
+///
+/// Expects a multipart form with a `file` field and a `password` field (not needed for
+/// the paste owner). The file name can be overridden with a `name` field.
+async fn upload_attachment<S: PasteStore>(
+    jar: CookieJar,
+    State(database): State<S>,
+    Path(url): Path<String>,
+    mut multipart: Multipart,
+) -> Result<Json<DefaultReturn<PasteAttachment>>, PasteError> {
+    let editing_as = match get_editing_as(&database, &jar).await {
+        Ok(ua) => ua,
+        Err(e) => return Err(e),
+    };
+
+    // read form
+    let mut password = String::new();
+    let mut name = String::new();
+    let mut file: Option<(String, String, Vec<u8>)> = None;
+
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(f)) => f,
+            Ok(None) => break,
+            Err(_) => return Err(PasteError::ValueError),
+        };
+
+        let field_name = field.name().unwrap_or("").to_string();
+
+        match field_name.as_str() {
+            "password" => password = field.text().await.unwrap_or_default(),
+            "name" => name = field.text().await.unwrap_or_default(),
+            "file" => {
+                let file_name = field.file_name().unwrap_or("").to_string();
+                let content_type = field
+                    .content_type()
+                    .unwrap_or("application/octet-stream")
+                    .split(";")
+                    .next()
+                    .unwrap_or("")
+                    .trim()
+                    .to_lowercase();
+
+                match field.bytes().await {
+                    Ok(data) => file = Some((file_name, content_type, data.to_vec())),
+                    Err(_) => return Err(PasteError::ValueError),
+                }
+            }
+            _ => continue,
+        }
+    }
+
+    let (file_name, content_type, data) = match file {
+        Some(f) => f,
+        None => return Err(PasteError::ValueError),
+    };
+
+    if name.is_empty() {
+        name = file_name;
+    }
+
+    // ...
+    match database
+        .upload_attachment(
+            url,
+            password,
+            name.trim().to_string(),
+            content_type,
+            data,
+            editing_as,
+        )
+        .await
+    {
+        Ok(a) => Ok(Json(DefaultReturn {
+            success: true,
+            message: String::from("Attachment uploaded"),
+            payload: a,
+        })),
+        Err(e) => Err(e),
+    }
+}
+
+/// Get the attachments of an existing paste (`/api/:url/attachments`)
+pub async fn get_attachments_by_url<S: PasteStore>(
+    State(database): State<S>,
+    Path(url): Path<String>,
+) -> Result<Json<DefaultReturn<Vec<PasteAttachment>>>, PasteError> {
+    // make sure paste isn't private
+    match database.get_paste_by_url(url.clone()).await {
+        Ok(p) => {
+            if !p.metadata.view_password.is_empty() {
+                return Err(PasteError::Other);
+            }
+        }
+        Err(e) => return Err(e),
+    }
+
+    // ...
+    match database.get_attachments_by_url(url).await {
+        Ok(a) => Ok(Json(DefaultReturn {
+            success: true,
+            message: String::from("Attachments exist"),
+            payload: a,
+        })),
+        Err(e) => Err(e),
+    }
+}
+
+/// Delete an attachment of an existing paste (`/api/:url/attachments/:name/delete`)
+async fn delete_attachment_by_name<S: PasteStore>(
+    jar: CookieJar,
+    State(database): State<S>,
+    Path((url, name)): Path<(String, String)>,
+    Json(attachment_to_delete): Json<PasteDelete>,
+) -> Result<Json<DefaultReturn<()>>, PasteError> {
+    let editing_as = match get_editing_as(&database, &jar).await {
+        Ok(ua) => ua,
+        Err(e) => return Err(e),
+    };
+
+    match database
+        .delete_attachment_by_name(url, name, attachment_to_delete.password, editing_as)
+        .await
+    {
+        Ok(_) => Ok(Json(DefaultReturn {
+            success: true,
+            message: String::from("Attachment deleted"),
+            payload: (),
+        })),
+        Err(e) => Err(e),
+    }
+}
+
+/// Create a new collection (`/api/collections/new`)
+async fn create_collection<S: PasteStore>(
+    jar: CookieJar,
+    State(database): State<S>,
+    Json(collection_to_create): Json<CollectionCreate>,
+) -> Result<Json<DefaultReturn<(String, PublicCollection)>>, PasteError> {
+    let editing_as = match get_editing_as(&database, &jar).await {
+        Ok(ua) => ua,
+        Err(e) => return Err(e),
+    };
+
+    let (password, collection) = match database
+        .create_collection(collection_to_create, editing_as)
+        .await
+    {
+        Ok(c) => c,
+        Err(e) => return Err(e),
+    };
+
+    match database
+        .get_public_collection_by_name(collection.name)
+        .await
+    {
+        Ok(collection) => Ok(Json(DefaultReturn {
+            success: true,
+            message: String::from("Collection created"),
+            payload: (password, collection),
+        })),
+        Err(e) => Err(e),
+    }
+}
+
+/// Get an existing collection by name (`/api/collections/:name`)
+async fn get_collection_by_name<S: PasteStore>(
+    State(database): State<S>,
+    Path(name): Path<String>,
+) -> Result<Json<DefaultReturn<PublicCollection>>, PasteError> {
+    match database.get_public_collection_by_name(name).await {
+        Ok(collection) => Ok(Json(DefaultReturn {
+            success: true,
+            message: String::from("Collection exists"),
+            payload: collection,
+        })),
+        Err(e) => Err(e),
+    }
+}
+
+/// Edit an existing collection (`/api/collections/:name/edit`)
+async fn edit_collection_by_name<S: PasteStore>(
+    jar: CookieJar,
+    State(database): State<S>,
+    Path(name): Path<String>,
+    Json(collection_to_edit): Json<CollectionEdit>,
+) -> Result<Json<DefaultReturn<()>>, PasteError> {
+    let editing_as = match get_editing_as(&database, &jar).await {
+        Ok(ua) => ua,
+        Err(e) => return Err(e),
+    };
+
+    match database
+        .edit_collection_by_name(
+            name,
+            collection_to_edit.password,
+            collection_to_edit.title,
+            collection_to_edit.description,
+            collection_to_edit.new_password,
+            collection_to_edit.pastes,
+            editing_as,
+        )
+        .await
+    {
+        Ok(_) => Ok(Json(DefaultReturn {
+            success: true,
+            message: String::from("Collection updated"),
+            payload: (),
+        })),
+        Err(e) => Err(e),
+    }
+}
+
+/// Add a paste to the end of an existing collection (`/api/collections/:name/add`)
+async fn add_paste_to_collection<S: PasteStore>(
+    jar: CookieJar,
+    State(database): State<S>,
+    Path(name): Path<String>,
+    Json(paste_to_add): Json<CollectionAddPaste>,
+) -> Result<Json<DefaultReturn<()>>, PasteError> {
+    let editing_as = match get_editing_as(&database, &jar).await {
+        Ok(ua) => ua,
+        Err(e) => return Err(e),
+    };
+
+    match database
+        .add_paste_to_collection(name, paste_to_add.password, paste_to_add.url, editing_as)
+        .await
+    {
+        Ok(_) => Ok(Json(DefaultReturn {
+            success: true,
+            message: String::from("Paste added to collection"),
+            payload: (),
+        })),
+        Err(e) => Err(e),
+    }
+}
+
+/// Delete an existing collection (`/api/collections/:name/delete`)
+async fn delete_collection_by_name<S: PasteStore>(
+    jar: CookieJar,
+    State(database): State<S>,
+    Path(name): Path<String>,
+    Json(collection_to_delete): Json<CollectionDelete>,
+) -> Result<Json<DefaultReturn<()>>, PasteError> {
+    let editing_as = match get_editing_as(&database, &jar).await {
+        Ok(ua) => ua,
+        Err(e) => return Err(e),
+    };
+
+    match database
+        .delete_collection_by_name(name, collection_to_delete.password, editing_as)
+        .await
+    {
+        Ok(_) => Ok(Json(DefaultReturn {
+            success: true,
+            message: String::from("Collection deleted"),
+            payload: (),
+        })),
+        Err(e) => Err(e),
+    }
+}
+
+/// Get the user we're editing as from the `__Secure-Token` cookie (if guppy is enabled)
+async fn get_editing_as<S: PasteStore>(
+    database: &S,
+    jar: &CookieJar,
+) -> Result<Option<FullUser<UserMetadata>>, PasteError> {
+    if let Some(cookie) = jar.get("__Secure-Token") {
+        let value = cookie.value_trimmed();
+
+        if database.options().guppy {
+            match database.get_user_by_token(value.to_string()).await {
+                Ok(ua) => Ok(Option::Some(ua)),
+                Err(_) => Err(PasteError::Other),
+            }
+        } else {
+            Ok(Option::None)
+        }
+    } else {
+        Ok(Option::None)
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct SearchQueryProps {
+    #[serde(default)]
+    pub q: String,
+    #[serde(default)]
+    pub page: i64,
+}
+
+/// Search public pastes (`/api/search?q=`)
+pub async fn search_pastes<S: PasteStore>(
+    State(database): State<S>,
+    Query(params): Query<SearchQueryProps>,
+) -> Result<Json<DefaultReturn<Vec<PasteSearchResult>>>, PasteError> {
+    let offset = match params.page.checked_mul(25) {
+        Some(o) => o,
+        None => return Err(PasteError::ValueError),
+    };
+
+    match database.search_pastes(params.q, 25, offset).await {
+        Ok(r) => Ok(Json(DefaultReturn {
+            success: true,
+            message: String::from("Search complete"),
+            payload: r,
+        })),
+        Err(e) => Err(e),
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct ForksQueryProps {
+    #[serde(default)]
+    pub page: i64,
+}
+
+/// Get the pastes derived from a template (`/api/:url/forks?page=`)
+pub async fn get_forks_by_url<S: PasteStore>(
+    State(database): State<S>,
+    Path(url): Path<String>,
+    Query(params): Query<ForksQueryProps>,
+) -> Result<Json<DefaultReturn<PasteForks>>, PasteError> {
+    let offset = match params.page.checked_mul(25) {
+        Some(o) => o,
+        None => return Err(PasteError::ValueError),
+    };
+
+    match database.get_forks_by_url(url, 25, offset).await {
+        Ok(f) => Ok(Json(DefaultReturn {
+            success: true,
+            message: String::from("Forks exist"),
+            payload: f,
+        })),
+        Err(e) => Err(e),
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct TagQueryProps {
+    #[serde(default)]
+    pub page: i64,
+}
+
+/// Get the public pastes carrying a tag (`/api/tags/:tag?page=`)
+pub async fn get_pastes_by_tag<S: PasteStore>(
+    State(database): State<S>,
+    Path(tag): Path<String>,
+    Query(params): Query<TagQueryProps>,
+) -> Result<Json<DefaultReturn<PasteTagged>>, PasteError> {
+    let offset = match params.page.checked_mul(25) {
+        Some(o) => o,
+        None => return Err(PasteError::ValueError),
+    };
+
+    match database.get_pastes_by_tag(tag, 25, offset).await {
+        Ok(t) => Ok(Json(DefaultReturn {
+            success: true,
+            message: String::from("Tag exists"),
+            payload: t,
+        })),
+        Err(e) => Err(e),
+    }
+}
+
+/// Get the most used tags of public pastes (`/api/tags`)
+pub async fn get_tags<S: PasteStore>(
+    State(database): State<S>,
+) -> Result<Json<DefaultReturn<Vec<TagCount>>>, PasteError> {
+    match database.get_tags(100).await {
+        Ok(t) => Ok(Json(DefaultReturn {
+            success: true,
+            message: String::from("Tags exist"),
+            payload: t,
+        })),
+        Err(e) => Err(e),
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct ListQueryProps {
+    #[serde(default)]
+    pub sort: PasteSort,
+    #[serde(default)]
+    pub cursor: String,
+    #[serde(default = "default_list_limit")]
+    pub limit: i64,
+}
+
+fn default_list_limit() -> i64 {
+    25
+}
+
+/// List the pastes owned by a user (`/api/user/:username/pastes`)
+pub async fn list_pastes_by_owner<S: PasteStore>(
+    jar: CookieJar,
+    State(database): State<S>,
+    Path(username): Path<String>,
+    Query(params): Query<ListQueryProps>,
+) -> Result<Json<DefaultReturn<PasteList>>, PasteError> {
+    // private pastes are only listed for their owner and paste managers
+    let include_private = match get_editing_as(&database, &jar).await {
+        Ok(Some(ua)) => {
+            (ua.user.username == username)
+                | ua.level.permissions.contains(&"ManagePastes".to_string())
+        }
+        Ok(None) => false,
+        Err(e) => return Err(e),
+    };
+
+    match database
+        .list_pastes_by_owner(
+            username,
+            params.sort,
+            params.cursor,
+            params.limit,
+            include_private,
+        )
+        .await
+    {
+        Ok(l) => Ok(Json(DefaultReturn {
+            success: true,
+            message: String::from("Pastes exist"),
+            payload: l,
+        })),
+        Err(e) => Err(e),
+    }
+}
+
 // general
 pub async fn not_found() -> impl IntoResponse {
     Json(DefaultReturn::<u16> {
@@ -216,11 +1010,11 @@ pub struct CallbackQueryProps {
     pub uid: String, // this uid will need to be sent to the client as a token
 }
 
-pub async fn callback_request(
-    State(database): State<Database>,
+pub async fn callback_request<S: PasteStore>(
+    State(database): State<S>,
     Query(params): Query<CallbackQueryProps>,
 ) -> impl IntoResponse {
-    if database.options.guppy == false {
+    if !database.options().guppy {
         return (
             [
                 ("Content-Type".to_string(), "text/plain".to_string()),
@@ -249,8 +1043,11 @@ pub async fn callback_request(
     )
 }
 
-pub async fn logout_request(State(database): State<Database>, jar: CookieJar) -> impl IntoResponse {
-    if database.options.guppy == false {
+pub async fn logout_request<S: PasteStore>(
+    State(database): State<S>,
+    jar: CookieJar,
+) -> impl IntoResponse {
+    if !database.options().guppy {
         return (
             [
                 ("Content-Type".to_string(), "text/plain".to_string()),
@@ -261,7 +1058,7 @@ pub async fn logout_request(State(database): State<Database>, jar: CookieJar) ->
     }
 
     // check for cookie
-    if let Some(_) = jar.get("__Secure-Token") {
+    if jar.get("__Secure-Token").is_some() {
         return (
             [
                 ("Content-Type".to_string(), "text/plain".to_string()),
@@ -0,0 +1,179 @@
+//! Limits how often clients can hit the API
+//!
+//! Every route belongs to a [`RouteClass`] with its own [`RateLimit`] in
+//! [`RateLimitConfig`](crate::database::RateLimitConfig). Clients are identified by their
+//! guppy user when they're signed in, and by their IP otherwise.
+use crate::database::{RateLimit, ServerOptions};
+use crate::store::PasteStore;
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::HeaderMap,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use axum_extra::extract::cookie::CookieJar;
+use std::net::SocketAddr;
+
+/// The groups of routes which are limited separately
+#[derive(Clone, Copy, Debug)]
+pub enum RouteClass {
+    /// Routes which create pastes
+    Create,
+    /// Routes which change a paste or check its password
+    Write,
+    /// Every other route
+    Read,
+}
+
+impl RouteClass {
+    /// The name of the class in rate limit keys
+    fn name(&self) -> &'static str {
+        match self {
+            RouteClass::Create => "create",
+            RouteClass::Write => "write",
+            RouteClass::Read => "read",
+        }
+    }
+
+    /// The limit of the class
+    fn limit(&self, options: &ServerOptions) -> RateLimit {
+        match self {
+            RouteClass::Create => options.rate_limits.create,
+            RouteClass::Write => options.rate_limits.write,
+            RouteClass::Read => options.rate_limits.read,
+        }
+    }
+}
+
+/// Get the IP of a client from `X-Forwarded-For`, falling back to the peer address
+///
+/// Every proxy appends the address it was connected from, so the entry `trusted_proxies`
+/// from the right is the one our outermost proxy saw. Anything left of it came from the client,
+/// and a header with fewer entries than that wasn't written by our proxies at all.
+///
+/// # Arguments
+/// * `headers` - the request headers
+/// * `addr` - the peer address (only known when the server was started with connect info)
+/// * `trusted_proxies` - [`RateLimitConfig::trusted_proxies`](crate::database::RateLimitConfig::trusted_proxies)
+pub fn client_ip(headers: &HeaderMap, addr: Option<SocketAddr>, trusted_proxies: usize) -> String {
+    let peer = match addr {
+        Some(a) => a.ip().to_string(),
+        None => String::new(),
+    };
+
+    if trusted_proxies == 0 {
+        return peer;
+    }
+
+    match headers.get("X-Forwarded-For").and_then(|v| v.to_str().ok()) {
+        Some(v) => {
+            let entries: Vec<&str> = v.split(",").map(|e| e.trim()).collect();
+
+            if entries.len() < trusted_proxies {
+                return peer;
+            }
+
+            entries[entries.len() - trusted_proxies].to_string()
+        }
+        None => peer,
+    }
+}
+
+/// Count a request against the limit of its [`RouteClass`], rejecting it once the limit is used up
+pub async fn rate_limit<S: PasteStore>(
+    State((database, class)): State<(S, RouteClass)>,
+    jar: CookieJar,
+    request: Request,
+    next: Next,
+) -> Response {
+    // identify client
+    let mut client = String::new();
+
+    if let Some(cookie) = jar.get("__Secure-Token") {
+        if let Ok(ua) = database
+            .get_user_by_token(cookie.value_trimmed().to_string())
+            .await
+        {
+            client = format!("user:{}", ua.user.username);
+        }
+    }
+
+    if client.is_empty() {
+        let addr = request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|c| c.0);
+
+        client = format!(
+            "ip:{}",
+            client_ip(
+                request.headers(),
+                addr,
+                database.options().rate_limits.trusted_proxies
+            )
+        );
+    }
+
+    // count request
+    if let Err(e) = database
+        .hit_rate_limit(
+            format!("{}:{}", class.name(), client),
+            class.limit(database.options()),
+        )
+        .await
+    {
+        return e.into_response();
+    }
+
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::client_ip;
+    use axum::http::HeaderMap;
+    use std::net::SocketAddr;
+
+    fn peer() -> Option<SocketAddr> {
+        Some("10.0.0.1:4000".parse().unwrap())
+    }
+
+    fn forwarded_for(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("X-Forwarded-For", value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn no_header() {
+        assert_eq!(client_ip(&HeaderMap::new(), peer(), 1), "10.0.0.1");
+        assert_eq!(client_ip(&HeaderMap::new(), None, 1), "");
+    }
+
+    #[test]
+    fn header_is_ignored_without_proxies() {
+        assert_eq!(
+            client_ip(&forwarded_for("203.0.113.7"), peer(), 0),
+            "10.0.0.1"
+        );
+    }
+
+    #[test]
+    fn spoofed_header() {
+        // the client sent its own header, which our proxy appended the real address to
+        let headers = forwarded_for("198.51.100.1, 203.0.113.7");
+
+        assert_eq!(client_ip(&headers, peer(), 1), "203.0.113.7");
+        assert_eq!(client_ip(&headers, peer(), 2), "198.51.100.1");
+    }
+
+    #[test]
+    fn short_header() {
+        // fewer entries than proxies means the header didn't come from our proxies
+        assert_eq!(
+            client_ip(&forwarded_for("198.51.100.1"), peer(), 2),
+            "10.0.0.1"
+        );
+    }
+}
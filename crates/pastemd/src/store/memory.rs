@@ -0,0 +1,1752 @@
+//! In-memory [`PasteStore`]
+//!
+//! Nothing stored here survives a restart, which makes it useful for tests and for
+//! throwaway instances. Guppy authentication is not supported.
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use crate::database::{RateLimit, Result, ServerOptions, ViewMode};
+use crate::password::{self, Verification};
+use crate::model::{
+    PasteCreate, PasteClone, PasteError, Paste, PasteMetadata, PasteRevision, PasteSearchResult,
+    PasteList, PasteSort, PasteStats, PasteStatsDay, PasteStatsReferrer, PasteAttachment,
+    PasteForks, TemplateSync, PasteTagged, TagCount, Collection, CollectionCreate,
+};
+use super::{
+    PasteStore, can_transfer, check_collection, check_variables, fill_variables,
+    hash_view_password, keep_ownership, keep_template_fields, next_lockout, next_window,
+    normalize_tag, normalize_tags, owner_of_new_paste, referrer_host, resolve_variables,
+    sync_template,
+};
+
+use async_trait::async_trait;
+use dorsal::utility;
+use dorsal::db::special::auth_db::{FullUser, UserMetadata};
+
+/// Paste store which keeps everything in memory
+/// `(url, day, referrer host)`
+type StatsKey = (String, u128, String);
+/// An attachment along with its contents
+type StoredAttachment = (PasteAttachment, Vec<u8>);
+
+#[derive(Clone)]
+pub struct MemoryStore {
+    pub options: ServerOptions,
+    pastes: Arc<RwLock<HashMap<String, Paste>>>,
+    revisions: Arc<RwLock<Vec<PasteRevision>>>,
+    views: Arc<RwLock<HashMap<String, i32>>>,
+    /// `(url, username)` pairs for [`ViewMode::AuthenticatedOnce`]
+    viewers: Arc<RwLock<Vec<(String, String)>>>,
+    /// When the window of each `(url, viewer key)` pair ends for [`ViewMode::OpenDeduplicated`]
+    seen: Arc<RwLock<HashMap<(String, String), u128>>>,
+    /// View counts by `(url, day, referrer host)`
+    stats: Arc<RwLock<HashMap<StatsKey, i64>>>,
+    /// Rate limit windows and password lockouts by key
+    limits: Arc<RwLock<HashMap<String, (u128, u32)>>>,
+    /// Attachments along with their contents
+    attachments: Arc<RwLock<Vec<StoredAttachment>>>,
+    /// Collections by name
+    collections: Arc<RwLock<HashMap<String, Collection>>>,
+}
+
+impl MemoryStore {
+    pub fn new(mut options: ServerOptions) -> Self {
+        // guppy is not supported, so tokens can't be checked and views can't be tied to accounts
+        options.guppy = false;
+
+        if options.view_mode == ViewMode::AuthenticatedOnce {
+            options.view_mode = ViewMode::OpenMultiple;
+        }
+
+        Self {
+            options,
+            pastes: Arc::new(RwLock::new(HashMap::new())),
+            revisions: Arc::new(RwLock::new(Vec::new())),
+            views: Arc::new(RwLock::new(HashMap::new())),
+            viewers: Arc::new(RwLock::new(Vec::new())),
+            seen: Arc::new(RwLock::new(HashMap::new())),
+            stats: Arc::new(RwLock::new(HashMap::new())),
+            limits: Arc::new(RwLock::new(HashMap::new())),
+            attachments: Arc::new(RwLock::new(Vec::new())),
+            collections: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Remove expired pastes and pastes which have been in the trash for longer than
+    /// [`ServerOptions::trash_retention`]
+    ///
+    /// # Returns
+    /// * the number of removed pastes
+    pub fn purge_pastes(&self) -> usize {
+        let cutoff = utility::unix_epoch_timestamp()
+            .saturating_sub(self.options.trash_retention as u128 * 1000);
+
+        let urls: Vec<String> = self
+            .pastes
+            .read()
+            .unwrap()
+            .values()
+            .filter(|p| p.is_expired() | (p.is_trashed() && (p.trashed_at <= cutoff)))
+            .map(|p| p.url.clone())
+            .collect();
+
+        for url in &urls {
+            self.remove_paste(url);
+        }
+
+        urls.len()
+    }
+
+    /// Remove the seen viewers of [`ViewMode::OpenDeduplicated`] whose window has ended
+    ///
+    /// # Returns
+    /// * the number of removed viewers
+    pub fn purge_seen_viewers(&self) -> usize {
+        let now = utility::unix_epoch_timestamp();
+        let mut seen = self.seen.write().unwrap();
+        let count = seen.len();
+
+        seen.retain(|_, until| *until > now);
+        count - seen.len()
+    }
+
+    /// Spawn the background task which purges pastes (and seen viewers) every
+    /// [`ServerOptions::reaper_interval`] seconds
+    pub fn spawn_background_tasks(&self) {
+        if self.options.reaper_interval != 0 {
+            let store = self.clone();
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+                    store.options.reaper_interval,
+                ));
+
+                loop {
+                    interval.tick().await;
+                    store.purge_pastes();
+                    store.purge_seen_viewers();
+                }
+            });
+        }
+    }
+
+    /// Remove a paste and everything attached to it without checking its password
+    fn remove_paste(&self, url: &str) {
+        self.pastes.write().unwrap().remove(url);
+        self.revisions.write().unwrap().retain(|r| r.url != url);
+        self.views.write().unwrap().remove(url);
+        self.viewers.write().unwrap().retain(|v| v.0 != url);
+        self.seen.write().unwrap().retain(|k, _| k.0 != url);
+        self.stats.write().unwrap().retain(|k, _| k.0 != url);
+        self.attachments.write().unwrap().retain(|a| a.0.url != url);
+
+        for collection in self.collections.write().unwrap().values_mut() {
+            collection.pastes.retain(|p| p != url);
+        }
+
+        // keep forks, but mark them as orphaned
+        for fork in self
+            .pastes
+            .write()
+            .unwrap()
+            .values_mut()
+            .filter(|p| p.metadata.template_source() == url)
+        {
+            fork.metadata.orphaned = true;
+        }
+    }
+
+    /// Check the paste's password, unless `editing_as` is the paste owner or a paste manager
+    async fn check_password(
+        &self,
+        paste: &Paste,
+        password: String,
+        editing_as: &Option<FullUser<UserMetadata>>,
+    ) -> Result<()> {
+        if let Some(ua) = editing_as {
+            if (ua.user.username == paste.metadata.owner)
+                | ua.level.permissions.contains(&"ManagePastes".to_string())
+            {
+                return Ok(());
+            }
+        }
+
+        self.check_password_lockout(paste.url.clone()).await?;
+
+        let valid = password::verify(&password, &paste.password).await != Verification::Invalid;
+        self.record_password_attempt(paste.url.clone(), valid).await;
+
+        if !valid {
+            return Err(PasteError::PasswordIncorrect);
+        }
+
+        Ok(())
+    }
+
+    /// Normalize the given paste urls, making sure every paste exists
+    async fn resolve_collection_pastes(&self, urls: Vec<String>) -> Result<Vec<String>> {
+        let mut pastes: Vec<String> = Vec::new();
+
+        for url in urls {
+            match self.get_paste_by_url(url).await {
+                Ok(p) => pastes.push(p.url),
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(pastes)
+    }
+}
+
+/// Normalize a paste url the same way [`Database`](crate::database::Database) does
+fn normalize_url(url: String) -> String {
+    let mut url = idna::punycode::encode_str(&url).unwrap().to_lowercase();
+
+    if url.ends_with("-") {
+        url.pop();
+    }
+
+    url
+}
+
+#[async_trait]
+impl PasteStore for MemoryStore {
+    fn options(&self) -> &ServerOptions {
+        &self.options
+    }
+
+    async fn get_user_by_token(&self, _token: String) -> Result<FullUser<UserMetadata>> {
+        // guppy is not supported
+        Err(PasteError::Other)
+    }
+
+    // pastes
+
+    async fn get_paste_by_url(&self, url: String) -> Result<Paste> {
+        let url = normalize_url(url);
+
+        let paste = match self.pastes.read().unwrap().get(&url) {
+            Some(p) => p.clone(),
+            None => return Err(PasteError::NotFound),
+        };
+
+        if paste.is_expired() {
+            self.remove_paste(&url);
+            return Err(PasteError::NotFound);
+        }
+
+        // pastes in the trash keep their url until they're purged
+        if paste.is_trashed() {
+            if paste.trashed_at + (self.options.trash_retention as u128 * 1000)
+                <= utility::unix_epoch_timestamp()
+            {
+                self.remove_paste(&url);
+            }
+
+            return Err(PasteError::NotFound);
+        }
+
+        Ok(paste)
+    }
+
+    async fn create_paste(
+        &self,
+        mut props: PasteCreate,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<(String, Paste)> {
+        props.url = normalize_url(props.url);
+
+        // create url if not supplied
+        if props.url.is_empty() {
+            props.url = utility::random_id().chars().take(10).collect();
+        }
+
+        // create random password if not supplied
+        if props.password.is_empty() {
+            props.password = utility::random_id().chars().take(10).collect();
+        }
+
+        // check values
+        self.options.validation.check_url(&props.url)?;
+
+        self.options.validation.check_content(&props.content)?;
+
+        if (props.expires_at != 0) && (props.expires_at <= utility::unix_epoch_timestamp()) {
+            return Err(PasteError::ValueError);
+        }
+
+        if props.max_views < 0 {
+            return Err(PasteError::ValueError);
+        }
+
+        // free the url if it's held by an expired paste
+        let _ = self.get_paste_by_url(props.url.clone()).await;
+
+        // ...
+        let paste = Paste {
+            id: utility::random_id(),
+            url: props.url,
+            content: props.content,
+            password: password::hash(&props.password).await,
+            date_published: utility::unix_epoch_timestamp(),
+            date_edited: utility::unix_epoch_timestamp(),
+            metadata: PasteMetadata {
+                owner: owner_of_new_paste(&self.options, &editing_as),
+                ..Default::default()
+            },
+            expires_at: props.expires_at,
+            max_views: props.max_views,
+            trashed_at: 0,
+        };
+
+        // create paste
+        let mut pastes = self.pastes.write().unwrap();
+
+        if pastes.contains_key(&paste.url) {
+            return Err(PasteError::AlreadyExists);
+        }
+
+        pastes.insert(paste.url.clone(), paste.clone());
+        Ok((props.password, paste))
+    }
+
+    async fn clone_paste(
+        &self,
+        mut props: PasteClone,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<(String, Paste)> {
+        props.url = normalize_url(props.url);
+
+        // make sure paste source exists
+        let source = match self.get_paste_by_url(props.source).await {
+            Ok(p) => p,
+            Err(e) => return Err(e),
+        };
+
+        // create url if not supplied
+        if props.url.is_empty() {
+            props.url = utility::random_id().chars().take(10).collect();
+        }
+
+        // create random password if not supplied
+        if props.password.is_empty() {
+            props.password = utility::random_id().chars().take(10).collect();
+        }
+
+        // check values
+        self.options.validation.check_url(&props.url)?;
+
+        // fill in template variables
+        let values = match resolve_variables(&source.metadata.variables, &props.values) {
+            Ok(v) => v,
+            Err(e) => return Err(e),
+        };
+
+        let content = fill_variables(&source.content, &values);
+
+        self.options.validation.check_content(&content)?;
+
+        // free the url if it's held by an expired paste
+        let _ = self.get_paste_by_url(props.url.clone()).await;
+
+        // ...
+        let paste = Paste {
+            id: utility::random_id(),
+            url: props.url,
+            content,
+            password: password::hash(&props.password).await,
+            date_published: utility::unix_epoch_timestamp(),
+            date_edited: utility::unix_epoch_timestamp(),
+            // use other paste as a template
+            metadata: PasteMetadata {
+                owner: owner_of_new_paste(&self.options, &editing_as),
+                template_values: values,
+                ..PasteMetadata::from(source)
+            },
+            expires_at: 0,
+            max_views: 0,
+            trashed_at: 0,
+        };
+
+        // create paste
+        let mut pastes = self.pastes.write().unwrap();
+
+        if pastes.contains_key(&paste.url) {
+            return Err(PasteError::AlreadyExists);
+        }
+
+        pastes.insert(paste.url.clone(), paste.clone());
+        Ok((props.password, paste))
+    }
+
+    async fn delete_paste_by_url(
+        &self,
+        url: String,
+        password: String,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()> {
+        let existing = match self.get_paste_by_url(url).await {
+            Ok(p) => p,
+            Err(e) => return Err(e),
+        };
+
+        self.check_password(&existing, password, &editing_as)
+            .await?;
+
+        // move paste to the trash (or delete it right away if the trash is disabled)
+        if self.options.trash_retention == 0 {
+            self.remove_paste(&existing.url);
+        } else if let Some(paste) = self.pastes.write().unwrap().get_mut(&existing.url) {
+            paste.trashed_at = utility::unix_epoch_timestamp();
+        }
+
+        Ok(())
+    }
+
+    async fn restore_paste_by_url(
+        &self,
+        url: String,
+        password: String,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()> {
+        let url = normalize_url(url);
+
+        let existing = match self.pastes.read().unwrap().get(&url) {
+            Some(p) => p.clone(),
+            None => return Err(PasteError::NotFound),
+        };
+
+        self.check_password(&existing, password, &editing_as)
+            .await?;
+
+        if !existing.is_trashed() {
+            return Err(PasteError::ValueError);
+        }
+
+        if let Some(paste) = self.pastes.write().unwrap().get_mut(&url) {
+            paste.trashed_at = 0;
+        }
+
+        Ok(())
+    }
+
+    async fn edit_paste_by_url(
+        &self,
+        url: String,
+        password: String,
+        new_content: String,
+        new_url: String,
+        new_password: String,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()> {
+        let existing = match self.get_paste_by_url(url).await {
+            Ok(p) => p,
+            Err(e) => return Err(e),
+        };
+
+        self.check_password(&existing, password, &editing_as)
+            .await?;
+
+        let new_url = if new_url.is_empty() {
+            existing.url.clone()
+        } else {
+            normalize_url(new_url)
+        };
+
+        // check values
+        if new_url != existing.url {
+            self.options.validation.check_url(&new_url)?
+        }
+
+        self.options.validation.check_content(&new_content)?;
+
+        let new_password = if new_password.is_empty() {
+            existing.password.clone()
+        } else {
+            password::hash(&new_password).await
+        };
+
+        // update paste (the revision shares the edit's timestamp, so it's never newer than
+        // the template revision a fork recorded from this edit)
+        let now = utility::unix_epoch_timestamp();
+        let mut pastes = self.pastes.write().unwrap();
+
+        if (new_url != existing.url) && pastes.contains_key(&new_url) {
+            return Err(PasteError::AlreadyExists);
+        }
+
+        pastes.remove(&existing.url);
+        pastes.insert(
+            new_url.clone(),
+            Paste {
+                url: new_url.clone(),
+                content: new_content.clone(),
+                password: new_password,
+                date_edited: now,
+                ..existing.clone()
+            },
+        );
+
+        // point forks at the new url
+        if new_url != existing.url {
+            for fork in pastes
+                .values_mut()
+                .filter(|p| p.metadata.template_source() == existing.url)
+            {
+                fork.metadata.template = new_url.clone();
+            }
+        }
+
+        drop(pastes);
+
+        // store the content we replaced as a revision
+        let mut revisions = self.revisions.write().unwrap();
+
+        for revision in revisions.iter_mut().filter(|r| r.url == existing.url) {
+            revision.url = new_url.clone();
+        }
+
+        for attachment in self
+            .attachments
+            .write()
+            .unwrap()
+            .iter_mut()
+            .filter(|a| a.0.url == existing.url)
+        {
+            attachment.0.url = new_url.clone();
+        }
+
+        for collection in self.collections.write().unwrap().values_mut() {
+            for url in collection.pastes.iter_mut().filter(|u| **u == existing.url) {
+                *url = new_url.clone();
+            }
+        }
+
+        if new_content != existing.content {
+            revisions.push(PasteRevision {
+                id: utility::random_id(),
+                url: new_url,
+                content: existing.content,
+                actor: match editing_as {
+                    Some(ua) => ua.user.username,
+                    None => String::new(),
+                },
+                timestamp: now,
+            });
+        }
+
+        Ok(())
+    }
+
+    async fn edit_paste_metadata_by_url(
+        &self,
+        url: String,
+        password: String,
+        mut metadata: PasteMetadata,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()> {
+        let existing = match self.get_paste_by_url(url).await {
+            Ok(p) => p,
+            Err(e) => return Err(e),
+        };
+
+        self.check_password(&existing, password, &editing_as)
+            .await?;
+
+        hash_view_password(&mut metadata, &existing.metadata).await;
+        keep_ownership(&mut metadata, &existing.metadata);
+        keep_template_fields(&mut metadata, &existing.metadata);
+
+        check_variables(&metadata)?;
+
+        normalize_tags(&mut metadata)?;
+
+        if let Some(paste) = self.pastes.write().unwrap().get_mut(&existing.url) {
+            paste.metadata = metadata;
+        }
+
+        Ok(())
+    }
+
+    async fn edit_paste_expiry_by_url(
+        &self,
+        url: String,
+        password: String,
+        expires_at: u128,
+        max_views: i32,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()> {
+        let existing = match self.get_paste_by_url(url).await {
+            Ok(p) => p,
+            Err(e) => return Err(e),
+        };
+
+        self.check_password(&existing, password, &editing_as)
+            .await?;
+
+        if ((expires_at != 0) && (expires_at <= utility::unix_epoch_timestamp())) | (max_views < 0)
+        {
+            return Err(PasteError::ValueError);
+        }
+
+        if let Some(paste) = self.pastes.write().unwrap().get_mut(&existing.url) {
+            paste.expires_at = expires_at;
+            paste.max_views = max_views;
+        }
+
+        Ok(())
+    }
+
+    // rate limits
+
+    async fn hit_rate_limit(&self, key: String, limit: RateLimit) -> Result<()> {
+        if limit.requests == 0 {
+            return Ok(());
+        }
+
+        let mut limits = self.limits.write().unwrap();
+        let window = next_window(
+            limits.get(&key).copied(),
+            &limit,
+            utility::unix_epoch_timestamp(),
+        );
+
+        limits.insert(key, window);
+
+        if window.1 > limit.requests {
+            return Err(PasteError::RateLimited);
+        }
+
+        Ok(())
+    }
+
+    async fn check_password_lockout(&self, url: String) -> Result<()> {
+        match self.limits.read().unwrap().get(&format!("lockout:{url}")) {
+            Some((until, _)) if *until > utility::unix_epoch_timestamp() => {
+                Err(PasteError::RateLimited)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    async fn record_password_attempt(&self, url: String, success: bool) {
+        let key = format!("lockout:{url}");
+        let mut limits = self.limits.write().unwrap();
+
+        if success {
+            limits.remove(&key);
+            return;
+        }
+
+        let state = next_lockout(
+            limits.get(&key).copied(),
+            &self.options.rate_limits,
+            utility::unix_epoch_timestamp(),
+        );
+
+        limits.insert(key, state);
+    }
+
+    // ownership
+
+    async fn claim_paste_by_url(
+        &self,
+        url: String,
+        password: String,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()> {
+        let owner = owner_of_new_paste(&self.options, &editing_as);
+
+        if owner.is_empty() {
+            return Err(PasteError::ValueError);
+        }
+
+        let existing = match self.get_paste_by_url(url).await {
+            Ok(p) => p,
+            Err(e) => return Err(e),
+        };
+
+        if !existing.metadata.owner.is_empty() {
+            return Err(PasteError::ValueError);
+        }
+
+        self.check_password(&existing, password, &None).await?;
+
+        if let Some(paste) = self.pastes.write().unwrap().get_mut(&existing.url) {
+            paste.metadata.owner = owner;
+            paste.metadata.pending_owner = String::new();
+        }
+
+        Ok(())
+    }
+
+    async fn transfer_paste_by_url(
+        &self,
+        url: String,
+        username: String,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()> {
+        let existing = match self.get_paste_by_url(url).await {
+            Ok(p) => p,
+            Err(e) => return Err(e),
+        };
+
+        if !can_transfer(&existing, &editing_as) {
+            return Err(PasteError::PasswordIncorrect);
+        }
+
+        let username = username.trim().to_string();
+
+        if !username.is_empty() && (username == existing.metadata.owner) {
+            return Err(PasteError::ValueError);
+        }
+
+        if let Some(paste) = self.pastes.write().unwrap().get_mut(&existing.url) {
+            paste.metadata.pending_owner = username;
+        }
+
+        Ok(())
+    }
+
+    async fn accept_transfer_by_url(
+        &self,
+        url: String,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()> {
+        let existing = match self.get_paste_by_url(url).await {
+            Ok(p) => p,
+            Err(e) => return Err(e),
+        };
+
+        let owner = owner_of_new_paste(&self.options, &editing_as);
+
+        if owner.is_empty() | (owner != existing.metadata.pending_owner) {
+            return Err(PasteError::PasswordIncorrect);
+        }
+
+        if let Some(paste) = self.pastes.write().unwrap().get_mut(&existing.url) {
+            paste.metadata.owner = owner;
+            paste.metadata.pending_owner = String::new();
+        }
+
+        Ok(())
+    }
+
+    // listings
+
+    async fn list_pastes_by_owner(
+        &self,
+        owner: String,
+        sort: PasteSort,
+        cursor: String,
+        limit: i64,
+        include_private: bool,
+    ) -> Result<PasteList> {
+        if owner.is_empty() | !(1..=100).contains(&limit) {
+            return Err(PasteError::ValueError);
+        }
+
+        let sort_value = |p: &Paste| match sort {
+            PasteSort::DatePublished => p.date_published,
+            PasteSort::DateEdited => p.date_edited,
+        };
+
+        // the cursor is the sort value and id of the last paste of the previous page
+        let cursor = match cursor.split_once(":") {
+            Some((value, id)) => match value.parse::<u128>() {
+                Ok(value) => Some((value, id.to_string())),
+                Err(_) => return Err(PasteError::ValueError),
+            },
+            None => {
+                if !cursor.is_empty() {
+                    return Err(PasteError::ValueError);
+                }
+
+                None
+            }
+        };
+
+        let mut owned: Vec<Paste> = self
+            .pastes
+            .read()
+            .unwrap()
+            .values()
+            .filter(|p| p.metadata.owner == owner)
+            .filter(|p| match cursor {
+                Some((ref value, ref id)) => (sort_value(p), &p.id) < (*value, id),
+                None => true,
+            })
+            .cloned()
+            .collect();
+
+        owned.sort_by(|a, b| (sort_value(b), &b.id).cmp(&(sort_value(a), &a.id)));
+
+        // build page
+        let has_more = owned.len() as i64 > limit;
+        let page: Vec<Paste> = owned.into_iter().take(limit as usize).collect();
+
+        let next_cursor = match page.last() {
+            Some(p) if has_more => format!("{}:{}", sort_value(p), p.id),
+            _ => String::new(),
+        };
+
+        Ok(PasteList {
+            pastes: page
+                .into_iter()
+                .filter(|p| !p.is_expired() && !p.is_trashed())
+                .filter(|p| include_private | p.metadata.view_password.is_empty())
+                .map(|p| p.into())
+                .collect(),
+            next_cursor,
+        })
+    }
+
+    async fn get_forks_by_url(&self, url: String, limit: i64, offset: i64) -> Result<PasteForks> {
+        if !(1..=100).contains(&limit) | (offset < 0) {
+            return Err(PasteError::ValueError);
+        }
+
+        // make sure template exists
+        let existing = match self.get_paste_by_url(url).await {
+            Ok(p) => p,
+            Err(e) => return Err(e),
+        };
+
+        let mut forks: Vec<Paste> = self
+            .pastes
+            .read()
+            .unwrap()
+            .values()
+            .filter(|p| (p.metadata.template_source() == existing.url) && !p.is_trashed())
+            .cloned()
+            .collect();
+
+        forks.sort_by(|a, b| (b.date_published, &b.id).cmp(&(a.date_published, &a.id)));
+
+        Ok(PasteForks {
+            count: forks.len() as i64,
+            forks: forks
+                .into_iter()
+                .skip(offset as usize)
+                .take(limit as usize)
+                .filter(|p| !p.is_expired() && p.metadata.view_password.is_empty())
+                .map(|p| p.into())
+                .collect(),
+        })
+    }
+
+    async fn search_pastes(
+        &self,
+        query: String,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<PasteSearchResult>> {
+        let terms: Vec<String> = query.split_whitespace().map(|t| t.to_lowercase()).collect();
+
+        if terms.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        if !(1..=100).contains(&limit) | (offset < 0) {
+            return Err(PasteError::ValueError);
+        }
+
+        // rank by the number of times the terms appear
+        let mut matches: Vec<(usize, Paste)> = self
+            .pastes
+            .read()
+            .unwrap()
+            .values()
+            .filter(|p| p.metadata.view_password.is_empty() && !p.is_expired() && !p.is_trashed())
+            .map(|p| {
+                let haystack = format!(
+                    "{} {} {}",
+                    p.metadata.title, p.metadata.description, p.content
+                )
+                .to_lowercase();
+
+                (
+                    terms
+                        .iter()
+                        .map(|t| haystack.matches(t.as_str()).count())
+                        .sum(),
+                    p.clone(),
+                )
+            })
+            .filter(|(rank, _)| *rank > 0)
+            .collect();
+
+        matches.sort_by_key(|m| std::cmp::Reverse(m.0));
+
+        Ok(matches
+            .into_iter()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .map(|(_, p)| PasteSearchResult {
+                url: p.url,
+                title: p.metadata.title,
+                description: p.metadata.description,
+                snippet: p.content.chars().take(200).collect(),
+            })
+            .collect())
+    }
+
+    // revisions
+
+    async fn get_revisions_by_url(&self, url: String) -> Result<Vec<PasteRevision>> {
+        let existing = match self.get_paste_by_url(url).await {
+            Ok(p) => p,
+            Err(e) => return Err(e),
+        };
+
+        let mut revisions: Vec<PasteRevision> = self
+            .revisions
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|r| r.url == existing.url)
+            .cloned()
+            .collect();
+
+        revisions.sort_by_key(|r| std::cmp::Reverse(r.timestamp));
+        Ok(revisions)
+    }
+
+    async fn get_revision_by_id(&self, url: String, id: String) -> Result<PasteRevision> {
+        let url = normalize_url(url);
+
+        match self
+            .revisions
+            .read()
+            .unwrap()
+            .iter()
+            .find(|r| (r.url == url) && (r.id == id))
+        {
+            Some(r) => Ok(r.clone()),
+            None => Err(PasteError::NotFound),
+        }
+    }
+
+    async fn restore_revision_by_id(
+        &self,
+        url: String,
+        id: String,
+        password: String,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()> {
+        let revision = match self.get_revision_by_id(url.clone(), id).await {
+            Ok(r) => r,
+            Err(e) => return Err(e),
+        };
+
+        self.edit_paste_by_url(
+            url,
+            password,
+            revision.content,
+            String::new(),
+            String::new(),
+            editing_as,
+        )
+        .await
+    }
+
+    // attachments
+
+    async fn upload_attachment(
+        &self,
+        url: String,
+        password: String,
+        name: String,
+        content_type: String,
+        data: Vec<u8>,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<PasteAttachment> {
+        let existing = match self.get_paste_by_url(url).await {
+            Ok(p) => p,
+            Err(e) => return Err(e),
+        };
+
+        self.check_password(&existing, password, &editing_as)
+            .await?;
+
+        self.options
+            .attachments
+            .check(&name, &content_type, data.len())?;
+
+        // replace the attachment with the same name
+        let mut attachments = self.attachments.write().unwrap();
+        attachments.retain(|a| (a.0.url != existing.url) | (a.0.name != name));
+
+        if attachments
+            .iter()
+            .filter(|a| a.0.url == existing.url)
+            .count()
+            >= self.options.attachments.max_count
+        {
+            return Err(PasteError::ValueError);
+        }
+
+        let attachment = PasteAttachment {
+            id: utility::random_id(),
+            url: existing.url,
+            name,
+            content_type,
+            size: data.len() as i64,
+            date_published: utility::unix_epoch_timestamp(),
+        };
+
+        attachments.push((attachment.clone(), data));
+        Ok(attachment)
+    }
+
+    async fn get_attachments_by_url(&self, url: String) -> Result<Vec<PasteAttachment>> {
+        let url = normalize_url(url);
+
+        Ok(self
+            .attachments
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|a| a.0.url == url)
+            .map(|a| a.0.clone())
+            .collect())
+    }
+
+    async fn get_attachment_by_name(
+        &self,
+        url: String,
+        name: String,
+    ) -> Result<(PasteAttachment, Vec<u8>)> {
+        // make sure paste exists (attachments of pastes in the trash aren't served)
+        let existing = match self.get_paste_by_url(url).await {
+            Ok(p) => p,
+            Err(e) => return Err(e),
+        };
+
+        match self
+            .attachments
+            .read()
+            .unwrap()
+            .iter()
+            .find(|a| (a.0.url == existing.url) && (a.0.name == name))
+        {
+            Some(a) => Ok(a.clone()),
+            None => Err(PasteError::NotFound),
+        }
+    }
+
+    async fn delete_attachment_by_name(
+        &self,
+        url: String,
+        name: String,
+        password: String,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()> {
+        let existing = match self.get_paste_by_url(url).await {
+            Ok(p) => p,
+            Err(e) => return Err(e),
+        };
+
+        self.check_password(&existing, password, &editing_as)
+            .await?;
+
+        let mut attachments = self.attachments.write().unwrap();
+        let count = attachments.len();
+        attachments.retain(|a| (a.0.url != existing.url) | (a.0.name != name));
+
+        if attachments.len() == count {
+            return Err(PasteError::NotFound);
+        }
+
+        Ok(())
+    }
+
+    // templates
+
+    async fn sync_template_by_url(
+        &self,
+        url: String,
+        password: String,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<TemplateSync> {
+        let existing = match self.get_paste_by_url(url).await {
+            Ok(p) => p,
+            Err(e) => return Err(e),
+        };
+
+        self.check_password(&existing, password, &editing_as)
+            .await?;
+
+        // merge
+        let (template, base) = match self.get_template_base(&existing).await {
+            Ok(t) => t,
+            Err(e) => return Err(e),
+        };
+
+        let sync = sync_template(&existing, &template, &base);
+
+        if sync.conflicted {
+            return Ok(sync);
+        }
+
+        self.options.validation.check_content(&sync.content)?;
+
+        // update paste
+        let now = utility::unix_epoch_timestamp();
+        let mut pastes = self.pastes.write().unwrap();
+
+        let paste = match pastes.get_mut(&existing.url) {
+            Some(p) => p,
+            None => return Err(PasteError::NotFound),
+        };
+
+        paste.metadata.template_revision = template.date_edited;
+
+        if sync.content != existing.content {
+            paste.content = sync.content.clone();
+            paste.date_edited = now;
+
+            // store the content we replaced as a revision
+            self.revisions.write().unwrap().push(PasteRevision {
+                id: utility::random_id(),
+                url: existing.url,
+                content: existing.content,
+                actor: match editing_as {
+                    Some(ua) => ua.user.username,
+                    None => String::new(),
+                },
+                timestamp: now,
+            });
+        }
+
+        Ok(sync)
+    }
+
+    // tags
+
+    async fn get_pastes_by_tag(&self, tag: String, limit: i64, offset: i64) -> Result<PasteTagged> {
+        if !(1..=100).contains(&limit) | (offset < 0) {
+            return Err(PasteError::ValueError);
+        }
+
+        let tag = normalize_tag(&tag);
+
+        let mut pastes: Vec<Paste> = self
+            .pastes
+            .read()
+            .unwrap()
+            .values()
+            .filter(|p| {
+                p.metadata.tags.contains(&tag)
+                    && !p.is_trashed()
+                    && !p.is_expired()
+                    && p.metadata.view_password.is_empty()
+            })
+            .cloned()
+            .collect();
+
+        pastes.sort_by(|a, b| (b.date_published, &b.id).cmp(&(a.date_published, &a.id)));
+
+        Ok(PasteTagged {
+            tag,
+            count: pastes.len() as i64,
+            pastes: pastes
+                .into_iter()
+                .skip(offset as usize)
+                .take(limit as usize)
+                .map(|p| p.into())
+                .collect(),
+        })
+    }
+
+    async fn get_tags(&self, limit: i64) -> Result<Vec<TagCount>> {
+        if !(1..=500).contains(&limit) {
+            return Err(PasteError::ValueError);
+        }
+
+        let mut counts: HashMap<String, i64> = HashMap::new();
+
+        for paste in
+            self.pastes.read().unwrap().values().filter(|p| {
+                !p.is_trashed() && !p.is_expired() && p.metadata.view_password.is_empty()
+            })
+        {
+            for tag in &paste.metadata.tags {
+                *counts.entry(tag.clone()).or_insert(0) += 1;
+            }
+        }
+
+        let mut tags: Vec<TagCount> = counts
+            .into_iter()
+            .map(|(tag, count)| TagCount { tag, count })
+            .collect();
+
+        tags.sort_by(|a, b| (b.count, &a.tag).cmp(&(a.count, &b.tag)));
+        tags.truncate(limit as usize);
+
+        Ok(tags)
+    }
+
+    // collections
+
+    async fn get_collection_by_name(&self, name: String) -> Result<Collection> {
+        match self.collections.read().unwrap().get(&normalize_url(name)) {
+            Some(c) => Ok(c.clone()),
+            None => Err(PasteError::NotFound),
+        }
+    }
+
+    async fn get_collections_by_url(&self, url: String) -> Result<Vec<Collection>> {
+        let url = normalize_url(url);
+
+        let mut collections: Vec<Collection> = self
+            .collections
+            .read()
+            .unwrap()
+            .values()
+            .filter(|c| c.pastes.contains(&url))
+            .cloned()
+            .collect();
+
+        collections.sort_by_key(|a| a.date_published);
+        Ok(collections)
+    }
+
+    async fn create_collection(
+        &self,
+        mut props: CollectionCreate,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<(String, Collection)> {
+        props.name = normalize_url(props.name);
+
+        // create name if not supplied
+        if props.name.is_empty() {
+            props.name = utility::random_id().chars().take(10).collect();
+        }
+
+        // create random password if not supplied
+        if props.password.is_empty() {
+            props.password = utility::random_id().chars().take(10).collect();
+        }
+
+        // check values
+        self.options.validation.check_url(&props.name)?;
+
+        let pastes = match self.resolve_collection_pastes(props.pastes).await {
+            Ok(p) => p,
+            Err(e) => return Err(e),
+        };
+
+        // ...
+        let collection = Collection {
+            id: utility::random_id(),
+            name: props.name,
+            title: props.title,
+            description: props.description,
+            owner: match editing_as {
+                Some(ua) if self.options.paste_ownership => ua.user.username,
+                _ => String::new(),
+            },
+            password: password::hash(&props.password).await,
+            pastes,
+            date_published: utility::unix_epoch_timestamp(),
+            date_edited: utility::unix_epoch_timestamp(),
+        };
+
+        check_collection(&collection)?;
+
+        // create collection
+        let mut collections = self.collections.write().unwrap();
+
+        if collections.contains_key(&collection.name) {
+            return Err(PasteError::AlreadyExists);
+        }
+
+        collections.insert(collection.name.clone(), collection.clone());
+        Ok((props.password, collection))
+    }
+
+    async fn edit_collection_by_name(
+        &self,
+        name: String,
+        password: String,
+        title: String,
+        description: String,
+        new_password: String,
+        pastes: Vec<String>,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()> {
+        let existing = match self.get_collection_by_name(name).await {
+            Ok(c) => c,
+            Err(e) => return Err(e),
+        };
+
+        self.check_collection_access(&existing, password, &editing_as)
+            .await?;
+
+        // check values
+        let pastes = match self.resolve_collection_pastes(pastes).await {
+            Ok(p) => p,
+            Err(e) => return Err(e),
+        };
+
+        let collection = Collection {
+            title,
+            description,
+            password: if new_password.is_empty() {
+                existing.password.clone()
+            } else {
+                password::hash(&new_password).await
+            },
+            pastes,
+            date_edited: utility::unix_epoch_timestamp(),
+            ..existing
+        };
+
+        check_collection(&collection)?;
+
+        // update collection
+        self.collections
+            .write()
+            .unwrap()
+            .insert(collection.name.clone(), collection);
+
+        Ok(())
+    }
+
+    async fn delete_collection_by_name(
+        &self,
+        name: String,
+        password: String,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()> {
+        let existing = match self.get_collection_by_name(name).await {
+            Ok(c) => c,
+            Err(e) => return Err(e),
+        };
+
+        self.check_collection_access(&existing, password, &editing_as)
+            .await?;
+
+        self.collections.write().unwrap().remove(&existing.name);
+        Ok(())
+    }
+
+    // views
+
+    async fn get_views_by_url(&self, url: String) -> i32 {
+        let url = normalize_url(url);
+        *self.views.read().unwrap().get(&url).unwrap_or(&0)
+    }
+
+    async fn incr_views_by_url(
+        &self,
+        url: String,
+        as_user: Option<FullUser<UserMetadata>>,
+        viewer: String,
+        referrer: String,
+    ) -> Result<()> {
+        let url = normalize_url(url);
+
+        // pastes with a view limit count every read, so they can't be read past it
+        let max_views = match self.get_paste_by_url(url.clone()).await {
+            Ok(p) => p.max_views,
+            Err(_) => 0,
+        };
+
+        // handle AuthenticatedOnce
+        if (self.options.view_mode == ViewMode::AuthenticatedOnce) && (max_views == 0) {
+            match as_user {
+                Some(ua) => {
+                    let mut viewers = self.viewers.write().unwrap();
+                    let view = (url.clone(), ua.user.username);
+
+                    if viewers.contains(&view) {
+                        // can only view once in this mode
+                        return Ok(());
+                    }
+
+                    viewers.push(view);
+                }
+                None => return Ok(()), // not technically an error, just not allowed
+            }
+        }
+
+        // handle OpenDeduplicated
+        if let ViewMode::OpenDeduplicated { window_secs } = self.options.view_mode {
+            if !viewer.is_empty() && (max_views == 0) {
+                let mut seen = self.seen.write().unwrap();
+                let now = utility::unix_epoch_timestamp();
+                let until = seen.entry((url.clone(), viewer)).or_insert(0);
+
+                if *until > now {
+                    // already counted in this window
+                    return Ok(());
+                }
+
+                *until = now + (window_secs as u128 * 1000);
+            }
+        }
+
+        // add view
+        let views = {
+            let mut views = self.views.write().unwrap();
+            let count = views.entry(url.clone()).or_insert(0);
+            *count += 1;
+            *count
+        };
+
+        // record view event
+        let day = (utility::unix_epoch_timestamp() / 86_400_000) * 86_400_000;
+        *self
+            .stats
+            .write()
+            .unwrap()
+            .entry((url.clone(), day, referrer_host(&referrer)))
+            .or_insert(0) += 1;
+
+        // burn paste if it has reached its view limit
+        if (max_views > 0) && (views >= max_views) {
+            self.remove_paste(&url);
+        }
+
+        Ok(())
+    }
+
+    async fn get_stats_by_url(&self, url: String, days: i64) -> Result<PasteStats> {
+        let existing = match self.get_paste_by_url(url).await {
+            Ok(p) => p,
+            Err(e) => return Err(e),
+        };
+
+        if !(1..=365).contains(&days) {
+            return Err(PasteError::ValueError);
+        }
+
+        let today = (utility::unix_epoch_timestamp() / 86_400_000) * 86_400_000;
+        let since = today - ((days as u128 - 1) * 86_400_000);
+
+        let mut series: HashMap<u128, i64> = HashMap::new();
+        let mut referrers: HashMap<String, i64> = HashMap::new();
+
+        for ((url, day, host), views) in self.stats.read().unwrap().iter() {
+            if (*url != existing.url) | (*day < since) {
+                continue;
+            }
+
+            *series.entry(*day).or_insert(0) += views;
+            *referrers.entry(host.clone()).or_insert(0) += views;
+        }
+
+        let mut series: Vec<PasteStatsDay> = series
+            .into_iter()
+            .map(|(day, views)| PasteStatsDay { day, views })
+            .collect();
+
+        series.sort_by_key(|a| a.day);
+
+        let mut referrers: Vec<PasteStatsReferrer> = referrers
+            .into_iter()
+            .map(|(host, views)| PasteStatsReferrer { host, views })
+            .collect();
+
+        referrers.sort_by(|a, b| (b.views, &a.host).cmp(&(a.views, &b.host)));
+        referrers.truncate(10);
+
+        Ok(PasteStats {
+            views: self.get_views_by_url(existing.url).await,
+            days: series,
+            referrers,
+        })
+    }
+
+    async fn user_has_viewed_paste(&self, url: String, username: String) -> bool {
+        self.viewers
+            .read()
+            .unwrap()
+            .contains(&(normalize_url(url), username))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MemoryStore;
+    use crate::database::{ServerOptions, ViewMode};
+    use crate::model::{PasteCreate, PasteError};
+    use crate::store::PasteStore;
+
+    /// Create a paste at `url` with a known password
+    async fn create(store: &MemoryStore, url: &str, content: &str) {
+        store
+            .create_paste(
+                PasteCreate {
+                    url: url.to_string(),
+                    content: content.to_string(),
+                    password: "password".to_string(),
+                    expires_at: 0,
+                    max_views: 0,
+                },
+                None,
+            )
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn create_paste() {
+        let store = MemoryStore::new(ServerOptions::default());
+
+        let (password, paste) = store
+            .create_paste(
+                PasteCreate {
+                    url: "Hello".to_string(),
+                    content: "world".to_string(),
+                    password: String::new(),
+                    expires_at: 0,
+                    max_views: 0,
+                },
+                None,
+            )
+            .await
+            .unwrap();
+
+        // urls are normalized and a password is generated
+        assert_eq!(paste.url, "hello");
+        assert_eq!(password.len(), 10);
+        assert_ne!(paste.password, password);
+
+        let paste = store.get_paste_by_url("HELLO".to_string()).await.unwrap();
+        assert_eq!(paste.content, "world");
+        assert!(paste.metadata.owner.is_empty());
+
+        // urls are unique
+        let res = store
+            .create_paste(
+                PasteCreate {
+                    url: "hello".to_string(),
+                    content: "again".to_string(),
+                    password: String::new(),
+                    expires_at: 0,
+                    max_views: 0,
+                },
+                None,
+            )
+            .await;
+
+        assert!(matches!(res, Err(PasteError::AlreadyExists)));
+    }
+
+    #[tokio::test]
+    async fn edit_paste() {
+        let store = MemoryStore::new(ServerOptions::default());
+        create(&store, "hello", "first").await;
+
+        // wrong password
+        let res = store
+            .edit_paste_by_url(
+                "hello".to_string(),
+                "wrong".to_string(),
+                "second".to_string(),
+                String::new(),
+                String::new(),
+                None,
+            )
+            .await;
+
+        assert!(matches!(res, Err(PasteError::PasswordIncorrect)));
+
+        // edit and rename
+        store
+            .edit_paste_by_url(
+                "hello".to_string(),
+                "password".to_string(),
+                "second".to_string(),
+                "renamed".to_string(),
+                String::new(),
+                None,
+            )
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            store.get_paste_by_url("hello".to_string()).await,
+            Err(PasteError::NotFound)
+        ));
+
+        let paste = store.get_paste_by_url("renamed".to_string()).await.unwrap();
+        assert_eq!(paste.content, "second");
+
+        // the replaced content is kept as a revision of the renamed paste
+        let revisions = store
+            .get_revisions_by_url("renamed".to_string())
+            .await
+            .unwrap();
+
+        assert_eq!(revisions.len(), 1);
+        assert_eq!(revisions[0].content, "first");
+        assert_eq!(revisions[0].timestamp, paste.date_edited);
+
+        // renaming onto a taken url fails
+        create(&store, "taken", "content").await;
+
+        let res = store
+            .edit_paste_by_url(
+                "renamed".to_string(),
+                "password".to_string(),
+                "third".to_string(),
+                "taken".to_string(),
+                String::new(),
+                None,
+            )
+            .await;
+
+        assert!(matches!(res, Err(PasteError::AlreadyExists)));
+    }
+
+    #[tokio::test]
+    async fn delete_paste() {
+        let store = MemoryStore::new(ServerOptions::default());
+        create(&store, "hello", "content").await;
+
+        let res = store
+            .delete_paste_by_url("hello".to_string(), "wrong".to_string(), None)
+            .await;
+
+        assert!(matches!(res, Err(PasteError::PasswordIncorrect)));
+
+        store
+            .delete_paste_by_url("hello".to_string(), "password".to_string(), None)
+            .await
+            .unwrap();
+
+        // trashed pastes are hidden, but keep their url until they're purged
+        assert!(matches!(
+            store.get_paste_by_url("hello".to_string()).await,
+            Err(PasteError::NotFound)
+        ));
+
+        assert_eq!(store.purge_pastes(), 0);
+        assert!(store.pastes.read().unwrap().contains_key("hello"));
+
+        // and can be restored with the password
+        store
+            .restore_paste_by_url("hello".to_string(), "password".to_string(), None)
+            .await
+            .unwrap();
+
+        assert!(store.get_paste_by_url("hello".to_string()).await.is_ok());
+
+        // without a trash, pastes are deleted right away
+        let store = MemoryStore::new(ServerOptions {
+            trash_retention: 0,
+            ..Default::default()
+        });
+
+        create(&store, "hello", "content").await;
+
+        store
+            .delete_paste_by_url("hello".to_string(), "password".to_string(), None)
+            .await
+            .unwrap();
+
+        assert!(store.pastes.read().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn count_views() {
+        let store = MemoryStore::new(ServerOptions::default());
+        create(&store, "hello", "content").await;
+
+        for _ in 0..3 {
+            store
+                .incr_views_by_url(
+                    "hello".to_string(),
+                    None,
+                    "viewer".to_string(),
+                    String::new(),
+                )
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(store.get_views_by_url("hello".to_string()).await, 3);
+
+        // deduplicated views only count each viewer once per window
+        let store = MemoryStore::new(ServerOptions {
+            view_mode: ViewMode::OpenDeduplicated { window_secs: 60 },
+            ..Default::default()
+        });
+
+        create(&store, "hello", "content").await;
+
+        for viewer in ["a", "a", "b"] {
+            store
+                .incr_views_by_url("hello".to_string(), None, viewer.to_string(), String::new())
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(store.get_views_by_url("hello".to_string()).await, 2);
+
+        // viewers are only kept until their window ends
+        assert_eq!(store.purge_seen_viewers(), 0);
+
+        for until in store.seen.write().unwrap().values_mut() {
+            *until = 0;
+        }
+
+        assert_eq!(store.purge_seen_viewers(), 2);
+        assert!(store.seen.read().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn burn_after_views() {
+        // repeat reads by the same viewer still count towards the limit
+        let store = MemoryStore::new(ServerOptions {
+            view_mode: ViewMode::OpenDeduplicated { window_secs: 3600 },
+            ..Default::default()
+        });
+
+        store
+            .create_paste(
+                PasteCreate {
+                    url: "hello".to_string(),
+                    content: "content".to_string(),
+                    password: String::new(),
+                    expires_at: 0,
+                    max_views: 2,
+                },
+                None,
+            )
+            .await
+            .unwrap();
+
+        for _ in 0..2 {
+            store
+                .incr_views_by_url(
+                    "hello".to_string(),
+                    None,
+                    "viewer".to_string(),
+                    String::new(),
+                )
+                .await
+                .unwrap();
+        }
+
+        assert!(matches!(
+            store.get_paste_by_url("hello".to_string()).await,
+            Err(PasteError::NotFound)
+        ));
+    }
+
+    #[test]
+    fn guppy_is_disabled() {
+        let store = MemoryStore::new(ServerOptions {
+            guppy: true,
+            view_mode: ViewMode::AuthenticatedOnce,
+            ..Default::default()
+        });
+
+        assert!(!store.options.guppy);
+        assert_eq!(store.options.view_mode, ViewMode::OpenMultiple);
+    }
+}
@@ -0,0 +1,997 @@
+//! Paste storage backends
+//!
+//! Every backend implements [`PasteStore`]. The API routes are generic over it, so the
+//! SQL-backed [`Database`](crate::database::Database) can be swapped for the
+//! [`MemoryStore`](memory::MemoryStore) in tests or ephemeral deployments.
+use crate::database::{RateLimit, RateLimitConfig, Result, ServerOptions};
+use crate::model::{
+    PasteCreate, PasteClone, Paste, PasteError, PasteMetadata, PasteRevision, PasteSearchResult,
+    PasteList, PasteSort, PasteStats, PasteSummary, PasteAttachment, PasteForks, TemplateSync,
+    PasteTagged, TagCount, Collection, CollectionCreate, PublicCollection, TemplateVariable,
+    VIEW_PASSWORD_UNCHANGED,
+};
+use crate::password::{self, Verification};
+
+use async_trait::async_trait;
+use dorsal::utility;
+use std::collections::HashMap;
+use dorsal::db::special::auth_db::{FullUser, UserMetadata};
+
+pub mod memory;
+
+#[async_trait]
+pub trait PasteStore: Clone + Send + Sync + 'static {
+    /// The options the store was created with
+    fn options(&self) -> &ServerOptions;
+
+    /// Get the user an (unhashed) guppy token belongs to
+    ///
+    /// # Arguments
+    /// * `token` - the value of the `__Secure-Token` cookie
+    async fn get_user_by_token(&self, token: String) -> Result<FullUser<UserMetadata>>;
+
+    // pastes
+
+    /// Get an existing paste by `url` (pastes in the trash are not found)
+    async fn get_paste_by_url(&self, url: String) -> Result<Paste>;
+
+    /// Create a new paste
+    ///
+    /// # Arguments
+    /// * `props` - the paste to create
+    /// * `editing_as` - the userstate of the user creating the paste (they become its owner)
+    ///
+    /// # Returns
+    /// * Result containing a tuple with the unhashed edit password and the paste
+    async fn create_paste(
+        &self,
+        props: PasteCreate,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<(String, Paste)>;
+
+    /// Use an existing paste as a template
+    ///
+    /// # Arguments
+    /// * `props` - the paste to create
+    /// * `editing_as` - the userstate of the user creating the paste (they become its owner)
+    ///
+    /// # Returns
+    /// * Result containing a tuple with the unhashed edit password and the paste
+    async fn clone_paste(
+        &self,
+        props: PasteClone,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<(String, Paste)>;
+
+    /// Move an existing paste by `url` to the trash
+    ///
+    /// # Arguments
+    /// * `url` - the paste to delete
+    /// * `password` - the paste's edit password (not needed by the owner or users with `ManagePastes`)
+    /// * `editing_as` - the userstate of the user deleting the paste
+    async fn delete_paste_by_url(
+        &self,
+        url: String,
+        password: String,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()>;
+
+    /// Restore an existing paste from the trash by `url`
+    ///
+    /// # Arguments
+    /// * `url` - the paste to restore
+    /// * `password` - the paste's edit password (not needed by the owner or users with `ManagePastes`)
+    /// * `editing_as` - the userstate of the user restoring the paste
+    async fn restore_paste_by_url(
+        &self,
+        url: String,
+        password: String,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()>;
+
+    /// Edit an existing paste by `url`
+    async fn edit_paste_by_url(
+        &self,
+        url: String,
+        password: String,
+        new_content: String,
+        new_url: String,
+        new_password: String,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()>;
+
+    /// Edit an existing paste's metadata by `url`
+    async fn edit_paste_metadata_by_url(
+        &self,
+        url: String,
+        password: String,
+        metadata: PasteMetadata,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()>;
+
+    /// Edit an existing paste's expiry settings by `url`
+    async fn edit_paste_expiry_by_url(
+        &self,
+        url: String,
+        password: String,
+        expires_at: u128,
+        max_views: i32,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()>;
+
+    // view passwords
+
+    /// Check an existing paste's view password by `url`
+    ///
+    /// # Returns
+    /// * Result containing a tuple with a signed unlock token and the paste
+    async fn unlock_paste_by_url(
+        &self,
+        url: String,
+        view_password: String,
+    ) -> Result<(String, Paste)> {
+        let paste = match self.get_paste_by_url(url).await {
+            Ok(p) => p,
+            Err(e) => return Err(e),
+        };
+
+        if paste.metadata.view_password.is_empty() {
+            return Err(PasteError::Other);
+        }
+
+        self.check_password_lockout(paste.url.clone()).await?;
+
+        let valid = password::verify_view(&view_password, &paste.metadata.view_password).await
+            != Verification::Invalid;
+
+        self.record_password_attempt(paste.url.clone(), valid).await;
+
+        if !valid {
+            return Err(PasteError::PasswordIncorrect);
+        }
+
+        // sign token
+        let options = self.options();
+        let token = password::sign_unlock(
+            &options.unlock_secret,
+            &paste.url,
+            &paste.metadata.view_password,
+            utility::unix_epoch_timestamp() + (options.unlock_ttl as u128 * 1000),
+        );
+
+        Ok((token, paste))
+    }
+
+    /// Check if an unlock token from [`PasteStore::unlock_paste_by_url`] is (still) valid for `paste`
+    fn paste_is_unlocked(&self, paste: &Paste, token: &str) -> bool {
+        password::verify_unlock(
+            &self.options().unlock_secret,
+            &paste.url,
+            &paste.metadata.view_password,
+            token,
+        )
+    }
+
+    // rate limits
+
+    /// Count a request from a client (`key`) against `limit`
+    ///
+    /// # Returns
+    /// * `Err(PasteError::RateLimited)` if the client has used up the current window
+    async fn hit_rate_limit(&self, key: String, limit: RateLimit) -> Result<()>;
+
+    /// Check if password attempts on the paste at (normalized) `url` are locked out
+    ///
+    /// # Returns
+    /// * `Err(PasteError::RateLimited)` while the paste is locked
+    async fn check_password_lockout(&self, url: String) -> Result<()>;
+
+    /// Record the result of a password attempt on the paste at (normalized) `url`
+    ///
+    /// Incorrect passwords past [`RateLimitConfig::lockout_threshold`] lock the paste, and a
+    /// correct password resets the count.
+    async fn record_password_attempt(&self, url: String, success: bool);
+
+    // ownership
+
+    /// Claim an existing paste by `url` which has no owner into the account of `editing_as`
+    ///
+    /// # Arguments
+    /// * `url` - the paste to claim
+    /// * `password` - the paste's edit password
+    /// * `editing_as` - the userstate of the user claiming the paste
+    ///
+    /// # Returns
+    /// * `Err(PasteError::ValueError)` if we aren't logged in (or pastes can't have owners),
+    ///   or the paste already has an owner
+    async fn claim_paste_by_url(
+        &self,
+        url: String,
+        password: String,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()>;
+
+    /// Offer an existing paste by `url` to `username`, who becomes its owner once they
+    /// accept it (a blank `username` cancels the pending transfer)
+    ///
+    /// # Arguments
+    /// * `url` - the paste to transfer
+    /// * `username` - the user to transfer the paste to
+    /// * `editing_as` - the userstate of the user transferring the paste (must be the owner or have `ManagePastes`)
+    async fn transfer_paste_by_url(
+        &self,
+        url: String,
+        username: String,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()>;
+
+    /// Accept the pending transfer of an existing paste by `url`
+    ///
+    /// # Arguments
+    /// * `url` - the paste to accept
+    /// * `editing_as` - the userstate of the user accepting the paste (must be [`PasteMetadata::pending_owner`])
+    async fn accept_transfer_by_url(
+        &self,
+        url: String,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()>;
+
+    // listings
+
+    /// Get a page of the pastes owned by `owner` (newest first)
+    async fn list_pastes_by_owner(
+        &self,
+        owner: String,
+        sort: PasteSort,
+        cursor: String,
+        limit: i64,
+        include_private: bool,
+    ) -> Result<PasteList>;
+
+    /// Get a page of the pastes derived from the template at `url` (newest first)
+    ///
+    /// The count includes private forks, but only public ones are listed.
+    async fn get_forks_by_url(&self, url: String, limit: i64, offset: i64) -> Result<PasteForks>;
+
+    /// Search all public pastes, best matches first
+    async fn search_pastes(
+        &self,
+        query: String,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<PasteSearchResult>>;
+
+    // revisions
+
+    /// Get all revisions of an existing paste by `url` (newest first)
+    async fn get_revisions_by_url(&self, url: String) -> Result<Vec<PasteRevision>>;
+
+    /// Get a single revision of an existing paste by `url` and revision `id`
+    async fn get_revision_by_id(&self, url: String, id: String) -> Result<PasteRevision>;
+
+    /// Restore an existing paste's content from one of its revisions
+    async fn restore_revision_by_id(
+        &self,
+        url: String,
+        id: String,
+        password: String,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()>;
+
+    // attachments
+
+    /// Attach a file to an existing paste, replacing any attachment with the same name
+    ///
+    /// The attachment is checked against [`AttachmentConfig`](crate::database::AttachmentConfig).
+    async fn upload_attachment(
+        &self,
+        url: String,
+        password: String,
+        name: String,
+        content_type: String,
+        data: Vec<u8>,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<PasteAttachment>;
+
+    /// Get all attachments of an existing paste by `url` (oldest first)
+    async fn get_attachments_by_url(&self, url: String) -> Result<Vec<PasteAttachment>>;
+
+    /// Get a single attachment of an existing paste by `url` and file `name`
+    ///
+    /// # Returns
+    /// * Result containing a tuple with the attachment and its contents
+    async fn get_attachment_by_name(
+        &self,
+        url: String,
+        name: String,
+    ) -> Result<(PasteAttachment, Vec<u8>)>;
+
+    /// Delete a single attachment of an existing paste by `url` and file `name`
+    async fn delete_attachment_by_name(
+        &self,
+        url: String,
+        name: String,
+        password: String,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()>;
+
+    // templates
+
+    /// Get the template `paste` is derived from, along with the content of the template
+    /// `paste` was derived from (or last synced with)
+    ///
+    /// # Returns
+    /// * `Err(PasteError::ValueError)` if `paste` isn't derived from an existing template,
+    ///   or was derived from it before [`PasteMetadata::template_revision`] was recorded
+    async fn get_template_base(&self, paste: &Paste) -> Result<(Paste, String)> {
+        let source = paste.metadata.template_source();
+
+        if source.is_empty() | (paste.metadata.template_revision == 0) {
+            return Err(PasteError::ValueError);
+        }
+
+        let template = match self.get_paste_by_url(source.to_string()).await {
+            Ok(p) => p,
+            Err(e) => return Err(e),
+        };
+
+        // every content edit stores the content it replaced as a revision, so the content we
+        // were derived from is the oldest revision made after that (or the current content)
+        let revisions = match self.get_revisions_by_url(template.url.clone()).await {
+            Ok(r) => r,
+            Err(e) => return Err(e),
+        };
+
+        let base = match revisions
+            .iter()
+            .rev()
+            .find(|r| r.timestamp > paste.metadata.template_revision)
+        {
+            Some(r) => r.content.clone(),
+            None => template.content.clone(),
+        };
+
+        Ok((template, base))
+    }
+
+    /// Check if the template `paste` is derived from has changed since `paste` was derived
+    /// from it (or last synced with it)
+    async fn template_has_updates(&self, paste: &Paste) -> bool {
+        match self.get_template_base(paste).await {
+            Ok((template, base)) => template.content != base,
+            Err(_) => false,
+        }
+    }
+
+    /// Merge the changes made to the template an existing paste is derived from into the paste
+    ///
+    /// Nothing is saved if the changes conflict with changes made to the paste.
+    async fn sync_template_by_url(
+        &self,
+        url: String,
+        password: String,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<TemplateSync>;
+
+    // tags
+
+    /// Get a page of the public pastes carrying `tag` (newest first)
+    ///
+    /// # Arguments
+    /// * `tag` - the tag (normalized with [`normalize_tag`])
+    /// * `limit` - the maximum number of pastes
+    /// * `offset` - the number of pastes to skip
+    async fn get_pastes_by_tag(&self, tag: String, limit: i64, offset: i64) -> Result<PasteTagged>;
+
+    /// Get the tags carried by the most public pastes (most used first)
+    async fn get_tags(&self, limit: i64) -> Result<Vec<TagCount>>;
+
+    // collections
+
+    /// Get an existing collection by `name`
+    async fn get_collection_by_name(&self, name: String) -> Result<Collection>;
+
+    /// Get the collections an existing paste by `url` belongs to
+    async fn get_collections_by_url(&self, url: String) -> Result<Vec<Collection>>;
+
+    /// Create a new collection
+    ///
+    /// # Arguments
+    /// * `props` - the collection to create
+    /// * `editing_as` - the userstate of the user creating the collection (they become its owner)
+    ///
+    /// # Returns
+    /// * Result containing a tuple with the unhashed edit password and the collection
+    async fn create_collection(
+        &self,
+        props: CollectionCreate,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<(String, Collection)>;
+
+    /// Edit an existing collection by `name`
+    ///
+    /// # Arguments
+    /// * `name` - the collection to edit
+    /// * `password` - the collection's edit password
+    /// * `title` - the new title of the collection
+    /// * `description` - the new description of the collection
+    /// * `new_password` - the new password of the collection (kept if blank)
+    /// * `pastes` - the new urls of the pastes in the collection, in order
+    /// * `editing_as` - the userstate of the user we're editing the collection as
+    #[allow(clippy::too_many_arguments)]
+    async fn edit_collection_by_name(
+        &self,
+        name: String,
+        password: String,
+        title: String,
+        description: String,
+        new_password: String,
+        pastes: Vec<String>,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()>;
+
+    /// Delete an existing collection by `name` (its pastes are kept)
+    async fn delete_collection_by_name(
+        &self,
+        name: String,
+        password: String,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()>;
+
+    /// Add an existing paste by `url` to the end of an existing collection by `name`
+    async fn add_paste_to_collection(
+        &self,
+        name: String,
+        password: String,
+        url: String,
+        editing_as: Option<FullUser<UserMetadata>>,
+    ) -> Result<()> {
+        let collection = match self.get_collection_by_name(name).await {
+            Ok(c) => c,
+            Err(e) => return Err(e),
+        };
+
+        let paste = match self.get_paste_by_url(url).await {
+            Ok(p) => p,
+            Err(e) => return Err(e),
+        };
+
+        if collection.pastes.contains(&paste.url) {
+            return Err(PasteError::AlreadyExists);
+        }
+
+        let mut pastes = collection.pastes;
+        pastes.push(paste.url);
+
+        self.edit_collection_by_name(
+            collection.name,
+            password,
+            collection.title,
+            collection.description,
+            String::new(),
+            pastes,
+            editing_as,
+        )
+        .await
+    }
+
+    /// Get an existing collection by `name`, along with the pastes of it anyone can view
+    ///
+    /// Pastes which no longer exist (or are in the trash) and pastes with a view password
+    /// are left out.
+    async fn get_public_collection_by_name(&self, name: String) -> Result<PublicCollection> {
+        let collection = match self.get_collection_by_name(name).await {
+            Ok(c) => c,
+            Err(e) => return Err(e),
+        };
+
+        let mut pastes: Vec<PasteSummary> = Vec::new();
+
+        for url in &collection.pastes {
+            if let Ok(paste) = self.get_paste_by_url(url.clone()).await {
+                if paste.metadata.view_password.is_empty() {
+                    pastes.push(paste.into());
+                }
+            }
+        }
+
+        Ok(PublicCollection::new(collection, pastes))
+    }
+
+    /// Check the edit password of `collection`, unless `editing_as` is its owner or a paste manager
+    ///
+    /// Failed attempts count towards the same lockout as paste passwords.
+    async fn check_collection_access(
+        &self,
+        collection: &Collection,
+        password: String,
+        editing_as: &Option<FullUser<UserMetadata>>,
+    ) -> Result<()> {
+        if let Some(ua) = editing_as {
+            if (!collection.owner.is_empty() && (ua.user.username == collection.owner))
+                | ua.level.permissions.contains(&"ManagePastes".to_string())
+            {
+                return Ok(());
+            }
+        }
+
+        // paste urls can't contain `/`, so this never shares a lockout with a paste
+        let key = format!("collections/{}", collection.name);
+
+        self.check_password_lockout(key.clone()).await?;
+
+        let valid =
+            password::verify(&password, &collection.password).await != Verification::Invalid;
+        self.record_password_attempt(key, valid).await;
+
+        if !valid {
+            return Err(PasteError::PasswordIncorrect);
+        }
+
+        Ok(())
+    }
+
+    // views
+
+    /// Get an existing url's view count
+    async fn get_views_by_url(&self, url: String) -> i32;
+
+    /// Update an existing url's view count
+    ///
+    /// # Arguments
+    /// * `url` - the paste to count the view for
+    /// * `as_user` - the userstate of the user viewing this
+    /// * `viewer` - the key from [`PasteStore::viewer_key`] (blank if unknown)
+    /// * `referrer` - the `Referer` header of the request (blank if there isn't one)
+    ///
+    /// Every read of a paste with a view limit is counted (the view mode's deduplication
+    /// doesn't apply), and the paste is deleted once it reaches the limit.
+    async fn incr_views_by_url(
+        &self,
+        url: String,
+        as_user: Option<FullUser<UserMetadata>>,
+        viewer: String,
+        referrer: String,
+    ) -> Result<()>;
+
+    /// Get the view analytics of an existing paste by `url` for the last `days` days
+    async fn get_stats_by_url(&self, url: String, days: i64) -> Result<PasteStats>;
+
+    /// Identify an anonymous viewer by a salted hash of their `ip` and `user_agent`
+    fn viewer_key(&self, ip: &str, user_agent: &str) -> String {
+        utility::hash(format!("{}:{ip}:{user_agent}", self.options().view_salt))
+    }
+
+    /// Check if a user has viewed a paste given the `url` and their `username`
+    async fn user_has_viewed_paste(&self, url: String, username: String) -> bool;
+}
+
+/// Resolve the view password of `metadata` which is about to replace `existing`
+///
+/// [`VIEW_PASSWORD_UNCHANGED`] keeps the existing hash, a blank value removes the view
+/// password, and anything else is hashed.
+pub(crate) async fn hash_view_password(metadata: &mut PasteMetadata, existing: &PasteMetadata) {
+    if metadata.view_password == VIEW_PASSWORD_UNCHANGED {
+        metadata.view_password = existing.view_password.clone();
+    } else if !metadata.view_password.is_empty() {
+        metadata.view_password = password::hash(&metadata.view_password).await;
+    }
+}
+
+/// Get the username a paste created by `editing_as` is owned by (blank if we aren't logged
+/// in, or pastes can't have owners)
+pub(crate) fn owner_of_new_paste(
+    options: &ServerOptions,
+    editing_as: &Option<FullUser<UserMetadata>>,
+) -> String {
+    match editing_as {
+        Some(ua) if options.paste_ownership => ua.user.username.clone(),
+        _ => String::new(),
+    }
+}
+
+/// Keep the ownership of `existing` in `metadata` which is about to replace it
+///
+/// Ownership only changes by claiming or transferring a paste, so metadata edits (anonymous
+/// or not) can never set or clear [`PasteMetadata::owner`].
+pub(crate) fn keep_ownership(metadata: &mut PasteMetadata, existing: &PasteMetadata) {
+    metadata.owner = existing.owner.clone();
+    metadata.pending_owner = existing.pending_owner.clone();
+}
+
+/// Check if `editing_as` can see the view analytics of `paste` (the owner or a paste manager)
+pub fn can_view_stats(paste: &Paste, editing_as: &Option<FullUser<UserMetadata>>) -> bool {
+    can_transfer(paste, editing_as)
+}
+
+/// Check if `editing_as` can transfer `paste` (the owner or a paste manager)
+pub(crate) fn can_transfer(paste: &Paste, editing_as: &Option<FullUser<UserMetadata>>) -> bool {
+    match editing_as {
+        Some(ua) => {
+            (!paste.metadata.owner.is_empty() && (ua.user.username == paste.metadata.owner))
+                | ua.level.permissions.contains(&"ManagePastes".to_string())
+        }
+        None => false,
+    }
+}
+
+/// Keep the template state of `existing` in `metadata` which is about to replace it
+///
+/// Only the server changes [`PasteMetadata::orphaned`], [`PasteMetadata::template_revision`]
+/// and [`PasteMetadata::template_values`], so clients can't set them. Pointing the paste at a
+/// different template resets them.
+pub(crate) fn keep_template_fields(metadata: &mut PasteMetadata, existing: &PasteMetadata) {
+    if metadata.template == existing.template {
+        metadata.orphaned = existing.orphaned;
+        metadata.template_revision = existing.template_revision;
+        metadata.template_values = existing.template_values.clone();
+    } else {
+        metadata.orphaned = false;
+        metadata.template_revision = 0;
+        metadata.template_values = HashMap::new();
+    }
+}
+
+/// Three-way merge the changes between `base` and `theirs` (the template) into `ours`
+pub(crate) fn merge_template(base: &str, ours: &str, theirs: &str) -> TemplateSync {
+    match diffy::merge(base, ours, theirs) {
+        Ok(content) => TemplateSync {
+            conflicted: false,
+            content,
+        },
+        Err(content) => TemplateSync {
+            conflicted: true,
+            content,
+        },
+    }
+}
+
+/// Check the title, description and pastes of `collection` (its name is checked like a paste url)
+///
+/// Collections can hold up to 500 pastes, and a paste can only be in a collection once.
+pub(crate) fn check_collection(collection: &Collection) -> Result<()> {
+    if (collection.title.chars().count() > 128)
+        | (collection.description.chars().count() > 1024)
+        | (collection.pastes.len() > 500)
+    {
+        return Err(PasteError::ValueError);
+    }
+
+    for (i, url) in collection.pastes.iter().enumerate() {
+        if collection.pastes[..i].contains(url) {
+            return Err(PasteError::ValueError);
+        }
+    }
+
+    Ok(())
+}
+
+/// Normalize a single tag: trimmed, lowercase, without a leading `#`, and with whitespace
+/// replaced by `-`
+pub fn normalize_tag(tag: &str) -> String {
+    tag.trim()
+        .trim_start_matches('#')
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join("-")
+        .to_lowercase()
+}
+
+/// Normalize and check the [tags](PasteMetadata::tags) of `metadata`
+///
+/// Blank and repeated tags are dropped. A paste can carry up to 10 tags of up to 32
+/// letters, digits, `-` and `_`.
+pub(crate) fn normalize_tags(metadata: &mut PasteMetadata) -> Result<()> {
+    let mut tags: Vec<String> = Vec::new();
+
+    for tag in metadata.tags.iter().map(|t| normalize_tag(t)) {
+        if tag.is_empty() | tags.contains(&tag) {
+            continue;
+        }
+
+        if (tag.chars().count() > 32)
+            | !tag
+                .chars()
+                .all(|c| c.is_alphanumeric() | (c == '_') | (c == '-'))
+        {
+            return Err(PasteError::ValueError);
+        }
+
+        tags.push(tag);
+    }
+
+    if tags.len() > 10 {
+        return Err(PasteError::ValueError);
+    }
+
+    metadata.tags = tags;
+    Ok(())
+}
+
+/// Check the [template variables](PasteMetadata::variables) of `metadata`
+///
+/// Names can only contain letters, digits, `_` and `-`, and can't be repeated.
+pub(crate) fn check_variables(metadata: &PasteMetadata) -> Result<()> {
+    if metadata.variables.len() > 50 {
+        return Err(PasteError::ValueError);
+    }
+
+    for (i, variable) in metadata.variables.iter().enumerate() {
+        if variable.name.is_empty()
+            | (variable.name.len() > 64)
+            | !variable
+                .name
+                .chars()
+                .all(|c| c.is_alphanumeric() | (c == '_') | (c == '-'))
+        {
+            return Err(PasteError::ValueError);
+        }
+
+        if metadata.variables[..i]
+            .iter()
+            .any(|v| v.name == variable.name)
+        {
+            return Err(PasteError::ValueError);
+        }
+    }
+
+    Ok(())
+}
+
+/// Resolve the value of every variable in `variables` from `values`, falling back to the
+/// variable's default
+///
+/// # Returns
+/// * `Err(PasteError::ValueError)` if a required variable ends up blank
+pub(crate) fn resolve_variables(
+    variables: &[TemplateVariable],
+    values: &HashMap<String, String>,
+) -> Result<HashMap<String, String>> {
+    let mut resolved: HashMap<String, String> = HashMap::new();
+
+    for variable in variables {
+        let value = match values.get(&variable.name) {
+            Some(v) if !v.trim().is_empty() => v.clone(),
+            _ => variable.default.clone(),
+        };
+
+        if variable.required && value.trim().is_empty() {
+            return Err(PasteError::ValueError);
+        }
+
+        resolved.insert(variable.name.clone(), value);
+    }
+
+    Ok(resolved)
+}
+
+/// Replace every `{{ name }}` in `content` with the (resolved) value of `name`
+///
+/// Placeholders are replaced in a single pass, so values are never expanded again, and
+/// placeholders without a value are left as they are.
+pub(crate) fn fill_variables(content: &str, values: &HashMap<String, String>) -> String {
+    let placeholder = regex::Regex::new(r"\{\{\s*([\w-]+)\s*\}\}").unwrap();
+
+    placeholder
+        .replace_all(content, |caps: &regex::Captures| {
+            match values.get(&caps[1]) {
+                Some(value) => value.clone(),
+                None => caps[0].to_string(),
+            }
+        })
+        .to_string()
+}
+
+/// Fill the template `base` and `template` in with the values `paste` was derived with, and
+/// merge the changes between them into `paste`
+///
+/// Variables added to the template since `paste` was derived from it use their default.
+pub(crate) fn sync_template(paste: &Paste, template: &Paste, base: &str) -> TemplateSync {
+    let mut values = paste.metadata.template_values.clone();
+
+    for variable in &template.metadata.variables {
+        values
+            .entry(variable.name.clone())
+            .or_insert_with(|| variable.default.clone());
+    }
+
+    merge_template(
+        &fill_variables(base, &values),
+        &paste.content,
+        &fill_variables(&template.content, &values),
+    )
+}
+
+/// Count another request in the fixed `(window start, requests)` window of a client
+pub(crate) fn next_window(
+    window: Option<(u128, u32)>,
+    limit: &RateLimit,
+    now: u128,
+) -> (u128, u32) {
+    match window {
+        Some((start, requests)) if now < start + (limit.window_secs as u128 * 1000) => {
+            (start, requests.saturating_add(1))
+        }
+        _ => (now, 1),
+    }
+}
+
+/// Count another incorrect password in the `(locked until, failures)` state of a paste
+///
+/// Lockouts start once the threshold is reached and double in length with every failure
+/// after it, up to [`RateLimitConfig::lockout_max_secs`].
+pub(crate) fn next_lockout(
+    state: Option<(u128, u32)>,
+    config: &RateLimitConfig,
+    now: u128,
+) -> (u128, u32) {
+    let failures = state.map(|s| s.1).unwrap_or(0).saturating_add(1);
+
+    if (config.lockout_threshold == 0) | (failures < config.lockout_threshold) {
+        return (0, failures);
+    }
+
+    let secs = config
+        .lockout_secs
+        .saturating_mul(2u64.saturating_pow(failures - config.lockout_threshold))
+        .min(config.lockout_max_secs);
+
+    (now + (secs as u128 * 1000), failures)
+}
+
+/// Parse a `{a}:{b}` pair, the format windows and lockouts are cached in
+pub(crate) fn parse_pair(value: &str) -> Option<(u128, u32)> {
+    let (a, b) = value.split_once(":")?;
+    Some((a.parse().ok()?, b.parse().ok()?))
+}
+
+/// Reduce a `Referer` header to its host (blank if it doesn't contain one)
+pub fn referrer_host(referrer: &str) -> String {
+    let rest = match referrer.split_once("://") {
+        Some((_, rest)) => rest,
+        None => return String::new(),
+    };
+
+    // strip path and credentials
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or("");
+    let authority = authority.rsplit('@').next().unwrap_or("");
+
+    // strip port
+    let host = if authority.starts_with('[') {
+        // ipv6
+        match authority.find(']') {
+            Some(i) => &authority[..=i],
+            None => authority,
+        }
+    } else {
+        authority.split(':').next().unwrap_or("")
+    };
+
+    host.to_lowercase().chars().take(255).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fill_variables, resolve_variables, sync_template};
+    use crate::model::{Paste, PasteError, PasteMetadata, TemplateVariable};
+    use std::collections::HashMap;
+
+    /// A paste with the given content and variables
+    fn template(content: &str, variables: Vec<TemplateVariable>) -> Paste {
+        Paste {
+            id: String::new(),
+            url: "template".to_string(),
+            content: content.to_string(),
+            password: String::new(),
+            date_published: 0,
+            date_edited: 0,
+            metadata: PasteMetadata {
+                variables,
+                ..Default::default()
+            },
+            expires_at: 0,
+            max_views: 0,
+            trashed_at: 0,
+        }
+    }
+
+    fn variable(name: &str, default: &str, required: bool) -> TemplateVariable {
+        TemplateVariable {
+            name: name.to_string(),
+            default: default.to_string(),
+            required,
+        }
+    }
+
+    fn values(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    /// Resolve `values` against the variables of `source` and fill in its content
+    fn fill(source: &Paste, values: &HashMap<String, String>) -> Result<String, PasteError> {
+        resolve_variables(&source.metadata.variables, values)
+            .map(|resolved| fill_variables(&source.content, &resolved))
+    }
+
+    #[test]
+    fn fill_values_and_defaults() {
+        let source = template(
+            "# {{ title }}\n{{author}} wrote this on {{  date }}",
+            vec![
+                variable("title", "Untitled", false),
+                variable("author", "someone", false),
+                variable("date", "", false),
+            ],
+        );
+
+        // blank values fall back to the default
+        assert_eq!(
+            fill(&source, &values(&[("title", "Notes"), ("author", " ")])).unwrap(),
+            "# Notes\nsomeone wrote this on "
+        );
+    }
+
+    #[test]
+    fn required_variables() {
+        let source = template("{{ name }}", vec![variable("name", "", true)]);
+
+        assert!(matches!(
+            fill(&source, &values(&[])),
+            Err(PasteError::ValueError)
+        ));
+
+        assert!(matches!(
+            fill(&source, &values(&[("name", "  ")])),
+            Err(PasteError::ValueError)
+        ));
+
+        assert_eq!(
+            fill(&source, &values(&[("name", "value")])).unwrap(),
+            "value"
+        );
+
+        // a default satisfies a required variable
+        let source = template("{{ name }}", vec![variable("name", "default", true)]);
+        assert_eq!(fill(&source, &values(&[])).unwrap(), "default");
+    }
+
+    #[test]
+    fn values_are_not_expanded_again() {
+        let source = template(
+            "{{ a }} {{ b }} {{ undeclared }}",
+            vec![variable("a", "", false), variable("b", "", false)],
+        );
+
+        assert_eq!(
+            fill(&source, &values(&[("a", "{{ b }}"), ("b", "$1 {{ a }}")])).unwrap(),
+            "{{ b }} $1 {{ a }} {{ undeclared }}"
+        );
+    }
+
+    #[test]
+    fn sync_fills_template_changes() {
+        let base = "# {{ title }}\n\nintro\n\nfooter\n";
+        let mut source = template(
+            "# {{ title }}\n\nintro\n\nfooter by {{ author }}\n",
+            vec![
+                variable("title", "", true),
+                variable("author", "someone", false),
+            ],
+        );
+
+        let mut paste = template("# Notes\n\nmy intro\n\nfooter\n", Vec::new());
+        paste.metadata.template_values = values(&[("title", "Notes")]);
+
+        // the new variable uses its default, and the filled title doesn't conflict
+        let sync = sync_template(&paste, &source, base);
+        assert!(!sync.conflicted);
+        assert_eq!(sync.content, "# Notes\n\nmy intro\n\nfooter by someone\n");
+
+        // changes to lines the paste also changed conflict
+        source.content = "# {{ title }}\n\nnew intro\n\nfooter\n".to_string();
+        assert!(sync_template(&paste, &source, base).conflicted);
+    }
+}